@@ -0,0 +1,7 @@
+fn main() {
+    // Link the system hunspell library for the hunspell backend
+    #[cfg(feature = "hunspell")]
+    if let Err(err) = pkg_config::probe_library("hunspell") {
+        panic!("unable to find the hunspell library: {}", err);
+    }
+}
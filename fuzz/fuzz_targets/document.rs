@@ -0,0 +1,27 @@
+//! Apply arbitrary incremental changes to arbitrary text, then lex it.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+use streaming_iterator::StreamingIterator;
+use zspell_lsp::document::Document;
+use zspell_lsp::lexer::Lexer;
+
+fuzz_target!(|input: (String, Vec<(u32, u32, u32, u32, String)>)| {
+    let (text, changes) = input;
+    let mut document = Document::new(text, 0);
+    for (start_line, start, end_line, end, text) in changes {
+        document.apply_change(TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start_line, start),
+                Position::new(end_line, end),
+            )),
+            range_length: None,
+            text,
+        });
+    }
+    if let Some(mut lexer) = Lexer::new(&document.text) {
+        while lexer.next().is_some() {}
+    }
+});
@@ -0,0 +1,30 @@
+//! Lex arbitrary text, checking that the range of every word matches its
+//! position in the text.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use streaming_iterator::StreamingIterator;
+use zspell_lsp::document::Document;
+use zspell_lsp::lexer::Lexer;
+
+fuzz_target!(|text: &str| {
+    let Some(mut lexer) = Lexer::new(text) else {
+        return;
+    };
+    let document = Document::new(text.to_string(), 0);
+    let mut previous = None;
+    while let Some(word) = lexer.next() {
+        let offset = word.text.as_ptr() as usize - text.as_ptr() as usize;
+        assert!(!word.text.is_empty());
+        assert_eq!(word.range.start.line, word.range.end.line);
+        assert!(word.range.start < word.range.end);
+        assert!(previous.is_none_or(|previous| previous < offset));
+        assert_eq!(document.offset_at(word.range.start), offset);
+        assert_eq!(
+            document.offset_at(word.range.end),
+            offset + word.text.len()
+        );
+        previous = Some(offset);
+    }
+});
@@ -0,0 +1,11 @@
+//! Read messages from arbitrary client input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zspell_lsp::transport::read_message;
+
+fuzz_target!(|input: &[u8]| {
+    let mut input = input;
+    while let Ok(Some(_)) = read_message(&mut input) {}
+});
@@ -0,0 +1,380 @@
+//! The prefix and suffix rules of hunspell affix files, to inflect the
+//! words added to personal dictionaries with flags.
+
+use serde::{Deserialize, Serialize};
+
+/// Whether an affix rule adds a prefix or a suffix.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AffixKind {
+    Prefix,
+    Suffix,
+}
+
+/// How the flags of words are written, set by the `FLAG` directive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum FlagType {
+    /// One character per flag.
+    #[default]
+    Short,
+    /// Two characters per flag.
+    Long,
+    /// Decimal numbers separated by commas.
+    Numeric,
+}
+
+/// One character of the condition of an affix entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    Any,
+    Char(char),
+    /// Any of the characters, or any but them if negated.
+    Set {
+        negated: bool,
+        chars: Vec<char>,
+    },
+}
+
+impl Condition {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Condition::Any => true,
+            Condition::Char(expected) => c == *expected,
+            Condition::Set { negated, chars } => chars.contains(&c) != *negated,
+        }
+    }
+}
+
+/// A way of inflecting words of a rule: strip some characters and add
+/// others, if the word matches the condition.
+#[derive(Debug, Clone)]
+struct Entry {
+    strip: String,
+    add: String,
+    condition: Vec<Condition>,
+}
+
+/// The entries of one flag.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub flag: String,
+    pub kind: AffixKind,
+    /// Whether the rule combines with rules of the other kind.
+    pub cross: bool,
+    /// The comment right above the rule in the affix file, if any.
+    pub comment: Option<String>,
+    entries: Vec<Entry>,
+}
+
+impl Rule {
+    /// The forms of `word` this rule makes.
+    pub fn apply(&self, word: &str) -> Vec<String> {
+        let chars = word.chars().collect::<Vec<_>>();
+        let mut forms = Vec::new();
+        for entry in &self.entries {
+            let length = entry.condition.len();
+            if length > chars.len() {
+                continue;
+            }
+            let matched = match self.kind {
+                AffixKind::Prefix => &chars[..length],
+                AffixKind::Suffix => &chars[chars.len() - length..],
+            };
+            if !matched
+                .iter()
+                .zip(&entry.condition)
+                .all(|(c, condition)| condition.matches(*c))
+            {
+                continue;
+            }
+            let form = match self.kind {
+                AffixKind::Prefix => word
+                    .strip_prefix(entry.strip.as_str())
+                    .map(|stem| format!("{}{}", entry.add, stem)),
+                AffixKind::Suffix => word
+                    .strip_suffix(entry.strip.as_str())
+                    .map(|stem| format!("{}{}", stem, entry.add)),
+            };
+            if let Some(form) = form.filter(|form| !form.is_empty()) {
+                if !forms.contains(&form) {
+                    forms.push(form);
+                }
+            }
+        }
+        forms
+    }
+
+    /// The comment of the rule, or the affixes it adds.
+    pub fn description(&self) -> String {
+        if let Some(comment) = &self.comment {
+            return comment.clone();
+        }
+        let kind = match self.kind {
+            AffixKind::Prefix => "Prefix",
+            AffixKind::Suffix => "Suffix",
+        };
+        format!("{} {}", kind, self.affixes().join(", "))
+    }
+
+    /// The affixes the rule adds, such as `un-` or `-ies`.
+    pub fn affixes(&self) -> Vec<String> {
+        let mut added = Vec::new();
+        for entry in &self.entries {
+            let affix = match self.kind {
+                AffixKind::Prefix => format!("{}-", entry.add),
+                AffixKind::Suffix => format!("-{}", entry.add),
+            };
+            if !added.contains(&affix) {
+                added.push(affix);
+            }
+        }
+        added
+    }
+}
+
+/// The prefix and suffix rules of an affix file.
+#[derive(Debug, Clone, Default)]
+pub struct Affixes {
+    flag_type: FlagType,
+    rules: Vec<Rule>,
+}
+
+impl Affixes {
+    /// Read the rules of the affix file `text`. Lines that aren't
+    /// understood are skipped, as the dictionary itself is checked by the
+    /// backend.
+    pub fn parse(text: &str) -> Self {
+        let mut affixes = Self::default();
+        let mut comment = None;
+        let mut remaining = 0;
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(text) = line.strip_prefix('#') {
+                let text = text.trim();
+                comment = (!text.is_empty()).then(|| text.to_string());
+                continue;
+            }
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => affixes.flag_type = FlagType::Long,
+                ["FLAG", "num", ..] => affixes.flag_type = FlagType::Numeric,
+                [kind @ ("PFX" | "SFX"), flag, cross, count]
+                    if remaining == 0 =>
+                {
+                    remaining = count.parse().unwrap_or(0);
+                    affixes.rules.push(Rule {
+                        flag: flag.to_string(),
+                        kind: match *kind {
+                            "PFX" => AffixKind::Prefix,
+                            _ => AffixKind::Suffix,
+                        },
+                        cross: *cross == "Y",
+                        comment: comment.take(),
+                        entries: Vec::new(),
+                    });
+                }
+                [_, flag, strip, add, rest @ ..] if remaining > 0 => {
+                    remaining -= 1;
+                    let rule = affixes.rules.last_mut().unwrap();
+                    if rule.flag != *flag {
+                        continue;
+                    }
+                    let condition = rest.first().copied().unwrap_or(".");
+                    let add = add.split('/').next().unwrap_or_default();
+                    let empty = |text: &str| match text {
+                        "0" => String::new(),
+                        text => text.to_string(),
+                    };
+                    rule.entries.push(Entry {
+                        strip: empty(strip),
+                        add: empty(add),
+                        condition: parse_condition(condition),
+                    });
+                }
+                _ => {}
+            }
+            comment = None;
+        }
+        affixes
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The rule of `flag`, if there is one.
+    pub fn rule(&self, flag: &str) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.flag == flag)
+    }
+
+    /// The flags of `flags`, written as in dictionary files.
+    pub fn split_flags(&self, flags: &str) -> Vec<String> {
+        let chars = flags.chars().collect::<Vec<_>>();
+        match self.flag_type {
+            FlagType::Short => chars.iter().map(char::to_string).collect(),
+            FlagType::Long => chars
+                .chunks(2)
+                .map(|flag| flag.iter().collect::<String>())
+                .collect(),
+            FlagType::Numeric => flags
+                .split(',')
+                .map(str::trim)
+                .filter(|flag| !flag.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// The flags of `lists` that have affix rules, without duplicates,
+    /// written as in dictionary files.
+    pub fn merge_flags(&self, lists: &[&str]) -> String {
+        let mut merged = Vec::new();
+        for flag in lists.iter().flat_map(|flags| self.split_flags(flags)) {
+            if self.rule(&flag).is_some() && !merged.contains(&flag) {
+                merged.push(flag);
+            }
+        }
+        match self.flag_type {
+            FlagType::Numeric => merged.join(","),
+            FlagType::Short | FlagType::Long => merged.concat(),
+        }
+    }
+
+    /// The forms of `word` with `flags`, including `word` itself. Prefixes
+    /// and suffixes are combined when both rules allow it. Unknown flags
+    /// are ignored.
+    pub fn forms(&self, word: &str, flags: &str) -> Vec<String> {
+        let flags = self.split_flags(flags);
+        let rules = flags
+            .iter()
+            .filter_map(|flag| self.rule(flag))
+            .collect::<Vec<_>>();
+
+        let mut forms = vec![word.to_string()];
+        let mut add = |form: String| {
+            if !forms.contains(&form) {
+                forms.push(form);
+            }
+        };
+        for rule in &rules {
+            rule.apply(word).into_iter().for_each(&mut add);
+        }
+        for suffix in rules
+            .iter()
+            .filter(|rule| rule.kind == AffixKind::Suffix && rule.cross)
+        {
+            for prefix in rules
+                .iter()
+                .filter(|rule| rule.kind == AffixKind::Prefix && rule.cross)
+            {
+                for form in suffix.apply(word) {
+                    prefix.apply(&form).into_iter().for_each(&mut add);
+                }
+            }
+        }
+        forms
+    }
+}
+
+/// The characters of a condition such as `[^aeiou]y`.
+fn parse_condition(condition: &str) -> Vec<Condition> {
+    let mut conditions = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => conditions.push(Condition::Any),
+            '[' => {
+                let mut set = chars
+                    .by_ref()
+                    .take_while(|c| *c != ']')
+                    .collect::<Vec<_>>();
+                let negated = set.first() == Some(&'^');
+                if negated {
+                    set.remove(0);
+                }
+                conditions.push(Condition::Set {
+                    negated,
+                    chars: set,
+                });
+            }
+            c => conditions.push(Condition::Char(c)),
+        }
+    }
+    conditions
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const AFFIX: &str = "SET UTF-8
+# Plural
+SFX S Y 3
+SFX S   y     ies        [^aeiou]y
+SFX S   0     s          [aeiou]y
+SFX S   0     s          [^y]
+
+SFX D N 2
+SFX D   0     d          e
+SFX D   0     ed         [^e]
+
+PFX U Y 1
+PFX U   0     un         .
+";
+
+    #[test]
+    fn forms() {
+        let affixes = Affixes::parse(AFFIX);
+
+        assert_eq!(affixes.forms("city", "S"), ["city", "cities"]);
+        assert_eq!(affixes.forms("day", "S"), ["day", "days"]);
+        assert_eq!(affixes.forms("bake", "D"), ["bake", "baked"]);
+        assert_eq!(
+            affixes.forms("tie", "SU"),
+            ["tie", "ties", "untie", "unties"]
+        );
+        // D doesn't combine with prefixes
+        assert_eq!(affixes.forms("lock", "DU"), ["lock", "locked", "unlock"]);
+        assert_eq!(affixes.forms("word", "X"), ["word"]);
+    }
+
+    #[test]
+    fn merge_flags() {
+        let affixes = Affixes::parse(AFFIX);
+        assert_eq!(affixes.merge_flags(&["SD", "DUX"]), "SDU");
+        assert_eq!(affixes.merge_flags(&["", ""]), "");
+
+        let numeric = Affixes::parse("FLAG num\nSFX 1 Y 1\nSFX 1 0 s .\n");
+        assert_eq!(numeric.merge_flags(&["1,2", "1"]), "1");
+    }
+
+    #[test]
+    fn descriptions() {
+        let affixes = Affixes::parse(AFFIX);
+        let descriptions = affixes
+            .rules()
+            .iter()
+            .map(|rule| (rule.flag.as_str(), rule.description()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            descriptions,
+            [
+                ("S", "Plural".to_string()),
+                ("D", "Suffix -d, -ed".to_string()),
+                ("U", "Prefix un-".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn flag_types() {
+        let long = Affixes::parse("FLAG long\n");
+        let numeric = Affixes::parse("FLAG num\n");
+
+        assert_eq!(long.split_flags("AaBb"), ["Aa", "Bb"]);
+        assert_eq!(numeric.split_flags("1,23"), ["1", "23"]);
+        assert_eq!(Affixes::default().split_flags("AB"), ["A", "B"]);
+    }
+}
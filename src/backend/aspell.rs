@@ -0,0 +1,206 @@
+use super::SpellBackend;
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Time to wait after a failed restart before trying again.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// A running `aspell -a` process.
+struct Pipe {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Pipe {
+    fn spawn(command: &Path, language: &str) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(["-a", "--encoding=utf-8"])
+            .arg(format!("--lang={}", language))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Unable to run {}", command.display()))?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        // The version banner, only printed once the dictionary is loaded
+        let mut banner = String::new();
+        stdout.read_line(&mut banner)?;
+        if !banner.starts_with("@(#)") {
+            _ = child.kill();
+            return Err(anyhow!(
+                "Unable to start aspell for {}: {}",
+                language,
+                banner.trim()
+            ));
+        }
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Send one line of input and read the result lines for its words,
+    /// which are terminated by an empty line.
+    fn query(&mut self, line: &str) -> Result<Vec<String>> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()?;
+
+        let mut results = Vec::new();
+        loop {
+            let mut result = String::new();
+            if self.stdout.read_line(&mut result)? == 0 {
+                return Err(anyhow!("aspell exited"));
+            }
+            let result = result.trim_end();
+            if result.is_empty() {
+                return Ok(results);
+            }
+            results.push(result.to_string());
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+/// Backend driving `aspell -a` through the ispell pipe protocol. The
+/// process is restarted if it dies.
+pub struct AspellBackend {
+    command: PathBuf,
+    language: String,
+    pipe: RefCell<Option<Pipe>>,
+    /// When restarting aspell last failed.
+    failed_at: Cell<Option<Instant>>,
+    /// Words added this session, accepted again after a restart.
+    added: HashSet<String>,
+}
+
+impl AspellBackend {
+    pub fn new(command: Option<&Path>, language: &str) -> Result<Self> {
+        let command = command.unwrap_or(Path::new("aspell")).to_path_buf();
+        let pipe = Pipe::spawn(&command, language)?;
+        Ok(Self {
+            command,
+            language: language.to_string(),
+            pipe: RefCell::new(Some(pipe)),
+            failed_at: Cell::new(None),
+            added: HashSet::new(),
+        })
+    }
+
+    /// The result for `word`, restarting aspell once if it died.
+    fn lookup(&self, word: &str) -> Option<String> {
+        // Lines starting with `^` are checked rather than interpreted as
+        // commands
+        let line = format!("^{}", word);
+        for attempt in 0..2 {
+            let mut pipe = self.pipe.borrow_mut();
+            if pipe.is_none() {
+                if self
+                    .failed_at
+                    .get()
+                    .is_some_and(|time| time.elapsed() < RESTART_DELAY)
+                {
+                    return None;
+                }
+                info!("restarting aspell for {}", self.language);
+                match self.restart() {
+                    Ok(restarted) => *pipe = Some(restarted),
+                    Err(err) => {
+                        warn!("{:#}", err);
+                        self.failed_at.set(Some(Instant::now()));
+                        return None;
+                    }
+                }
+            }
+            match pipe.as_mut()?.query(&line) {
+                Ok(results) => return results.into_iter().next(),
+                Err(err) => {
+                    if attempt > 0 {
+                        warn!("aspell failed: {:#}", err);
+                    }
+                    *pipe = None;
+                }
+            }
+        }
+        None
+    }
+
+    fn restart(&self) -> Result<Pipe> {
+        let mut pipe = Pipe::spawn(&self.command, &self.language)?;
+        for word in &self.added {
+            pipe.stdin.write_all(format!("@{}\n", word).as_bytes())?;
+        }
+        Ok(pipe)
+    }
+}
+
+impl SpellBackend for AspellBackend {
+    fn check(&self, word: &str) -> bool {
+        // Words aspell couldn't check aren't reported
+        self.lookup(word)
+            .is_none_or(|result| !is_misspelling(&result))
+    }
+
+    fn suggest(&self, word: &str) -> Vec<String> {
+        self.lookup(word)
+            .map(|result| suggestions(&result))
+            .unwrap_or_default()
+    }
+
+    fn add_word(&mut self, word: &str) {
+        self.added.insert(word.to_string());
+        if let Some(pipe) = self.pipe.get_mut() {
+            _ = writeln!(pipe.stdin, "@{}", word);
+        }
+    }
+}
+
+/// Whether a result line reports a misspelling, `& word count offset:
+/// suggestions` or `# word offset` if there are none.
+fn is_misspelling(result: &str) -> bool {
+    result.starts_with('&') || result.starts_with('#')
+}
+
+fn suggestions(result: &str) -> Vec<String> {
+    if !result.starts_with('&') {
+        return Vec::new();
+    }
+    match result.split_once(": ") {
+        Some((_, list)) => list.split(", ").map(String::from).collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn results() {
+        assert!(!is_misspelling("*"));
+        assert!(!is_misspelling("+ walk"));
+        assert!(is_misspelling("# wrld 0"));
+
+        let result = "& wrld 3 0: world, weld, wild";
+        assert!(is_misspelling(result));
+        assert_eq!(suggestions(result), ["world", "weld", "wild"]);
+        assert!(suggestions("# wrld 0").is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use super::{Issue, SpellBackend};
+use crate::cache::LruCache;
+use crate::metrics::metrics;
+use std::cell::RefCell;
+
+/// A backend remembering the results of [`SpellBackend::check`] and
+/// [`SpellBackend::suggest`] of another, within a memory budget.
+pub struct CachedBackend {
+    backend: Box<dyn SpellBackend>,
+    checks: RefCell<LruCache<bool>>,
+    suggestions: RefCell<LruCache<Vec<String>>>,
+}
+
+impl CachedBackend {
+    /// Cache the results of `backend` in up to `capacity` bytes.
+    pub fn new(backend: Box<dyn SpellBackend>, capacity: usize) -> Self {
+        let (checks, suggestions) = split(capacity);
+        Self {
+            backend,
+            checks: RefCell::new(LruCache::new(checks)),
+            suggestions: RefCell::new(LruCache::new(suggestions)),
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        let (checks, suggestions) = split(capacity);
+        self.checks.get_mut().set_capacity(checks);
+        self.suggestions.get_mut().set_capacity(suggestions);
+    }
+}
+
+/// Capacities of the check and suggestion caches. Suggestions take more
+/// space, but words are checked far more often, so each gets half.
+fn split(capacity: usize) -> (usize, usize) {
+    (capacity / 2, capacity - capacity / 2)
+}
+
+impl SpellBackend for CachedBackend {
+    fn check(&self, word: &str) -> bool {
+        if !self.backend.cacheable() {
+            return self.backend.check(word);
+        }
+        let cached = self.checks.borrow_mut().get(word).copied();
+        metrics().cache_lookup("checks", cached.is_some());
+        cached.unwrap_or_else(|| {
+            let correct = self.backend.check(word);
+            self.checks
+                .borrow_mut()
+                .insert(word.to_string(), correct, 0);
+            correct
+        })
+    }
+
+    fn suggest(&self, word: &str) -> Vec<String> {
+        if !self.backend.cacheable() {
+            return self.backend.suggest(word);
+        }
+        let cached = self.suggestions.borrow_mut().get(word).cloned();
+        metrics().cache_lookup("suggestions", cached.is_some());
+        cached.unwrap_or_else(|| {
+            let suggestions = self.backend.suggest(word);
+            let size = suggestions
+                .iter()
+                .map(|suggestion| suggestion.len() + size_of::<String>())
+                .sum();
+            self.suggestions.borrow_mut().insert(
+                word.to_string(),
+                suggestions.clone(),
+                size,
+            );
+            suggestions
+        })
+    }
+
+    fn add_word(&mut self, word: &str) {
+        self.backend.add_word(word);
+        // The word may now be accepted, or suggested for others
+        self.checks.get_mut().clear();
+        self.suggestions.get_mut().clear();
+    }
+
+    /// Only asked for misspelled words, so not cached.
+    fn lists(&self, word: &str) -> bool {
+        self.backend.lists(word)
+    }
+
+    fn forbidden(&self, word: &str) -> bool {
+        self.backend.forbidden(word)
+    }
+
+    fn checks_text(&self) -> bool {
+        self.backend.checks_text()
+    }
+
+    fn check_text(&self, text: &str) -> Vec<Issue> {
+        self.backend.check_text(text)
+    }
+
+    /// Only asked on hover, so not cached.
+    fn stems(&self, word: &str) -> Vec<String> {
+        self.backend.stems(word)
+    }
+
+    fn analyze(&self, word: &str) -> Vec<String> {
+        self.backend.analyze(word)
+    }
+
+    /// Already cached.
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn language(&self) -> Option<&str> {
+        self.backend.language()
+    }
+
+    /// A fork of the backend, without the caches.
+    fn fork(&self) -> Option<Box<dyn SpellBackend>> {
+        self.backend.fork()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Backend counting its lookups, accepting words added to it.
+    struct CountingBackend {
+        lookups: Arc<AtomicUsize>,
+        added: Vec<String>,
+    }
+
+    impl SpellBackend for CountingBackend {
+        fn check(&self, word: &str) -> bool {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.added.iter().any(|added| added == word)
+        }
+
+        fn suggest(&self, _word: &str) -> Vec<String> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.added.clone()
+        }
+
+        fn add_word(&mut self, word: &str) {
+            self.added.push(word.to_string());
+        }
+    }
+
+    #[test]
+    fn caching() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let backend = CountingBackend {
+            lookups: lookups.clone(),
+            added: Vec::new(),
+        };
+        let mut cached = CachedBackend::new(Box::new(backend), 1 << 20);
+
+        assert!(!cached.check("wrld"));
+        assert!(!cached.check("wrld"));
+        assert!(cached.suggest("wrld").is_empty());
+        assert!(cached.suggest("wrld").is_empty());
+        assert_eq!(lookups.load(Ordering::Relaxed), 2);
+
+        cached.add_word("wrld");
+        assert!(cached.check("wrld"));
+        assert_eq!(cached.suggest("wrld"), ["wrld"]);
+        assert_eq!(lookups.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,176 @@
+use super::SpellBackend;
+use anyhow::{anyhow, Context, Result};
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::path::Path;
+
+#[repr(C)]
+struct Hunhandle {
+    _private: [u8; 0],
+}
+
+// The C interface of libhunspell, see hunspell/hunspell.h
+extern "C" {
+    fn Hunspell_create(
+        affpath: *const c_char,
+        dpath: *const c_char,
+    ) -> *mut Hunhandle;
+    fn Hunspell_destroy(handle: *mut Hunhandle);
+    fn Hunspell_get_dic_encoding(handle: *mut Hunhandle) -> *mut c_char;
+    fn Hunspell_spell(handle: *mut Hunhandle, word: *const c_char) -> c_int;
+    fn Hunspell_suggest(
+        handle: *mut Hunhandle,
+        slst: *mut *mut *mut c_char,
+        word: *const c_char,
+    ) -> c_int;
+    fn Hunspell_free_list(
+        handle: *mut Hunhandle,
+        slst: *mut *mut *mut c_char,
+        n: c_int,
+    );
+    fn Hunspell_add(handle: *mut Hunhandle, word: *const c_char) -> c_int;
+}
+
+/// Encodings of dictionaries the backend can convert words to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    Latin1,
+}
+
+impl Encoding {
+    fn encode(self, word: &str) -> Option<CString> {
+        let bytes = match self {
+            Self::Utf8 => word.as_bytes().to_vec(),
+            Self::Latin1 => word
+                .chars()
+                .map(|char| u8::try_from(char).ok())
+                .collect::<Option<_>>()?,
+        };
+        CString::new(bytes).ok()
+    }
+
+    fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Latin1 => {
+                bytes.iter().map(|&byte| char::from(byte)).collect()
+            }
+        }
+    }
+}
+
+/// Backend using the system hunspell library, for the same results as
+/// other tools built on it.
+pub struct HunspellBackend {
+    handle: *mut Hunhandle,
+    encoding: Encoding,
+}
+
+impl HunspellBackend {
+    pub fn new(affix: &Path, dictionary: &Path) -> Result<Self> {
+        for file in [affix, dictionary] {
+            if !file.is_file() {
+                return Err(anyhow!("Unable to open {}", file.display()));
+            }
+        }
+        let path = |path: &Path| {
+            CString::new(path.as_os_str().as_encoded_bytes())
+                .with_context(|| format!("Invalid path {}", path.display()))
+        };
+        let (affix, dictionary) = (path(affix)?, path(dictionary)?);
+
+        // SAFETY: both paths are valid NUL terminated strings
+        let handle =
+            unsafe { Hunspell_create(affix.as_ptr(), dictionary.as_ptr()) };
+        if handle.is_null() {
+            return Err(anyhow!("Unable to create hunspell dictionary"));
+        }
+
+        // SAFETY: the encoding is owned by the handle and NUL terminated
+        let encoding =
+            unsafe { CStr::from_ptr(Hunspell_get_dic_encoding(handle)) }
+                .to_string_lossy()
+                .to_uppercase();
+        let encoding = match encoding.as_str() {
+            "UTF-8" => Encoding::Utf8,
+            "ISO8859-1" | "ISO-8859-1" => Encoding::Latin1,
+            _ => {
+                // SAFETY: the handle was created above and isn't used again
+                unsafe { Hunspell_destroy(handle) };
+                return Err(anyhow!(
+                    "Unsupported dictionary encoding {}",
+                    encoding
+                ));
+            }
+        };
+
+        Ok(Self { handle, encoding })
+    }
+}
+
+// SAFETY: a hunspell handle isn't tied to the thread that created it, and
+// `SpellBackend` isn't `Sync`, so it's only used by one thread at a time
+unsafe impl Send for HunspellBackend {}
+
+impl SpellBackend for HunspellBackend {
+    fn check(&self, word: &str) -> bool {
+        let Some(word) = self.encoding.encode(word) else {
+            return false;
+        };
+        // SAFETY: the handle is valid until dropped
+        unsafe { Hunspell_spell(self.handle, word.as_ptr()) != 0 }
+    }
+
+    fn suggest(&self, word: &str) -> Vec<String> {
+        let Some(word) = self.encoding.encode(word) else {
+            return Vec::new();
+        };
+
+        let mut list = std::ptr::null_mut();
+        // SAFETY: hunspell allocates `count` suggestions in `list`, which
+        // are released with `Hunspell_free_list`
+        unsafe {
+            let count = Hunspell_suggest(self.handle, &mut list, word.as_ptr());
+            let suggestions = (0..count.max(0) as usize)
+                .map(|index| {
+                    let suggestion = CStr::from_ptr(*list.add(index));
+                    self.encoding.decode(suggestion.to_bytes())
+                })
+                .collect();
+            Hunspell_free_list(self.handle, &mut list, count);
+            suggestions
+        }
+    }
+
+    fn add_word(&mut self, word: &str) {
+        if let Some(word) = self.encoding.encode(word) {
+            // SAFETY: the handle is valid until dropped
+            unsafe { Hunspell_add(self.handle, word.as_ptr()) };
+        }
+    }
+}
+
+impl Drop for HunspellBackend {
+    fn drop(&mut self) {
+        // SAFETY: the handle is valid and no longer used
+        unsafe { Hunspell_destroy(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        let latin1 = Encoding::Latin1.encode("Grüße").unwrap();
+        assert_eq!(latin1.as_bytes(), b"Gr\xfc\xdfe");
+        assert_eq!(Encoding::Latin1.decode(latin1.as_bytes()), "Grüße");
+        assert!(Encoding::Latin1.encode("Zoë ☃").is_none());
+        assert_eq!(
+            Encoding::Utf8.encode("Zoë").unwrap().as_bytes(),
+            "Zoë".as_bytes()
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use super::{Issue, SpellBackend};
+use crate::metrics::metrics;
+use anyhow::Result;
+use log::warn;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_URL: &str = "http://localhost:8081";
+/// Replacements kept per issue, LanguageTool may return dozens.
+const MAX_REPLACEMENTS: usize = 5;
+/// Paragraphs whose issues are remembered before the cache is cleared.
+const CACHE_SIZE: usize = 1024;
+
+/// Backend sending paragraphs to a LanguageTool server. Words are checked
+/// in context, so every misspelling and grammar issue is reported through
+/// [`SpellBackend::check_text`].
+pub struct LanguageToolBackend {
+    url: String,
+    /// Language code in the form LanguageTool expects, e.g. `en-US`.
+    language: String,
+    added: HashSet<String>,
+    /// Issues of recently checked paragraphs, relative to the paragraph.
+    cache: RefCell<HashMap<String, Vec<Issue>>>,
+    /// Replacements of the misspellings found so far.
+    suggestions: RefCell<HashMap<String, Vec<String>>>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    matches: Vec<Match>,
+}
+
+#[derive(Deserialize)]
+struct Match {
+    message: String,
+    offset: usize,
+    length: usize,
+    replacements: Vec<Replacement>,
+    rule: Rule,
+}
+
+#[derive(Deserialize)]
+struct Replacement {
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: String,
+    issue_type: String,
+}
+
+impl LanguageToolBackend {
+    pub fn new(url: Option<&str>, language: &str) -> Self {
+        Self {
+            url: url.unwrap_or(DEFAULT_URL).trim_end_matches('/').to_string(),
+            language: language.replace('_', "-"),
+            added: HashSet::new(),
+            cache: RefCell::new(HashMap::new()),
+            suggestions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn request(&self, paragraph: &str) -> Result<Vec<Issue>> {
+        let response: Response = ureq::post(format!("{}/v2/check", self.url))
+            .send_form([
+                ("language", self.language.as_str()),
+                ("text", paragraph),
+            ])?
+            .body_mut()
+            .read_json()?;
+
+        let issues = response.matches.into_iter().filter_map(|found| {
+            let start = byte_offset(paragraph, found.offset)?;
+            let end = byte_offset(paragraph, found.offset + found.length)?;
+            Some(Issue {
+                span: start..end,
+                rule: (found.rule.issue_type != "misspelling")
+                    .then_some(found.rule.id),
+                message: found.message,
+                replacements: found
+                    .replacements
+                    .into_iter()
+                    .take(MAX_REPLACEMENTS)
+                    .map(|replacement| replacement.value)
+                    .collect(),
+            })
+        });
+        Ok(issues.collect())
+    }
+
+    fn check_paragraph(&self, paragraph: &str) -> Vec<Issue> {
+        if let Some(issues) = self.cache.borrow().get(paragraph) {
+            metrics().cache_lookup("languageTool", true);
+            return issues.clone();
+        }
+        metrics().cache_lookup("languageTool", false);
+
+        let issues = match self.request(paragraph) {
+            Ok(issues) => issues,
+            Err(err) => {
+                warn!("LanguageTool request failed: {:#}", err);
+                return Vec::new();
+            }
+        };
+
+        let mut suggestions = self.suggestions.borrow_mut();
+        for issue in issues.iter().filter(|issue| issue.rule.is_none()) {
+            suggestions.insert(
+                paragraph[issue.span.clone()].to_string(),
+                issue.replacements.clone(),
+            );
+        }
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(paragraph.to_string(), issues.clone());
+        issues
+    }
+}
+
+impl SpellBackend for LanguageToolBackend {
+    fn check(&self, _word: &str) -> bool {
+        true
+    }
+
+    fn suggest(&self, word: &str) -> Vec<String> {
+        self.suggestions
+            .borrow()
+            .get(word)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn add_word(&mut self, word: &str) {
+        self.added.insert(word.to_string());
+    }
+
+    /// Suggestions are only known once the text containing the word was
+    /// checked.
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn checks_text(&self) -> bool {
+        true
+    }
+
+    fn check_text(&self, text: &str) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        for (offset, paragraph) in paragraphs(text) {
+            for mut issue in self.check_paragraph(paragraph) {
+                if issue.rule.is_none()
+                    && self.added.contains(&paragraph[issue.span.clone()])
+                {
+                    continue;
+                }
+                issue.span = issue.span.start + offset..issue.span.end + offset;
+                issues.push(issue);
+            }
+        }
+        issues
+    }
+}
+
+/// The paragraphs of `text`, separated by blank lines, and their byte
+/// offsets.
+fn paragraphs(text: &str) -> Vec<(usize, &str)> {
+    let mut paragraphs = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(start) = start.take() {
+                paragraphs.push((start, &text[start..offset]));
+            }
+        } else if start.is_none() {
+            start = Some(offset);
+        }
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        paragraphs.push((start, &text[start..]));
+    }
+    paragraphs
+}
+
+/// Convert an offset in UTF-16 code units, as reported by LanguageTool, to
+/// a byte offset into `text`.
+fn byte_offset(text: &str, offset: usize) -> Option<usize> {
+    let mut units = 0;
+    for (index, char) in text.char_indices() {
+        if units == offset {
+            return Some(index);
+        }
+        units += char.len_utf16();
+    }
+    (units == offset).then_some(text.len())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn split_paragraphs() {
+        let text = "First line\nsecond line\n\n  \nThird\n";
+        assert_eq!(
+            paragraphs(text),
+            [(0, "First line\nsecond line\n"), (27, "Third\n")]
+        );
+    }
+
+    #[test]
+    fn utf16_offsets() {
+        let text = "a😀b";
+        assert_eq!(byte_offset(text, 1), Some(1));
+        assert_eq!(byte_offset(text, 2), None);
+        assert_eq!(byte_offset(text, 3), Some(5));
+        assert_eq!(byte_offset(text, 4), Some(6));
+        assert_eq!(byte_offset(text, 5), None);
+    }
+}
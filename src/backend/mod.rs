@@ -0,0 +1,199 @@
+mod aspell;
+mod cached;
+#[cfg(feature = "hunspell")]
+mod hunspell;
+#[cfg(feature = "languagetool")]
+mod languagetool;
+mod zspell;
+
+pub use self::aspell::AspellBackend;
+pub use self::cached::CachedBackend;
+#[cfg(feature = "hunspell")]
+pub use self::hunspell::HunspellBackend;
+#[cfg(feature = "languagetool")]
+pub use self::languagetool::LanguageToolBackend;
+pub use self::zspell::ZspellBackend;
+
+use crate::config::{Config, Engine};
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+use std::path::Path;
+
+/// A problem found by checking a whole text rather than single words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Byte range of the problem in the checked text.
+    pub span: Range<usize>,
+    /// Id of the rule that found the problem, or `None` for misspellings.
+    pub rule: Option<String>,
+    pub message: String,
+    /// Replacements for the text in `span`, best first.
+    pub replacements: Vec<String>,
+}
+
+/// A spell checking engine the checker delegates word lookups to. Backends
+/// are moved to the thread checking documents, but only used by one thread
+/// at a time.
+pub trait SpellBackend: Send {
+    /// Whether `word` is spelled correctly.
+    fn check(&self, word: &str) -> bool;
+
+    /// Corrections for `word`, best first.
+    fn suggest(&self, word: &str) -> Vec<String>;
+
+    /// Accept `word` from now on. Persisting it is up to the checker's
+    /// personal dictionaries, this only informs the engine.
+    fn add_word(&mut self, word: &str);
+
+    /// Whether the dictionary has `word` written exactly so, rather than
+    /// accepting it as the Title Case or ALL CAPS form of another word.
+    /// Engines that can't tell have every word they accept.
+    fn lists(&self, word: &str) -> bool {
+        self.check(word)
+    }
+
+    /// Whether the dictionary marks `word` as wrong, as with hunspell's
+    /// `FORBIDDENWORD` flag, rather than merely not knowing it.
+    fn forbidden(&self, _word: &str) -> bool {
+        false
+    }
+
+    /// Whether the engine checks whole texts with `check_text`, so that
+    /// the checker only prepares them for engines that do.
+    fn checks_text(&self) -> bool {
+        false
+    }
+
+    /// Problems in `text` that can't be found word by word, such as
+    /// grammar issues. Engines checking whole texts may report misspellings
+    /// here as well.
+    fn check_text(&self, _text: &str) -> Vec<Issue> {
+        Vec::new()
+    }
+
+    /// The stems `word` is derived from, if it's correct and the engine
+    /// knows them.
+    fn stems(&self, _word: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The morphological analysis of `word` in hunspell's notation, such
+    /// as `po:verb` or `ds:able`, if it's correct and the dictionary has
+    /// any.
+    fn analyze(&self, _word: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The language the dictionary names itself, such as the `LANG` of
+    /// hunspell affix files.
+    fn language(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether the results of `check` and `suggest` only change when words
+    /// are added, so that the checker may cache them.
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// Another backend checking the same way, sharing the dictionary, to
+    /// check on another thread. `None` if the engine can't be shared.
+    fn fork(&self) -> Option<Box<dyn SpellBackend>> {
+        None
+    }
+}
+
+impl<B: SpellBackend + ?Sized> SpellBackend for Box<B> {
+    fn check(&self, word: &str) -> bool {
+        (**self).check(word)
+    }
+
+    fn suggest(&self, word: &str) -> Vec<String> {
+        (**self).suggest(word)
+    }
+
+    fn add_word(&mut self, word: &str) {
+        (**self).add_word(word)
+    }
+
+    fn lists(&self, word: &str) -> bool {
+        (**self).lists(word)
+    }
+
+    fn forbidden(&self, word: &str) -> bool {
+        (**self).forbidden(word)
+    }
+
+    fn checks_text(&self) -> bool {
+        (**self).checks_text()
+    }
+
+    fn check_text(&self, text: &str) -> Vec<Issue> {
+        (**self).check_text(text)
+    }
+
+    fn stems(&self, word: &str) -> Vec<String> {
+        (**self).stems(word)
+    }
+
+    fn analyze(&self, word: &str) -> Vec<String> {
+        (**self).analyze(word)
+    }
+
+    fn language(&self) -> Option<&str> {
+        (**self).language()
+    }
+
+    fn cacheable(&self) -> bool {
+        (**self).cacheable()
+    }
+
+    fn fork(&self) -> Option<Box<dyn SpellBackend>> {
+        (**self).fork()
+    }
+}
+
+/// The backend of `engine` for `language`. Backends of engines using
+/// dictionary files are built by [`crate::dictionaries`] instead.
+pub fn create(
+    engine: Engine,
+    language: &str,
+    config: &Config,
+) -> Result<Box<dyn SpellBackend>> {
+    match engine {
+        Engine::Zspell | Engine::Hunspell => Err(anyhow!(
+            "{:?} backends are loaded from dictionary files",
+            engine
+        )),
+        Engine::Aspell => Ok(Box::new(AspellBackend::new(
+            config.aspell_command.as_deref(),
+            language,
+        )?)),
+        #[cfg(feature = "languagetool")]
+        Engine::LanguageTool => Ok(Box::new(LanguageToolBackend::new(
+            config.language_tool_url.as_deref(),
+            language,
+        ))),
+        #[cfg(not(feature = "languagetool"))]
+        Engine::LanguageTool => {
+            _ = (language, config);
+            Err(anyhow!(
+                "zspell_lsp was built without the languagetool feature"
+            ))
+        }
+    }
+}
+
+/// The system hunspell backend for an affix and dictionary file.
+pub fn hunspell(
+    affix: &Path,
+    dictionary: &Path,
+) -> Result<Box<dyn SpellBackend>> {
+    #[cfg(feature = "hunspell")]
+    return Ok(Box::new(HunspellBackend::new(affix, dictionary)?));
+    #[cfg(not(feature = "hunspell"))]
+    {
+        _ = (affix, dictionary);
+        Err(anyhow!("zspell_lsp was built without the hunspell feature"))
+    }
+}
@@ -0,0 +1,97 @@
+use super::SpellBackend;
+use std::collections::HashSet;
+use std::sync::Arc;
+use zspell::{Dictionary, MorphInfo};
+
+/// Backend using a [`zspell::Dictionary`] built from hunspell files.
+pub struct ZspellBackend {
+    /// Shared with the forks of the backend.
+    dict: Arc<Dictionary>,
+    /// Words added at runtime, which zspell dictionaries can't take after
+    /// they're built.
+    added: HashSet<String>,
+    /// The `LANG` of the affix file.
+    language: Option<String>,
+}
+
+impl ZspellBackend {
+    pub fn new(dict: Dictionary) -> Self {
+        Self {
+            dict: Arc::new(dict),
+            added: HashSet::new(),
+            language: None,
+        }
+    }
+
+    pub fn with_language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+}
+
+impl SpellBackend for ZspellBackend {
+    fn check(&self, word: &str) -> bool {
+        self.dict.check(word) || self.added.contains(word)
+    }
+
+    fn suggest(&self, word: &str) -> Vec<String> {
+        self.dict
+            .entry(word)
+            .suggest()
+            .unwrap_or_default()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn add_word(&mut self, word: &str) {
+        self.added.insert(word.to_string());
+    }
+
+    fn lists(&self, word: &str) -> bool {
+        // zspell accepts the capitalized forms of lowercase words
+        self.added.contains(word)
+            || [self.dict.wordlist(), self.dict.wordlist_nosuggest()]
+                .iter()
+                .any(|list| list.inner().contains_key(word))
+    }
+
+    fn forbidden(&self, word: &str) -> bool {
+        !self.added.contains(word) && self.dict.entry(word).forbidden()
+    }
+
+    fn fork(&self) -> Option<Box<dyn SpellBackend>> {
+        Some(Box::new(Self {
+            dict: Arc::clone(&self.dict),
+            added: self.added.clone(),
+            language: self.language.clone(),
+        }))
+    }
+
+    fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    fn stems(&self, word: &str) -> Vec<String> {
+        self.dict
+            .entry(word)
+            .stems()
+            .map(|stems| stems.map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    fn analyze(&self, word: &str) -> Vec<String> {
+        self.dict
+            .entry(word)
+            .analyze()
+            .map(|morphs| {
+                // Stems are listed by `stems`, compiled dictionaries keep them
+                // as `st:` fields
+                morphs
+                    .filter(|morph| !matches!(morph, MorphInfo::Stem(_)))
+                    .map(ToString::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
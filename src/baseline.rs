@@ -0,0 +1,104 @@
+use crate::report::Finding;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Known findings that are not reported again. Entries are keyed by file
+/// and word rather than position so that unrelated edits don't invalidate
+/// the baseline.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Baseline {
+    files: BTreeMap<PathBuf, BTreeMap<String, usize>>,
+}
+
+impl Baseline {
+    pub fn from_findings(findings: &[Finding]) -> Self {
+        let mut baseline = Self::default();
+        for finding in findings {
+            *baseline
+                .files
+                .entry(finding.path.clone())
+                .or_default()
+                .entry(finding.word.clone())
+                .or_default() += 1;
+        }
+        baseline
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "unable to read baseline {} (create it with --write-baseline)",
+                path.display()
+            )
+        })?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid baseline {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text + "\n")
+            .with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Remove findings covered by the baseline. If a word occurs more often
+    /// in a file than recorded, the surplus occurrences are kept.
+    pub fn filter(&self, findings: Vec<Finding>) -> Vec<Finding> {
+        let mut remaining = self.files.clone();
+        findings
+            .into_iter()
+            .filter(|finding| {
+                let count = remaining
+                    .get_mut(&finding.path)
+                    .and_then(|words| words.get_mut(&finding.word));
+                match count {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        false
+                    }
+                    _ => true,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use lsp_types::Range;
+
+    fn finding(path: &str, word: &str) -> Finding {
+        Finding {
+            path: PathBuf::from(path),
+            range: Range::default(),
+            word: word.to_string(),
+            suggestions: Vec::new(),
+            line: String::new(),
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let baseline = Baseline::from_findings(&[
+            finding("a.md", "teh"),
+            finding("b.md", "wrld"),
+        ]);
+
+        let findings = baseline.filter(vec![
+            finding("a.md", "teh"),
+            finding("a.md", "teh"),
+            finding("a.md", "wrld"),
+            finding("b.md", "wrld"),
+        ]);
+
+        let remaining = findings
+            .iter()
+            .map(|finding| (finding.path.to_str().unwrap(), &*finding.word))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, [("a.md", "teh"), ("a.md", "wrld")]);
+    }
+}
@@ -0,0 +1,554 @@
+use crate::baseline::Baseline;
+use crate::changes::{self, ChangedLines};
+use crate::checker::Checker;
+use crate::config::Config;
+use crate::incremental::{CachedWord, ResultsCache};
+use crate::report::{self, Finding, Format};
+use crate::skip::Skip;
+use crate::workspace;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::{info, warn};
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::io::{IsTerminal, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Files or directories to check, or `-` to read from standard input.
+    /// Directories are walked honoring `.gitignore` and `.ignore` files.
+    /// With `--staged`, limits the staged files checked.
+    #[arg(required_unless_present_any = ["stdin", "files_from", "staged"])]
+    paths: Vec<PathBuf>,
+
+    /// Check the files staged in git, as they will be committed, for use
+    /// as a pre-commit hook.
+    #[arg(long, conflicts_with_all = ["stdin", "files_from", "watch"])]
+    staged: bool,
+
+    /// Read NUL-separated paths to check from a file, or `-` for standard
+    /// input, as produced by `git ls-files -z` or `find -print0`.
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Check text read from standard input.
+    #[arg(long)]
+    stdin: bool,
+
+    /// File name to report for text read from standard input.
+    #[arg(long, value_name = "PATH")]
+    stdin_filename: Option<PathBuf>,
+
+    /// Output format, by default `pretty` on terminals and `plain`
+    /// otherwise.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Don't color pretty output. Output is only colored on terminals, and
+    /// unless the `NO_COLOR` environment variable is set.
+    #[arg(long)]
+    no_color: bool,
+
+    /// Only report findings that are not recorded in this baseline file.
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// Record the current findings in the baseline file instead of
+    /// reporting them.
+    #[arg(long, requires = "baseline")]
+    write_baseline: bool,
+
+    /// Exit successfully as long as at most this many misspellings are
+    /// reported.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_errors: usize,
+
+    /// Keep running and recheck files below the given paths whenever they
+    /// change.
+    #[arg(long, conflicts_with_all = ["stdin", "write_baseline"])]
+    watch: bool,
+
+    /// Always exit successfully when checking completes, even if
+    /// misspellings are reported.
+    #[arg(long)]
+    warn_only: bool,
+
+    /// Only report misspellings on lines added or changed since the last
+    /// commit, as the `onlyChangedLines` setting does.
+    #[arg(long)]
+    changed_lines: bool,
+
+    /// Reuse the results of earlier runs for files that haven't changed,
+    /// kept in the user cache directory.
+    #[arg(long)]
+    cache: bool,
+
+    /// Number of files to check at once, by default the number of CPUs.
+    #[arg(long, short, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+}
+
+impl CheckArgs {
+    fn inputs(&self) -> Result<Vec<PathBuf>> {
+        if self.staged {
+            return changes::staged_files(Path::new("."), &self.paths);
+        }
+
+        let mut inputs = self.paths.clone();
+        if self.stdin && !inputs.iter().any(|path| is_stdin(path)) {
+            inputs.push(PathBuf::from("-"));
+        }
+
+        if let Some(files_from) = &self.files_from {
+            if is_stdin(files_from) && inputs.iter().any(|path| is_stdin(path))
+            {
+                bail!("standard input can't be used for both text and paths");
+            }
+            let list = read_input_bytes(files_from)?;
+            inputs.extend(
+                list.split(|&byte| byte == 0)
+                    .filter(|path| !path.is_empty())
+                    .map(path_from_bytes),
+            );
+        }
+
+        Ok(inputs)
+    }
+
+    /// The path findings in `input` are reported under.
+    fn display_path(&self, input: &Path) -> PathBuf {
+        match &self.stdin_filename {
+            Some(filename) if is_stdin(input) => filename.clone(),
+            _ => input.to_path_buf(),
+        }
+    }
+
+    fn format(&self) -> Format {
+        self.format.unwrap_or(if std::io::stdout().is_terminal() {
+            Format::Pretty
+        } else {
+            Format::Plain
+        })
+    }
+
+    /// Whether to color output: on terminals, unless turned off.
+    fn color(&self) -> bool {
+        !self.no_color
+            && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            && std::io::stdout().is_terminal()
+    }
+
+    fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| std::thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    /// Whether a run reporting `count` misspellings should succeed.
+    pub fn passed(&self, count: usize) -> bool {
+        self.warn_only || count <= self.max_errors
+    }
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn read_input_bytes(path: &Path) -> Result<Vec<u8>> {
+    if is_stdin(path) {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .context("unable to read standard input")?;
+        return Ok(bytes);
+    }
+    std::fs::read(path)
+        .with_context(|| format!("unable to read {}", path.display()))
+}
+
+/// The text of `path`, or of its contents in the git index if `staged`,
+/// `None` if it's too large, binary or, unless `config` says otherwise,
+/// minified or generated.
+fn read_input(
+    path: &Path,
+    config: &Config,
+    staged: bool,
+) -> Result<Option<String>> {
+    let too_large = |size: u64| size > config.max_file_size() as u64;
+    let bytes = if staged {
+        let bytes = changes::staged_bytes(path)?;
+        (!too_large(bytes.len() as u64)).then_some(bytes)
+    } else {
+        // Don't read files too large to be checked
+        let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        if is_stdin(path) || !too_large(size) {
+            Some(read_input_bytes(path)?)
+        } else {
+            None
+        }
+    };
+    let Some(bytes) = bytes else {
+        info!("skipping {} file {}", Skip::TooLarge, path.display());
+        return Ok(None);
+    };
+    if let Some(skip) = Skip::of(&bytes, config) {
+        info!("skipping {} file {}", skip, path.display());
+        return Ok(None);
+    }
+    String::from_utf8(bytes)
+        .map(Some)
+        .with_context(|| format!("{} is not valid UTF-8", path.display()))
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Check files outside of the language server and print the findings in
+/// the requested format. Returns the number of misspellings reported.
+pub fn check(
+    checker: &Checker,
+    config: &Config,
+    args: &CheckArgs,
+) -> Result<usize> {
+    let inputs = args.inputs()?;
+    let (checked, findings) = collect(checker, config, args, &inputs)?;
+
+    if let (Some(path), true) = (&args.baseline, args.write_baseline) {
+        Baseline::from_findings(&findings).save(path)?;
+        return Ok(0);
+    }
+
+    let count = report(checker, config, args, &checked, findings)?;
+
+    if args.watch {
+        watch(checker, config, args, &inputs)?;
+    }
+
+    Ok(count)
+}
+
+fn collect(
+    checker: &Checker,
+    config: &Config,
+    args: &CheckArgs,
+    inputs: &[PathBuf],
+) -> Result<(Vec<PathBuf>, Vec<Finding>)> {
+    // Files found in directories, rather than named, are skipped instead of
+    // failing the run if they can't be read
+    let inputs = inputs
+        .iter()
+        .flat_map(|input| {
+            if !args.staged && !is_stdin(input) && input.is_dir() {
+                workspace::files(input)
+                    .into_iter()
+                    .map(|file| (file, true))
+                    .collect()
+            } else {
+                vec![(input.clone(), false)]
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut forks = Vec::new();
+    while forks.len() + 1 < args.jobs().min(inputs.len()) {
+        let Some(fork) = checker.fork() else {
+            info!("checking on one thread, as the backends can't be shared");
+            break;
+        };
+        forks.push(fork);
+    }
+
+    let cache = args.cache.then(|| ResultsCache::open(config)).flatten();
+    let cache = cache.as_ref();
+
+    // Workers take the next input until none are left, and the results are
+    // put back in order
+    let next = AtomicUsize::new(0);
+    let work = |checker: &Checker| {
+        let mut results = Vec::new();
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let Some((input, walked)) = inputs.get(index) else {
+                return results;
+            };
+            let result =
+                check_input(checker, config, args, cache, input, *walked);
+            results.push((index, result));
+        }
+    };
+    let mut results = std::thread::scope(|scope| {
+        let workers = forks
+            .into_iter()
+            .map(|fork| scope.spawn(move || work(&fork)))
+            .collect::<Vec<_>>();
+        let mut results = work(checker);
+        for worker in workers {
+            results.extend(worker.join().expect("checking thread panicked"));
+        }
+        results
+    });
+    results.sort_by_key(|(index, _)| *index);
+    if let Some(Err(err)) = cache.map(ResultsCache::save) {
+        warn!("Unable to write results cache: {:#}", err);
+    }
+
+    let mut findings = Vec::new();
+    let mut checked = Vec::new();
+    for (_, result) in results {
+        if let Some((path, found)) = result? {
+            findings.extend(found);
+            checked.push(path);
+        }
+    }
+
+    Ok((checked, findings))
+}
+
+/// Check `input`, returning the path it's reported under and its findings,
+/// or `None` if it's skipped. `walked` inputs, found in directories, are
+/// skipped if they can't be read. Results in `cache` are reused.
+fn check_input(
+    checker: &Checker,
+    config: &Config,
+    args: &CheckArgs,
+    cache: Option<&ResultsCache>,
+    input: &Path,
+    walked: bool,
+) -> Result<Option<(PathBuf, Vec<Finding>)>> {
+    if !is_stdin(input) && !config.is_included(input) {
+        return Ok(None);
+    }
+
+    let path = args.display_path(input);
+    let config = config.for_path(&path);
+    let text = match read_input(input, &config, args.staged) {
+        Ok(Some(text)) => text,
+        Ok(None) => return Ok(None),
+        // Staged files are found rather than named as well
+        Err(err) if walked || args.staged => {
+            warn!("skipping {}: {:#}", input.display(), err);
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let changed_lines = args.changed_lines || config.only_changed_lines;
+    // Results on changed lines depend on the repository as well
+    let cache = cache.filter(|_| !changed_lines && !is_stdin(input));
+    let key = cache.map(|cache| cache.key(&path, &config, &text));
+    let cached = cache
+        .zip(key)
+        .and_then(|(cache, key)| cache.get(&path, key));
+    let words = match cached {
+        Some(words) => words,
+        None => {
+            let changed = if changed_lines {
+                ChangedLines::since_head(&path, &text)
+            } else {
+                None
+            };
+            let words = checker
+                .misspelled_words(&text, &config)
+                .into_iter()
+                .filter(|word| {
+                    let line = word.range.start.line;
+                    changed
+                        .as_ref()
+                        .is_none_or(|changed| changed.contains(line))
+                })
+                .map(|word| CachedWord {
+                    range: word.range,
+                    word: word.text.to_string(),
+                })
+                .collect::<Vec<_>>();
+            if let Some((cache, key)) = cache.zip(key) {
+                cache.insert(&path, key, words.clone());
+            }
+            words
+        }
+    };
+    let lines = text.lines().collect::<Vec<_>>();
+    let findings = words
+        .into_iter()
+        .map(|word| Finding {
+            path: path.clone(),
+            line: lines
+                .get(word.range.start.line as usize)
+                .map_or_else(String::new, |line| line.to_string()),
+            range: word.range,
+            word: word.word,
+            suggestions: Vec::new(),
+        })
+        .collect();
+
+    Ok(Some((path, findings)))
+}
+
+fn report(
+    checker: &Checker,
+    config: &Config,
+    args: &CheckArgs,
+    checked: &[PathBuf],
+    mut findings: Vec<Finding>,
+) -> Result<usize> {
+    if let Some(path) = &args.baseline {
+        findings = Baseline::load(path)?.filter(findings);
+    }
+
+    if args.format().needs_suggestions() {
+        for finding in &mut findings {
+            let config = config.for_path(&finding.path);
+            finding.suggestions = checker.suggest(&finding.word, &config);
+        }
+    }
+
+    report::write(
+        &mut std::io::stdout().lock(),
+        args.format(),
+        checked,
+        &findings,
+        args.color(),
+    )?;
+
+    Ok(findings.len())
+}
+
+/// Recheck files below `inputs` as they change, until interrupted.
+fn watch(
+    checker: &Checker,
+    config: &Config,
+    args: &CheckArgs,
+    inputs: &[PathBuf],
+) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for input in inputs {
+        watcher
+            .watch(input, RecursiveMode::Recursive)
+            .with_context(|| format!("unable to watch {}", input.display()))?;
+        eprintln!("watching {}", input.display());
+    }
+
+    loop {
+        let mut changed = BTreeSet::new();
+        let mut next = Some(receiver.recv()?);
+        // Editors tend to emit several events per save, collect them all
+        // before rechecking
+        while let Some(event) = next {
+            let event = event?;
+            if event.kind.is_create() || event.kind.is_modify() {
+                changed.extend(event.paths.into_iter().filter(|path| {
+                    path.is_file() && !workspace::is_hidden(path)
+                }));
+            }
+            next = receiver.recv_timeout(Duration::from_millis(100)).ok();
+        }
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        let changed = changed.into_iter().collect::<Vec<_>>();
+        match collect(checker, config, args, &changed) {
+            Ok((checked, findings)) => {
+                let count = report(checker, config, args, &checked, findings)?;
+                eprintln!(
+                    "checked {} file(s), {} misspelling(s)",
+                    checked.len(),
+                    count
+                );
+            }
+            Err(err) => eprintln!("{:#}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::backend::ZspellBackend;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: CheckArgs,
+    }
+
+    #[test]
+    fn directories() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_directories_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("docs/.hidden")).unwrap();
+        std::fs::create_dir_all(dir.join("docs/build")).unwrap();
+        std::fs::write(dir.join("docs/.ignore"), "build/\n").unwrap();
+        std::fs::write(dir.join("docs/a.md"), "hello wrld").unwrap();
+        std::fs::write(dir.join("docs/build/b.md"), "wrld").unwrap();
+        std::fs::write(dir.join("docs/.hidden/c.md"), "wrld").unwrap();
+        std::fs::write(dir.join("docs/d.bin"), b"wrld\0\xff").unwrap();
+        std::fs::write(dir.join("docs/e.txt"), b"caf\xe9").unwrap();
+
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\n")
+            .dict_str("1\nhello\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let cli =
+            Cli::parse_from(["check", dir.join("docs").to_str().unwrap()]);
+        let collected =
+            collect(&checker, &Config::default(), &cli.args, &cli.args.paths);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (checked, findings) = collected.unwrap();
+        assert_eq!(checked, [dir.join("docs/a.md")]);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].word, "wrld");
+    }
+
+    #[test]
+    fn jobs() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_jobs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for letter in 'a'..='t' {
+            let text = format!("hello wrld{}", letter);
+            std::fs::write(dir.join(format!("{}.txt", letter)), text).unwrap();
+        }
+
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\n")
+            .dict_str("1\nhello\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let dir_arg = dir.to_str().unwrap();
+        let collected = ["1", "4"].map(|jobs| {
+            let cli = Cli::parse_from(["check", "--jobs", jobs, dir_arg]);
+            collect(&checker, &Config::default(), &cli.args, &cli.args.paths)
+        });
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let [(sequential, _), (parallel, findings)] =
+            collected.map(Result::unwrap);
+        assert_eq!(parallel, sequential);
+        let words = findings.iter().map(|finding| finding.word.as_str());
+        assert!(words.eq(('a'..='t').map(|letter| format!("wrld{}", letter))));
+    }
+}
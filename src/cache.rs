@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Estimated size of an entry besides its key and value, for the hash
+/// table slots and the recency index.
+const ENTRY_OVERHEAD: usize = 64;
+
+/// A map from strings to values that evicts the least recently used
+/// entries once their estimated size exceeds its capacity in bytes.
+pub struct LruCache<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Keys by the time they were last used, oldest first.
+    recency: BTreeMap<u64, String>,
+    time: u64,
+    size: usize,
+    capacity: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    used: u64,
+}
+
+impl<V> LruCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            time: 0,
+            size: 0,
+            capacity,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<&V> {
+        let entry = self.entries.get_mut(key)?;
+        let key = self.recency.remove(&entry.used).unwrap();
+        self.time += 1;
+        entry.used = self.time;
+        self.recency.insert(self.time, key);
+        Some(&entry.value)
+    }
+
+    /// Insert `value`, whose heap allocations take `size` bytes, and evict
+    /// entries until the cache fits its capacity again.
+    pub fn insert(&mut self, key: String, value: V, size: usize) {
+        self.remove(&key);
+        let size = size + 2 * key.len() + ENTRY_OVERHEAD;
+        if size > self.capacity {
+            return;
+        }
+        self.time += 1;
+        self.recency.insert(self.time, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                used: self.time,
+            },
+        );
+        self.size += size;
+        self.evict();
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+            self.size -= entry.size;
+        }
+    }
+
+    /// Change the capacity, evicting entries that no longer fit.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.capacity {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.size -= entry.size;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn eviction() {
+        let entry = 2 + ENTRY_OVERHEAD;
+        let mut cache = LruCache::new(3 * entry);
+        cache.insert("a".into(), 1, 0);
+        cache.insert("b".into(), 2, 0);
+        cache.insert("c".into(), 3, 0);
+        assert_eq!(cache.get("a"), Some(&1));
+
+        // "b" is the least recently used
+        cache.insert("d".into(), 4, 0);
+        assert_eq!(cache.entries.len(), 3);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&1));
+
+        cache.set_capacity(entry);
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.size, entry);
+
+        // Values larger than the whole cache aren't kept
+        cache.insert("e".into(), 5, 2 * entry);
+        assert_eq!(cache.get("e"), None);
+    }
+}
@@ -0,0 +1,140 @@
+//! Changing the case of words by the rules of their language, where
+//! Unicode's default mappings are wrong, as for the dotted and dotless i of
+//! Turkish.
+
+use crate::dictionaries;
+
+/// How a word is capitalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// No uppercase letters.
+    Lower,
+    /// Only the first letter is uppercase.
+    Title,
+    /// Several letters, all uppercase.
+    Upper,
+    /// Anything else, such as `iPhone`.
+    Mixed,
+}
+
+impl Case {
+    pub fn of(word: &str) -> Self {
+        let mut letters = word.chars().filter(|c| c.is_alphabetic());
+        let Some(first) = letters.next() else {
+            return Case::Mixed;
+        };
+        let rest = letters.collect::<Vec<_>>();
+        if !first.is_uppercase() {
+            if rest.iter().any(|c| c.is_uppercase()) {
+                Case::Mixed
+            } else {
+                Case::Lower
+            }
+        } else if rest.iter().all(|c| !c.is_uppercase()) {
+            Case::Title
+        } else if rest.iter().all(|c| c.is_uppercase()) {
+            Case::Upper
+        } else {
+            Case::Mixed
+        }
+    }
+}
+
+/// The case mappings of a language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Casing {
+    /// Whether `i` and `ı` are distinct letters, uppercase `İ` and `I`.
+    dotless_i: bool,
+}
+
+impl Casing {
+    /// The mappings of `locale`, Unicode's default ones if it's `None`.
+    pub fn of(locale: Option<&str>) -> Self {
+        let base = locale.map(|locale| {
+            dictionaries::base_language(locale).to_ascii_lowercase()
+        });
+        Self {
+            dotless_i: matches!(base.as_deref(), Some("tr" | "az" | "crh")),
+        }
+    }
+
+    pub fn lowercase(self, word: &str) -> String {
+        if !self.dotless_i {
+            return word.to_lowercase();
+        }
+        let mut lowercase = String::with_capacity(word.len());
+        for c in word.chars() {
+            match c {
+                'I' => lowercase.push('ı'),
+                'İ' => lowercase.push('i'),
+                c => lowercase.extend(c.to_lowercase()),
+            }
+        }
+        lowercase
+    }
+
+    pub fn uppercase(self, word: &str) -> String {
+        if !self.dotless_i {
+            return word.to_uppercase();
+        }
+        word.chars().map(|c| self.uppercase_char(c)).collect()
+    }
+
+    fn uppercase_char(self, c: char) -> String {
+        match c {
+            'i' if self.dotless_i => "İ".to_string(),
+            c => c.to_uppercase().collect(),
+        }
+    }
+
+    /// `word` with its first letter uppercase.
+    pub fn capitalize(self, word: &str) -> String {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => self.uppercase_char(first) + chars.as_str(),
+            None => String::new(),
+        }
+    }
+
+    /// `suggestion` capitalized or uppercase like `word`, unless it has
+    /// its own capitals.
+    pub fn match_case(self, word: &str, suggestion: &str) -> String {
+        match (Case::of(word), Case::of(suggestion)) {
+            (Case::Title, Case::Lower) => self.capitalize(suggestion),
+            (Case::Upper, Case::Lower | Case::Title) => {
+                self.uppercase(suggestion)
+            }
+            _ => suggestion.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn turkish() {
+        let turkish = Casing::of(Some("tr_TR"));
+        let english = Casing::of(Some("en_US"));
+
+        assert_eq!(turkish.lowercase("DİYARBAKIR"), "diyarbakır");
+        assert_eq!(turkish.uppercase("istanbul"), "İSTANBUL");
+        assert_eq!(turkish.capitalize("izmir"), "İzmir");
+        assert_eq!(english.lowercase("DIYARBAKIR"), "diyarbakir");
+        assert_eq!(english.capitalize("izmir"), "Izmir");
+        assert_eq!(Casing::of(None), english);
+    }
+
+    #[test]
+    fn suggestions() {
+        let casing = Casing::default();
+
+        assert_eq!(casing.match_case("Helo", "hello"), "Hello");
+        assert_eq!(casing.match_case("HELO", "hello"), "HELLO");
+        assert_eq!(casing.match_case("helo", "hello"), "hello");
+        assert_eq!(casing.match_case("Iphone", "iPhone"), "iPhone");
+        assert_eq!(casing.match_case("PARRIS", "Paris"), "PARIS");
+    }
+}
@@ -0,0 +1,259 @@
+//! Lines changed since the last commit, for reporting problems only on
+//! them with `onlyChangedLines`, and the files staged for the next one.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Differences between the old and new text of a file beyond which all
+/// lines between the first and last change are taken as changed, rather
+/// than finding the changes exactly.
+const MAX_EDITS: usize = 500;
+
+/// The lines of a file added or changed since an older version of it.
+pub struct ChangedLines(Vec<bool>);
+
+impl ChangedLines {
+    /// The lines of `text`, at `path`, changed since the last commit,
+    /// `None` if the file isn't committed and all of its lines are new.
+    pub fn since_head(path: &Path, text: &str) -> Option<Self> {
+        head_text(path).map(|head| Self::between(&head, text))
+    }
+
+    /// The lines of `new` changed since `old`.
+    pub fn between(old: &str, new: &str) -> Self {
+        Self(changed_lines(old, new))
+    }
+
+    pub fn contains(&self, line: u32) -> bool {
+        self.0.get(line as usize).copied().unwrap_or(false)
+    }
+}
+
+/// The text of the file at `path` at the last commit of its git
+/// repository, `None` if it isn't committed or git can't tell.
+pub fn head_text(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let output = Command::new("git")
+        .arg("show")
+        .arg(format!("HEAD:./{}", name))
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// The files below `directory` added or changed in the index of its git
+/// repository, relative to it, limited to those matching `pathspecs` if
+/// any are given.
+pub fn staged_files(
+    directory: &Path,
+    pathspecs: &[PathBuf],
+) -> Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args(["diff", "--cached", "--name-only", "-z", "--relative"])
+        .arg("--diff-filter=ACMR")
+        .arg("--")
+        .args(pathspecs)
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()
+        .context("unable to run git")?;
+    if !output.status.success() {
+        bail!(
+            "unable to list staged files: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output
+        .stdout
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| PathBuf::from(String::from_utf8_lossy(name).as_ref()))
+        .collect())
+}
+
+/// The contents of the file at `path` in the index of its git repository,
+/// as it will be committed.
+pub fn staged_bytes(path: &Path) -> Result<Vec<u8>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("{} is not a file name", path.display()))?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let output = Command::new("git")
+        .arg("show")
+        .arg(format!(":./{}", name))
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()
+        .context("unable to run git")?;
+    if !output.status.success() {
+        bail!(
+            "unable to read staged {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Whether each line of `new` was added or changed since `old`.
+fn changed_lines(old: &str, new: &str) -> Vec<bool> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let mut changed = vec![false; new.len()];
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new_lines = &new[prefix..new.len() - suffix];
+    match inserted(old, new_lines) {
+        Some(inserted) => {
+            for index in inserted {
+                changed[prefix + index] = true;
+            }
+        }
+        None => changed[prefix..prefix + new_lines.len()].fill(true),
+    }
+    changed
+}
+
+/// The indices of the lines of `new` inserted into `old` by a shortest
+/// edit script, found with Myers' algorithm, `None` if it takes more than
+/// `MAX_EDITS` edits.
+fn inserted(old: &[&str], new: &[&str]) -> Option<Vec<usize>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (old.len() + new.len()).min(MAX_EDITS) as isize;
+    // The furthest x reached on each diagonal k = x - y, offset by max
+    let mut v = vec![0; 2 * max as usize + 2];
+    let index = |k: isize| (k + max) as usize;
+    let mut trace = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let down = k == -d || k != d && v[index(k - 1)] < v[index(k + 1)];
+            let mut x = if down {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m, max));
+            }
+        }
+    }
+    None
+}
+
+/// The lines inserted along the edit script found to reach (`n`, `m`).
+fn backtrack(
+    trace: &[Vec<isize>],
+    n: isize,
+    m: isize,
+    max: isize,
+) -> Vec<usize> {
+    let index = |k: isize| (k + max) as usize;
+    let mut inserted = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let k = x - y;
+        let down = k == -d || k != d && v[index(k - 1)] < v[index(k + 1)];
+        let previous_k = if down { k + 1 } else { k - 1 };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        if down {
+            inserted.push(previous_y as usize);
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    inserted.reverse();
+    inserted
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn changes() {
+        let old = "a\nb\nc\nd\ne\nf\n";
+        let new = "a\nB\nc\nd\nnew\ne\nf\n";
+        let changed = changed_lines(old, new);
+        assert_eq!(changed, [false, true, false, false, true, false, false]);
+
+        assert_eq!(changed_lines("", "a\nb"), [true, true]);
+        assert_eq!(changed_lines("a\nb", "a\nb"), [false, false]);
+        let changed = changed_lines("a\nb\nc", "c\nb\na");
+        assert_eq!(changed.iter().filter(|&&changed| changed).count(), 2);
+
+        let old = (0..2000).map(|n| format!("{}\n", n)).collect::<String>();
+        let new = (0..2000)
+            .map(|n| format!("{}\n", n * 7))
+            .collect::<String>();
+        let changed = changed_lines(&old, &new);
+        assert_eq!(changed.iter().filter(|&&changed| changed).count(), 1999);
+    }
+
+    #[test]
+    fn staged() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_staged_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        std::fs::write(dir.join("docs/a.md"), "staged").unwrap();
+        std::fs::write(dir.join("b.md"), "staged").unwrap();
+        std::fs::write(dir.join("c.md"), "untracked").unwrap();
+        if !git(&["init", "-q"]) || !git(&["add", "docs/a.md", "b.md"]) {
+            // Without git there's nothing to stage
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        std::fs::write(dir.join("docs/a.md"), "modified").unwrap();
+
+        let files = staged_files(&dir, &[]).unwrap();
+        let filtered = staged_files(&dir, &[PathBuf::from("docs")]).unwrap();
+        let bytes = staged_bytes(&dir.join("docs/a.md")).unwrap();
+        let untracked = staged_bytes(&dir.join("c.md"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files, [Path::new("b.md"), Path::new("docs/a.md")]);
+        assert_eq!(filtered, [Path::new("docs/a.md")]);
+        assert_eq!(bytes, b"staged");
+        assert!(untracked.is_err());
+    }
+}
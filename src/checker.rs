@@ -0,0 +1,1132 @@
+use crate::affixes::Affixes;
+use crate::backend::{CachedBackend, Issue, SpellBackend};
+use crate::casing::{Case, Casing};
+use crate::config::{Config, DetectIn, Layer};
+use crate::lexer::{self, Lexer, Word};
+use crate::personal::{self, PersonalDictionary};
+use crate::words::{self, UserWord};
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use lsp_types::{Position, Range};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use streaming_iterator::StreamingIterator;
+
+/// Number of words from the start of a document its language is detected
+/// by.
+const DETECTION_WORDS: usize = 200;
+
+/// Memory the caches of the backends may take unless set otherwise.
+pub const DEFAULT_CACHE_BUDGET: usize = 32 << 20;
+
+/// The lexer and spell backend pipeline shared by the language server and
+/// the command line checker.
+pub struct Checker {
+    backend: CachedBackend,
+    /// Language of `backend`, if it was selected by locale.
+    language: Option<String>,
+    /// Backends of the languages selected through settings, used instead
+    /// of `backend` when their language is configured.
+    languages: HashMap<String, CachedBackend>,
+    /// Memory in bytes shared by the caches of all backends.
+    cache_budget: usize,
+    /// Personal dictionaries by file, `None` holding the words added while
+    /// no file is configured.
+    personal: HashMap<Option<PathBuf>, PersonalDictionary>,
+}
+
+impl Checker {
+    pub fn new(
+        backend: impl SpellBackend + 'static,
+        language: Option<String>,
+    ) -> Self {
+        Self {
+            backend: CachedBackend::new(
+                Box::new(backend),
+                DEFAULT_CACHE_BUDGET,
+            ),
+            language,
+            languages: HashMap::new(),
+            cache_budget: DEFAULT_CACHE_BUDGET,
+            personal: HashMap::new(),
+        }
+    }
+
+    /// A checker sharing the dictionaries of this one, with caches of its
+    /// own, to check on another thread. `None` if a backend can't be
+    /// forked.
+    pub fn fork(&self) -> Option<Self> {
+        let share = self.cache_budget / (self.languages.len() + 1);
+        let fork = |backend: &CachedBackend| {
+            Some(CachedBackend::new(backend.fork()?, share))
+        };
+        let languages = self
+            .languages
+            .iter()
+            .map(|(language, backend)| Some((language.clone(), fork(backend)?)))
+            .collect::<Option<_>>()?;
+        Some(Self {
+            backend: fork(&self.backend)?,
+            language: self.language.clone(),
+            languages,
+            cache_budget: self.cache_budget,
+            personal: self.personal.clone(),
+        })
+    }
+
+    /// Limit the memory taken by the caches of the backends to about
+    /// `budget` bytes, evicting the least recently used entries.
+    pub fn set_cache_budget(&mut self, budget: usize) {
+        if budget == self.cache_budget {
+            return;
+        }
+        self.cache_budget = budget;
+        self.balance_caches();
+    }
+
+    fn balance_caches(&mut self) {
+        let share = self.cache_budget / (self.languages.len() + 1);
+        self.backend.set_capacity(share);
+        for backend in self.languages.values_mut() {
+            backend.set_capacity(share);
+        }
+    }
+
+    /// Load the personal dictionary at `path`, replacing the words read
+    /// from it before, and inflecting those with flags by the rules of
+    /// `affixes`. Words added without a file are kept.
+    pub fn load_personal(
+        &mut self,
+        path: Option<PathBuf>,
+        affixes: Option<&Affixes>,
+    ) -> Result<()> {
+        if path.is_none() && self.personal.contains_key(&None) {
+            return Ok(());
+        }
+        let personal = PersonalDictionary::load(path.clone(), affixes)?;
+        self.personal.insert(path, personal);
+        Ok(())
+    }
+
+    /// Load the personal and team wordlists of `config`, with the words
+    /// of the dictionaries it imports from other spell checkers.
+    pub fn load_wordlists(
+        &mut self,
+        config: &Config,
+        affixes: Option<&Affixes>,
+    ) -> Result<()> {
+        self.load_personal(config.personal_dictionary.clone(), affixes)?;
+        let personal =
+            self.personal.get_mut(&config.personal_dictionary).unwrap();
+        for path in personal::import_paths(config) {
+            match personal.import(&path) {
+                Ok(count) => {
+                    info!("imported {} words from {}", count, path.display())
+                }
+                Err(err) => {
+                    warn!("unable to import {}: {:#}", path.display(), err)
+                }
+            }
+        }
+        if config.team_dictionary.is_some() {
+            self.load_personal(config.team_dictionary.clone(), affixes)?;
+        }
+        Ok(())
+    }
+
+    /// Add `word` to the personal dictionary of `config`.
+    pub fn add_personal(&mut self, config: &Config, word: &str) -> Result<()> {
+        self.add_word(config, Layer::Personal, word, "", &[])
+    }
+
+    /// Add `word` with affix `flags` to the wordlist of `config` in
+    /// `layer`, accepting its inflected `forms` as well.
+    pub fn add_word(
+        &mut self,
+        config: &Config,
+        layer: Layer,
+        word: &str,
+        flags: &str,
+        forms: &[String],
+    ) -> Result<()> {
+        let path = wordlist(config, layer)
+            .ok_or_else(|| anyhow!("no teamDictionary is configured"))?;
+        let backend = self.backend_mut(config);
+        backend.add_word(word);
+        for form in forms {
+            backend.add_word(form);
+        }
+        if !self.personal.contains_key(path) {
+            self.load_personal(path.clone(), None)?;
+        }
+        self.personal.get_mut(path).unwrap().add(word, flags, forms)
+    }
+
+    /// The number of words in the wordlist of `config` in `layer`, `None`
+    /// if it has none.
+    pub fn wordlist_len(&self, config: &Config, layer: Layer) -> Option<usize> {
+        let path = wordlist(config, layer)?;
+        Some(self.personal.get(path).map_or(0, PersonalDictionary::len))
+    }
+
+    /// The words added to the wordlists of `config`, including those added
+    /// at runtime without a personal dictionary file.
+    pub fn user_words(&self, config: &Config) -> Vec<UserWord> {
+        [Layer::Personal, Layer::Team]
+            .into_iter()
+            .filter_map(|layer| Some((layer, wordlist(config, layer)?)))
+            .filter_map(|(layer, path)| {
+                Some(words::user_words(layer, path, self.personal.get(path)?))
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Whether `backend` or the wordlists of `config` know `word`, under
+    /// the casing rules of `config` and the case mappings of `casing`.
+    fn is_known(
+        &self,
+        backend: &dyn SpellBackend,
+        config: &Config,
+        casing: Casing,
+        word: &str,
+    ) -> bool {
+        let known =
+            |word: &str| backend.check(word) || self.is_personal(config, word);
+        let listed =
+            |word: &str| backend.lists(word) || self.is_personal(config, word);
+        match Case::of(word) {
+            Case::Lower => {
+                known(word)
+                    || config.lowercase_proper_nouns
+                        && known(&casing.capitalize(word))
+            }
+            Case::Title => {
+                let common = known(&casing.lowercase(word));
+                if config.strict_title_case && common {
+                    // Accepted if it's a proper noun too, like `May`
+                    listed(word)
+                } else {
+                    common || known(word)
+                }
+            }
+            Case::Upper => {
+                let lowercase = casing.lowercase(word);
+                let common =
+                    known(&lowercase) || known(&casing.capitalize(&lowercase));
+                if config.strict_all_caps && common {
+                    listed(word)
+                } else {
+                    common || known(word)
+                }
+            }
+            Case::Mixed => known(word),
+        }
+    }
+
+    fn is_personal(&self, config: &Config, word: &str) -> bool {
+        [Layer::Personal, Layer::Team]
+            .into_iter()
+            .filter_map(|layer| wordlist(config, layer))
+            .filter_map(|path| self.personal.get(path))
+            .any(|personal| personal.contains(word))
+    }
+
+    pub fn has_language(&self, language: &str) -> bool {
+        self.language.as_deref() == Some(language)
+            || self.languages.contains_key(language)
+    }
+
+    pub fn add_language(
+        &mut self,
+        language: String,
+        backend: impl SpellBackend + 'static,
+    ) {
+        let backend = CachedBackend::new(Box::new(backend), 0);
+        self.languages.insert(language, backend);
+        self.balance_caches();
+    }
+
+    /// The language among `candidates` whose dictionary knows the most of
+    /// the first words of `text`, if it knows at least half of them.
+    /// Languages that aren't loaded are skipped.
+    pub fn detect_language<'a>(
+        &self,
+        text: &str,
+        candidates: &'a [String],
+    ) -> Option<&'a str> {
+        let mut lexer = Lexer::new(text)?;
+        let mut words = Vec::new();
+        while let Some(word) = lexer.next() {
+            words.push(word.text);
+            if words.len() == DETECTION_WORDS {
+                break;
+            }
+        }
+        self.detect_among(&words, candidates)
+    }
+
+    fn detect_among<'a>(
+        &self,
+        words: &[&str],
+        candidates: &'a [String],
+    ) -> Option<&'a str> {
+        let words = &words[..words.len().min(DETECTION_WORDS)];
+        let (language, known) = candidates
+            .iter()
+            .filter_map(|language| {
+                let backend = self.language_backend(language)?;
+                let known =
+                    words.iter().filter(|word| backend.check(word)).count();
+                Some((language, known))
+            })
+            // The first of the candidates knowing the most words
+            .rev()
+            .max_by_key(|(_, known)| *known)?;
+        (known > 0 && 2 * known >= words.len()).then_some(language)
+    }
+
+    /// The languages detected in the paragraphs or lines of `text`,
+    /// according to the `detectLanguagesIn` setting of `config`.
+    pub fn sections<'a>(&self, text: &str, config: &'a Config) -> Sections<'a> {
+        let mut sections = Vec::new();
+        if config.detect_languages.is_empty()
+            || config.detect_languages_in == DetectIn::Document
+        {
+            return Sections(sections);
+        }
+        let max_line_length = config.max_line_length();
+        let Some(mut lexer) =
+            Lexer::with_max_line_length(text, max_line_length)
+                .map(|lexer| lexer.with_syntax(config.syntax.clone()))
+        else {
+            return Sections(sections);
+        };
+        let blank = text
+            .lines()
+            .map(|line| line.trim().is_empty())
+            .collect::<Vec<_>>();
+        let breaks = |previous: u32, next: u32| match config.detect_languages_in
+        {
+            DetectIn::Document => false,
+            DetectIn::Paragraph => (previous + 1..next)
+                .any(|line| blank.get(line as usize) == Some(&true)),
+            DetectIn::Line => next > previous,
+        };
+
+        let mut section = Vec::new();
+        let mut start = 0;
+        let mut previous = 0;
+        let mut detect = |start, section: &mut Vec<&str>| {
+            let language = self.detect_among(section, &config.detect_languages);
+            sections.push((start, language));
+            section.clear();
+        };
+        while let Some(word) = lexer.next() {
+            let line = word.range.start.line;
+            if !section.is_empty() && breaks(previous, line) {
+                detect(start, &mut section);
+            }
+            if section.is_empty() {
+                start = line;
+            }
+            section.push(word.text);
+            previous = word.range.end.line;
+        }
+        if !section.is_empty() {
+            detect(start, &mut section);
+        }
+        Sections(sections)
+    }
+
+    /// The backend of `language`, if it's loaded.
+    fn language_backend(&self, language: &str) -> Option<&CachedBackend> {
+        match self.languages.get(language) {
+            Some(backend) => Some(backend),
+            None if self.language.as_deref() == Some(language) => {
+                Some(&self.backend)
+            }
+            None => None,
+        }
+    }
+
+    /// The backend checking the words of `line`: that of the language
+    /// detected in its section, or that of `config`.
+    fn backend_at(
+        &self,
+        sections: &Sections,
+        line: u32,
+        config: &Config,
+    ) -> &dyn SpellBackend {
+        match sections
+            .language_at(line)
+            .and_then(|language| self.language_backend(language))
+        {
+            Some(backend) => backend,
+            None => self.backend(config),
+        }
+    }
+
+    /// The case mappings of `language`, or of the language the default
+    /// dictionary names itself if there's none.
+    pub fn casing(&self, language: Option<&str>) -> Casing {
+        Casing::of(language.or(self.backend.language()))
+    }
+
+    /// The backend for the language of `config`, falling back to the one
+    /// the checker was created with.
+    fn backend(&self, config: &Config) -> &dyn SpellBackend {
+        config
+            .language
+            .as_ref()
+            .and_then(|language| self.languages.get(language))
+            .unwrap_or(&self.backend)
+    }
+
+    fn backend_mut(&mut self, config: &Config) -> &mut dyn SpellBackend {
+        match config
+            .language
+            .as_ref()
+            .and_then(|language| self.languages.get_mut(language))
+        {
+            Some(backend) => backend,
+            None => &mut self.backend,
+        }
+    }
+
+    pub fn misspelled_words<'a>(
+        &self,
+        text: &'a str,
+        config: &Config,
+    ) -> Vec<Word<'a>> {
+        self.check(text, config).0
+    }
+
+    /// The misspelled words of `text` and its other problems, such as
+    /// grammar issues reported by backends checking whole texts, which
+    /// check the text once for both.
+    pub fn check<'a>(
+        &self,
+        text: &'a str,
+        config: &Config,
+    ) -> (Vec<Word<'a>>, Vec<(Range, Issue)>) {
+        let mut words = Vec::new();
+        let sections = self.sections(text, config);
+
+        let Some(mut lexer) = lexer(text, config) else {
+            return (words, Vec::new());
+        };
+
+        while let Some(word) = lexer.next() {
+            let line = word.range.start.line;
+            let backend = self.backend_at(&sections, line, config);
+            let casing = self.casing(language_at(&sections, line, config));
+            if !self.is_known(backend, config, casing, word.text)
+                && !is_ignored(config, text, word)
+            {
+                words.push(*word);
+            }
+        }
+
+        let (misspellings, issues): (Vec<_>, Vec<_>) = self
+            .text_issues(text, config)
+            .into_iter()
+            .partition(|issue| issue.rule.is_none());
+        let misspellings = misspellings
+            .into_iter()
+            .map(|issue| Word {
+                text: &text[issue.span.clone()],
+                range: range_of(text, &issue.span),
+            })
+            .filter(|word| {
+                !self.is_personal(config, word.text)
+                    && !is_ignored(config, text, word)
+            })
+            .collect::<Vec<_>>();
+        if !misspellings.is_empty() {
+            words.extend(misspellings);
+            words.sort_by_key(|word| word.range.start);
+        }
+        let issues = issues
+            .into_iter()
+            .map(|issue| (range_of(text, &issue.span), issue))
+            .collect();
+
+        (words, issues)
+    }
+
+    /// Problems other than misspellings in `text`, such as grammar issues
+    /// reported by backends checking whole texts.
+    pub fn issues(&self, text: &str, config: &Config) -> Vec<(Range, Issue)> {
+        self.text_issues(text, config)
+            .into_iter()
+            .filter(|issue| issue.rule.is_some())
+            .map(|issue| (range_of(text, &issue.span), issue))
+            .collect()
+    }
+
+    /// What the backend checking whole texts finds in the prose of `text`,
+    /// leaving out code and URLs like the lexer does.
+    fn text_issues(&self, text: &str, config: &Config) -> Vec<Issue> {
+        let backend = self.backend(config);
+        if !backend.checks_text() {
+            return Vec::new();
+        }
+        let prose =
+            lexer::prose(text, &config.syntax, config.max_line_length());
+        backend.check_text(&prose)
+    }
+
+    pub fn suggest(&self, word: &str, config: &Config) -> Vec<String> {
+        let casing = self.casing(config.language.as_deref());
+        match_case(casing, word, self.backend(config).suggest(word))
+    }
+
+    /// Whether the dictionary of the section of `word` forbids it, rather
+    /// than merely not knowing it.
+    pub fn is_forbidden(
+        &self,
+        word: &Word,
+        sections: &Sections,
+        config: &Config,
+    ) -> bool {
+        self.backend_at(sections, word.range.start.line, config)
+            .forbidden(word.text)
+    }
+
+    /// The loaded languages whose dictionaries know `word`, in order.
+    pub fn languages_knowing(&self, word: &str, config: &Config) -> Vec<&str> {
+        let mut languages = self
+            .language
+            .iter()
+            .map(|language| (language, &self.backend))
+            .chain(&self.languages)
+            .filter(|(language, backend)| {
+                let casing = Casing::of(Some(language));
+                self.is_known(*backend, config, casing, word)
+            })
+            .map(|(language, _)| language.as_str())
+            .collect::<Vec<_>>();
+        languages.sort_unstable();
+        languages.dedup();
+        languages
+    }
+
+    /// The stems of `word` other than itself, in the language of `line`.
+    pub fn stems_at(
+        &self,
+        word: &str,
+        line: u32,
+        sections: &Sections,
+        config: &Config,
+    ) -> Vec<String> {
+        let mut stems = self.backend_at(sections, line, config).stems(word);
+        stems.retain(|stem| stem.to_lowercase() != word.to_lowercase());
+        stems
+    }
+
+    /// The morphological analysis of `word` in the language of `line`.
+    pub fn analyze_at(
+        &self,
+        word: &str,
+        line: u32,
+        sections: &Sections,
+        config: &Config,
+    ) -> Vec<String> {
+        self.backend_at(sections, line, config).analyze(word)
+    }
+
+    /// Suggestions for `word` in the language of its section.
+    pub fn suggest_in(
+        &self,
+        word: &Word,
+        sections: &Sections,
+        config: &Config,
+    ) -> Vec<String> {
+        let line = word.range.start.line;
+        let casing = self.casing(language_at(sections, line, config));
+        let suggestions =
+            self.backend_at(sections, line, config).suggest(word.text);
+        match_case(casing, word.text, suggestions)
+    }
+}
+
+/// The language of `line`: that detected in its section, or that of
+/// `config`.
+fn language_at<'a>(
+    sections: &'a Sections,
+    line: u32,
+    config: &'a Config,
+) -> Option<&'a str> {
+    sections.language_at(line).or(config.language.as_deref())
+}
+
+/// `suggestions` capitalized like `word`, each listed once.
+fn match_case(
+    casing: Casing,
+    word: &str,
+    suggestions: Vec<String>,
+) -> Vec<String> {
+    let mut matched = Vec::with_capacity(suggestions.len());
+    for suggestion in suggestions {
+        let suggestion = casing.match_case(word, &suggestion);
+        if !matched.contains(&suggestion) {
+            matched.push(suggestion);
+        }
+    }
+    matched
+}
+
+/// The key of the wordlist of `config` in `layer`, `None` if there is no
+/// team wordlist. Personal words are kept in memory without a file.
+fn wordlist(config: &Config, layer: Layer) -> Option<&Option<PathBuf>> {
+    match layer {
+        Layer::Personal => Some(&config.personal_dictionary),
+        Layer::Team => config
+            .team_dictionary
+            .is_some()
+            .then_some(&config.team_dictionary),
+    }
+}
+
+/// The languages detected in the parts of a text, see
+/// [`Checker::sections`].
+pub struct Sections<'a>(Vec<(u32, Option<&'a str>)>);
+
+impl Sections<'_> {
+    /// The language detected in the section containing `line`, `None` if
+    /// none was.
+    pub fn language_at(&self, line: u32) -> Option<&str> {
+        let index = self.0.partition_point(|(start, _)| *start <= line);
+        index.checked_sub(1).and_then(|index| self.0[index].1)
+    }
+}
+
+/// The range of the bytes `span` of `text`, counting characters in UTF-16
+/// code units like the lexer does.
+fn range_of(text: &str, span: &std::ops::Range<usize>) -> Range {
+    let position = |offset: usize| {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Position {
+            line: before.matches('\n').count() as u32,
+            character: before[line_start..].encode_utf16().count() as u32,
+        }
+    };
+    Range {
+        start: position(span.start),
+        end: position(span.end),
+    }
+}
+
+/// Whether `word` is a Roman numeral skipped by `config`, or an ignore
+/// pattern of `config` matches it or the whitespace delimited token
+/// containing it with surrounding punctuation removed. Words only contain
+/// letters, so this lets patterns match tokens such as `JIRA123` or
+/// `0xff`.
+fn is_ignored(config: &Config, text: &str, word: &Word) -> bool {
+    if !config.check_roman_numerals && is_roman_numeral(word.text) {
+        return true;
+    }
+    if config.check_identifiers && config.keywords.contains(&word.text) {
+        return true;
+    }
+    if config.ignore_patterns.is_empty() {
+        return false;
+    }
+
+    let start = word.text.as_ptr() as usize - text.as_ptr() as usize;
+    let end = start + word.text.len();
+    let token_start = text[..start]
+        .trim_end_matches(|c: char| !c.is_whitespace())
+        .len();
+    let token_end = text.len()
+        - text[end..]
+            .trim_start_matches(|c: char| !c.is_whitespace())
+            .len();
+    let token = text[token_start..token_end]
+        .trim_matches(|c: char| !c.is_alphanumeric());
+
+    config.ignore_patterns.iter().any(|pattern| {
+        pattern.0.is_match(word.text) || pattern.0.is_match(token)
+    })
+}
+
+/// The lexer of the words of `text` checked with `config`.
+pub fn lexer<'a>(text: &'a str, config: &Config) -> Option<Lexer<'a>> {
+    Lexer::with_max_line_length(text, config.max_line_length()).map(|lexer| {
+        lexer
+            .with_syntax(config.syntax.clone())
+            .with_segmentation(config.segment_unspaced_scripts)
+            .with_identifiers(config.check_identifiers)
+            .with_code(config.is_code())
+    })
+}
+
+/// The words of `text` checked with `config`, known or not, leaving out
+/// those masked by its syntax, on lines too long or ignored.
+pub fn checked_words<'a>(text: &'a str, config: &Config) -> Vec<Word<'a>> {
+    let mut words = Vec::new();
+    let Some(mut lexer) = lexer(text, config) else {
+        return words;
+    };
+    while let Some(word) = lexer.next() {
+        if !is_ignored(config, text, word) {
+            words.push(*word);
+        }
+    }
+    words
+}
+
+/// Whether `word` is part of an identifier in `text`, joined to other
+/// words or digits by camelCase or underscores, as in `parseHtml`,
+/// `max_size` or `utf8`.
+pub fn is_identifier(text: &str, word: &Word) -> bool {
+    let is_identifier_char =
+        |c: char| c.is_alphanumeric() || c == '_' || c == '\'';
+    let start = word.text.as_ptr() as usize - text.as_ptr() as usize;
+    let end = start + word.text.len();
+    text[..start].ends_with(is_identifier_char)
+        || text[end..].starts_with(is_identifier_char)
+}
+
+/// Whether `word` is a Roman numeral from 1 to 3999 in its usual form, in
+/// any case, such as `XIV` but not `IIII` or `IC`.
+fn is_roman_numeral(word: &str) -> bool {
+    const NUMERALS: [(&str, u32); 13] = [
+        ("M", 1000),
+        ("CM", 900),
+        ("D", 500),
+        ("CD", 400),
+        ("C", 100),
+        ("XC", 90),
+        ("L", 50),
+        ("XL", 40),
+        ("X", 10),
+        ("IX", 9),
+        ("V", 5),
+        ("IV", 4),
+        ("I", 1),
+    ];
+
+    let word = word.to_ascii_uppercase();
+    let mut value = 0;
+    let mut rest = word.as_str();
+    while let Some((numeral, n)) = NUMERALS
+        .iter()
+        .find(|(numeral, _)| rest.starts_with(numeral))
+    {
+        rest = &rest[numeral.len()..];
+        value += n;
+    }
+    if !rest.is_empty() || !(1..4000).contains(&value) {
+        return false;
+    }
+
+    // Only the usual form writes the value back the same way
+    let mut usual = String::new();
+    for (numeral, n) in NUMERALS {
+        while value >= n {
+            usual.push_str(numeral);
+            value -= n;
+        }
+    }
+    usual == word
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::backend::ZspellBackend;
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    /// Backend accepting a fixed set of words.
+    struct MockBackend(HashSet<String>);
+
+    impl SpellBackend for MockBackend {
+        fn check(&self, word: &str) -> bool {
+            self.0.contains(word)
+        }
+
+        fn suggest(&self, _word: &str) -> Vec<String> {
+            vec!["see".to_string()]
+        }
+
+        fn add_word(&mut self, word: &str) {
+            self.0.insert(word.to_string());
+        }
+    }
+
+    #[test]
+    fn backends() {
+        let mut checker =
+            Checker::new(MockBackend(HashSet::from(["see".into()])), None);
+        checker.add_language(
+            "xx_XX".into(),
+            MockBackend(HashSet::from(["wrld".into()])),
+        );
+        let config = Config::default();
+        let other = Config::from_value(serde_json::json!({
+            "language": "xx_XX",
+        }))
+        .unwrap();
+
+        let words = checker.misspelled_words("see wrld", &config);
+        assert_eq!(
+            words.iter().map(|word| word.text).collect::<Vec<_>>(),
+            ["wrld"]
+        );
+        let words = checker.misspelled_words("see wrld", &other);
+        assert_eq!(
+            words.iter().map(|word| word.text).collect::<Vec<_>>(),
+            ["see"]
+        );
+        assert_eq!(checker.suggest("se", &config), ["see"]);
+
+        checker.add_personal(&config, "wrld").unwrap();
+        assert!(checker.misspelled_words("see wrld", &config).is_empty());
+    }
+
+    #[test]
+    fn casing() {
+        let words = ["hello", "Paris", "NASA"].map(String::from);
+        let checker = Checker::new(MockBackend(HashSet::from(words)), None);
+        let misspelled = |text, config: &Config| {
+            checker
+                .misspelled_words(text, config)
+                .iter()
+                .map(|word| word.text)
+                .collect::<Vec<_>>()
+        };
+        let text = "Hello HELLO paris PARIS nasa NASA hElLo";
+
+        let mut config = Config::default();
+        assert_eq!(misspelled(text, &config), ["paris", "nasa", "hElLo"]);
+        config.lowercase_proper_nouns = true;
+        assert_eq!(misspelled(text, &config), ["nasa", "hElLo"]);
+        config.strict_title_case = true;
+        config.strict_all_caps = true;
+        assert_eq!(
+            misspelled(text, &config),
+            ["Hello", "HELLO", "PARIS", "nasa", "hElLo"]
+        );
+    }
+
+    #[test]
+    fn strict_title_case() {
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\n")
+            .dict_str("3\nhello\nmay\nMay\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let config = Config {
+            strict_title_case: true,
+            ..Default::default()
+        };
+
+        let misspelled = checker.misspelled_words("Hello May may", &config);
+        let misspelled =
+            misspelled.iter().map(|word| word.text).collect::<Vec<_>>();
+        assert_eq!(misspelled, ["Hello"]);
+    }
+
+    #[test]
+    fn dictionary_language() {
+        let checker = |language: Option<&str>| {
+            let dict = zspell::builder()
+                .config_str("SET UTF-8\n")
+                .dict_str("1\nılık\n")
+                .build()
+                .unwrap();
+            let backend = ZspellBackend::new(dict)
+                .with_language(language.map(String::from));
+            Checker::new(backend, None)
+        };
+        let config = Config::default();
+
+        // Lowercased with the dotless i of Turkish
+        let turkish = checker(Some("tr_TR"));
+        assert!(turkish.misspelled_words("ILIK", &config).is_empty());
+        let unknown = checker(None);
+        assert_eq!(unknown.misspelled_words("ILIK", &config).len(), 1);
+    }
+
+    #[test]
+    fn forbidden() {
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\nFORBIDDENWORD !\n")
+            .dict_str("2\nregardless\nirregardless/!\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let config = Config::default();
+        let text = "irregardless regardles";
+        let sections = checker.sections(text, &config);
+
+        let forbidden = checker
+            .misspelled_words(text, &config)
+            .iter()
+            .map(|word| checker.is_forbidden(word, &sections, &config))
+            .collect::<Vec<_>>();
+        assert_eq!(forbidden, [true, false]);
+    }
+
+    #[test]
+    fn layers() {
+        let team = std::env::temp_dir()
+            .join(format!("zspell_lsp_layers_{}.txt", std::process::id()));
+        std::fs::write(&team, "teh\n").unwrap();
+        let mut checker = Checker::new(MockBackend(HashSet::new()), None);
+        let personal = Config::default();
+        let config = Config {
+            team_dictionary: Some(team.clone()),
+            ..Default::default()
+        };
+
+        checker.load_wordlists(&config, None).unwrap();
+        checker
+            .add_word(&config, Layer::Team, "wrld", "", &[])
+            .unwrap();
+        checker.add_personal(&config, "see").unwrap();
+        let words = std::fs::read_to_string(&team).unwrap();
+        std::fs::remove_file(&team).unwrap();
+
+        let misspelled = |config| {
+            checker
+                .misspelled_words("teh", config)
+                .iter()
+                .map(|word| word.text)
+                .collect::<Vec<_>>()
+        };
+        assert!(misspelled(&config).is_empty());
+        assert_eq!(misspelled(&personal), ["teh"]);
+        assert_eq!(words, "teh\nwrld\n");
+        assert_eq!(checker.wordlist_len(&config, Layer::Team), Some(2));
+        assert_eq!(checker.wordlist_len(&config, Layer::Personal), Some(1));
+        assert_eq!(checker.wordlist_len(&personal, Layer::Team), None);
+        assert!(checker
+            .add_word(&personal, Layer::Team, "wrld", "", &[])
+            .is_err());
+    }
+
+    #[test]
+    fn detection() {
+        let words = |words: &[&str]| {
+            MockBackend(words.iter().map(|word| word.to_string()).collect())
+        };
+        let mut checker =
+            Checker::new(words(&["the", "world"]), Some("en_US".into()));
+        checker.add_language("de_DE".into(), words(&["die", "welt"]));
+        checker.add_language("sv_SE".into(), words(&["världen", "die"]));
+        let candidates = ["sv_SE", "de_DE", "en_US", "fr_FR"]
+            .map(String::from)
+            .to_vec();
+
+        let detect = |text| checker.detect_language(text, &candidates);
+        assert_eq!(detect("die welt, the"), Some("de_DE"));
+        assert_eq!(detect("the world"), Some("en_US"));
+        // Both know one of the words, the first candidate wins
+        assert_eq!(detect("die"), Some("sv_SE"));
+        assert_eq!(detect("die le monde"), None);
+        assert_eq!(detect(""), None);
+    }
+
+    #[test]
+    fn paragraphs() {
+        let words = |words: &[&str]| {
+            MockBackend(words.iter().map(|word| word.to_string()).collect())
+        };
+        let mut checker =
+            Checker::new(words(&["the", "world"]), Some("en_US".into()));
+        checker.add_language("de_DE".into(), words(&["die", "welt"]));
+        let mut config = Config {
+            detect_languages: vec!["de_DE".into()],
+            ..Default::default()
+        };
+        let text = "die welt\nthe welt\n\nthe world die";
+        let misspelled = |config: &Config| {
+            checker
+                .misspelled_words(text, config)
+                .iter()
+                .map(|word| word.text)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(misspelled(&config), ["die", "welt", "welt", "die"]);
+        config.detect_languages_in = DetectIn::Paragraph;
+        assert_eq!(
+            checker.sections(text, &config).language_at(1),
+            Some("de_DE")
+        );
+        assert_eq!(checker.sections(text, &config).language_at(3), None);
+        assert_eq!(misspelled(&config), ["the", "die"]);
+        config.detect_languages_in = DetectIn::Line;
+        assert_eq!(misspelled(&config), ["the", "die"]);
+        // Ties go to the first candidate
+        config.detect_languages.insert(0, "en_US".into());
+        assert_eq!(misspelled(&config), ["welt", "die"]);
+    }
+
+    /// Backend reporting everything through `check_text`, like engines
+    /// checking whole paragraphs.
+    struct TextBackend;
+
+    impl SpellBackend for TextBackend {
+        fn check(&self, _word: &str) -> bool {
+            true
+        }
+
+        fn suggest(&self, _word: &str) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn add_word(&mut self, _word: &str) {}
+
+        fn checks_text(&self) -> bool {
+            true
+        }
+
+        fn check_text(&self, text: &str) -> Vec<Issue> {
+            let issue = |pattern: &str, rule: Option<&str>| {
+                let start = text.find(pattern).unwrap();
+                Issue {
+                    span: start..start + pattern.len(),
+                    rule: rule.map(String::from),
+                    message: String::new(),
+                    replacements: Vec::new(),
+                }
+            };
+            vec![issue("a apple", Some("A_AN")), issue("teh", None)]
+        }
+    }
+
+    #[test]
+    fn text_issues() {
+        let checker = Checker::new(TextBackend, None);
+        let config = Config::default();
+        // The URL isn't checked
+        let text = "see https://teh.io\nÜnï teh a apple";
+
+        let words = checker.misspelled_words(text, &config);
+        assert_eq!(words.len(), 1);
+        assert_eq!(words[0].text, "teh");
+        assert_eq!(words[0].range.start, Position::new(1, 4));
+        assert_eq!(words[0].range.end, Position::new(1, 7));
+
+        let issues = checker.issues(text, &config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].1.rule.as_deref(), Some("A_AN"));
+        assert_eq!(issues[0].0.start, Position::new(1, 8));
+    }
+
+    #[test]
+    fn ignore_patterns() {
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\n")
+            .dict_str("1\nsee\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let config = Config::from_value(serde_json::json!({
+            "ignorePatterns": [r"^[A-Z]{2,5}\d+$", r"^0x[0-9a-f]+$"],
+        }))
+        .unwrap();
+
+        let words =
+            checker.misspelled_words("see JIRA123, 0xfab and JIRA", &config);
+        let words = words.iter().map(|word| word.text).collect::<Vec<_>>();
+
+        assert_eq!(words, ["and", "JIRA"]);
+    }
+
+    #[test]
+    fn morphology() {
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\nSFX X Y 1\nSFX X 0 able . ds:able\n")
+            .dict_str("2\ndrink/X po:verb\nwater\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let config = Config::default();
+        let sections = checker.sections("", &config);
+
+        let stems = |word| checker.stems_at(word, 0, &sections, &config);
+        assert_eq!(stems("Drinkable"), ["drink"]);
+        assert!(stems("drink").is_empty());
+        assert!(stems("water").is_empty());
+        assert!(stems("drinkible").is_empty());
+
+        let analyze = |word| checker.analyze_at(word, 0, &sections, &config);
+        assert_eq!(analyze("drinkable"), ["ds:able", "po:verb"]);
+        assert_eq!(analyze("drink"), ["po:verb"]);
+        assert!(analyze("water").is_empty());
+    }
+
+    #[test]
+    fn roman_numerals() {
+        for numeral in ["I", "II", "xiv", "MCMXCIV", "Xl", "MMMCMXCIX"] {
+            assert!(is_roman_numeral(numeral), "{}", numeral);
+        }
+        for word in ["", "IIII", "IC", "VX", "MMMM", "XIVe", "civil"] {
+            assert!(!is_roman_numeral(word), "{}", word);
+        }
+
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\n")
+            .dict_str("1\nchapter\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let text = "chapter XIV and xiv";
+        let words = checker.misspelled_words(text, &Config::default());
+        let words = words.iter().map(|word| word.text).collect::<Vec<_>>();
+        assert_eq!(words, ["and"]);
+
+        let config = Config {
+            check_roman_numerals: true,
+            ..Default::default()
+        };
+        let words = checker.misspelled_words(text, &config);
+        let words = words.iter().map(|word| word.text).collect::<Vec<_>>();
+        assert_eq!(words, ["XIV", "and", "xiv"]);
+    }
+
+    #[test]
+    fn identifiers() {
+        let dict = zspell::builder()
+            .config_str("SET UTF-8\n")
+            .dict_str("4\nparse\nhtml\nsize\nmax\n")
+            .build()
+            .unwrap();
+        let checker = Checker::new(ZspellBackend::new(dict), None);
+        let text = "fn parseHtml(max_sise: usize) -> HTMLParsr";
+        let misspelled = |config: &Config| {
+            let words = checker.misspelled_words(text, config);
+            words
+                .iter()
+                .map(|word| (word.text, is_identifier(text, word)))
+                .collect::<Vec<_>>()
+        };
+
+        let config = Config::default();
+        assert_eq!(
+            misspelled(&config),
+            [
+                ("fn", false),
+                ("parseHtml", false),
+                ("sise", true),
+                ("usize", false),
+                ("HTMLParsr", false)
+            ]
+        );
+
+        let config = Config {
+            check_identifiers: true,
+            ..Config::default().for_path(Path::new("lib.rs"))
+        };
+        assert_eq!(misspelled(&config), [("sise", true), ("Parsr", true)]);
+    }
+}
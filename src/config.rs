@@ -0,0 +1,598 @@
+use crate::keywords;
+use crate::syntax::{Column, Syntax};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use wildmatch::WildMatch;
+
+/// Name of the project configuration file, looked up in the project root.
+pub const PROJECT_FILE: &str = ".zspell.toml";
+
+/// Affix file used when neither `affix` nor `language` is set.
+pub const DEFAULT_AFFIX: &str = "./index.aff";
+/// Dictionary file used when neither `dictionary` nor `language` is set.
+pub const DEFAULT_DICTIONARY: &str = "./index.dic";
+
+/// Length in bytes of the longest lines checked unless set otherwise.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 10_000;
+
+/// Size in bytes of the largest files checked unless set otherwise.
+pub const DEFAULT_MAX_FILE_SIZE: usize = 10 << 20;
+
+/// When documents are checked and diagnostics published.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckOn {
+    /// Check on open and on every change.
+    #[default]
+    Change,
+    /// Check on open and whenever the document is saved.
+    Save,
+    /// Only check when the `zspell.checkDocument` command is executed.
+    Manual,
+}
+
+/// Parts of documents whose language is detected separately.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectIn {
+    /// One language for the whole document.
+    #[default]
+    Document,
+    /// Paragraphs separated by blank lines.
+    Paragraph,
+    /// Every line, as in translation files.
+    Line,
+}
+
+/// The wordlists words can be added to.
+#[derive(
+    Serialize,
+    Deserialize,
+    ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// The user's own wordlist, `personalDictionary`.
+    #[default]
+    Personal,
+    /// The wordlist shared with the project, `teamDictionary`.
+    Team,
+}
+
+/// How misspellings are presented to the client.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Presentation {
+    /// Publish a diagnostic per misspelling.
+    #[default]
+    Diagnostics,
+    /// Mark misspellings through `textDocument/semanticTokens` only.
+    SemanticTokens,
+}
+
+/// Which occurrences of a misspelled word are reported.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportDuplicates {
+    /// Every occurrence.
+    #[default]
+    All,
+    /// The first occurrence, with the others as related information.
+    First,
+    /// The first occurrence, with the number of occurrences in its
+    /// message.
+    Count,
+    /// The first occurrence of the word in any case, with the word and
+    /// the number of occurrences in its message and the others as related
+    /// information, for diagnostics panels. Unlike `Count`, words differing
+    /// in case are grouped, and single occurrences are counted too.
+    Summary,
+}
+
+/// Severity of diagnostics, as in LSP.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// Spell checking engine of a language.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// zspell with the hunspell dictionary of the language.
+    #[default]
+    Zspell,
+    /// The system hunspell library with the hunspell dictionary of the
+    /// language.
+    Hunspell,
+    /// An `aspell -a` process with aspell's dictionary of the language.
+    Aspell,
+    /// A LanguageTool server, which also reports grammar issues.
+    LanguageTool,
+}
+
+impl Engine {
+    /// Whether the engine loads the affix and dictionary file of the
+    /// language.
+    pub fn uses_files(self) -> bool {
+        matches!(self, Self::Zspell | Self::Hunspell)
+    }
+}
+
+/// A compiled regular expression, written as its source in settings.
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Regex::new(&source)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Settings that apply to files matching any of `paths`, layered on top of
+/// the rest of the configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Override {
+    pub paths: Vec<String>,
+    #[serde(flatten)]
+    pub settings: serde_json::Map<String, Value>,
+}
+
+/// Effective settings. These are layered from the global configuration
+/// file, the project's `.zspell.toml`, command line options, and finally
+/// settings received from the client through `initializationOptions` or
+/// `workspace/didChangeConfiguration`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Locale of the dictionary to load from the search paths.
+    pub language: Option<String>,
+    /// Affix file, used unless `language` is set.
+    pub affix: Option<PathBuf>,
+    /// Dictionary file, used unless `language` is set.
+    pub dictionary: Option<PathBuf>,
+    /// Additional directories to search for dictionaries.
+    pub dictionary_paths: Vec<PathBuf>,
+    /// MyThes thesaurus data file for the synonyms shown on hover, with
+    /// its `.idx` file next to it. Searched for with the dictionaries if
+    /// unset.
+    pub thesaurus: Option<PathBuf>,
+    /// Languages to choose from by the words of each document, replacing
+    /// the configured language with the one knowing the most. Languages
+    /// aren't detected if empty.
+    pub detect_languages: Vec<String>,
+    /// Whether `detectLanguages` selects the language of whole documents
+    /// or of each of their paragraphs or lines.
+    pub detect_languages_in: DetectIn,
+    /// Language of documents by their LSP `languageId`, taking precedence
+    /// over `language` but not over overrides.
+    pub language_ids: BTreeMap<String, String>,
+    /// Locales tried in order when the dictionary of a language isn't
+    /// installed. Languages not listed fall back to their base language,
+    /// then to any other variant of it.
+    pub language_fallbacks: BTreeMap<String, Vec<String>>,
+    /// Engine checking each language, zspell for languages not listed.
+    pub backends: BTreeMap<String, Engine>,
+    /// The aspell executable, `aspell` from `PATH` if unset.
+    pub aspell_command: Option<PathBuf>,
+    /// Address of the LanguageTool server, `http://localhost:8081` if
+    /// unset.
+    pub language_tool_url: Option<String>,
+    /// Glob patterns selecting the files to check. All files are checked
+    /// if empty.
+    pub include: Vec<String>,
+    /// Glob patterns of files never to check.
+    pub exclude: Vec<String>,
+    /// Regular expressions matching tokens that are never reported.
+    pub ignore_patterns: Vec<Pattern>,
+    /// Columns of CSV and TSV files checked, by number or header name. All
+    /// are checked if empty.
+    pub checked_columns: Vec<Column>,
+    /// Accept proper nouns written in lowercase, `paris` for `Paris`.
+    pub lowercase_proper_nouns: bool,
+    /// Reject common words written in Title Case, `Hello` for `hello`.
+    pub strict_title_case: bool,
+    /// Reject words written in ALL CAPS unless the dictionary has them so,
+    /// `HELLO` for `hello`.
+    pub strict_all_caps: bool,
+    /// Check Roman numerals such as `XIV`, which are skipped otherwise.
+    pub check_roman_numerals: bool,
+    /// Check the words of identifiers in code, split at camelCase and
+    /// snake_case boundaries, and report their misspellings as hints.
+    /// Keywords of the language of the document are skipped.
+    pub check_identifiers: bool,
+    /// Split text in scripts written without spaces, such as Chinese and
+    /// Japanese, into words and check them, rather than skipping it. Only
+    /// when built with the `segmentation` feature.
+    pub segment_unspaced_scripts: bool,
+    /// Only report problems on lines added or changed since the last
+    /// commit of the file's git repository.
+    pub only_changed_lines: bool,
+    /// Lines longer than this many bytes, such as those of minified files,
+    /// aren't checked. `DEFAULT_MAX_LINE_LENGTH` if unset.
+    pub max_line_length: Option<usize>,
+    /// Files larger than this many bytes aren't checked at all.
+    /// `DEFAULT_MAX_FILE_SIZE` if unset.
+    pub max_file_size: Option<usize>,
+    /// Check files that look minified or generated. Binary files are never
+    /// checked.
+    pub check_generated_files: bool,
+    pub check_on: CheckOn,
+    pub presentation: Presentation,
+    pub report_duplicates: ReportDuplicates,
+    /// Severity of words the dictionary forbids, such as `irregardless`.
+    pub forbidden_severity: Severity,
+    /// Personal table of known typos and their corrections.
+    pub substitutions: BTreeMap<String, String>,
+    /// Apply `substitutions` to the document before it is saved.
+    pub autocorrect_on_save: bool,
+    /// Wordlist file that words added from the editor are appended to.
+    pub personal_dictionary: Option<PathBuf>,
+    /// Wordlist file shared with the project, usually committed to its
+    /// repository. Its words are accepted along with the personal ones.
+    pub team_dictionary: Option<PathBuf>,
+    /// Personal dictionaries of hunspell or LibreOffice whose words are
+    /// accepted as well. Their default locations are searched if unset.
+    pub import_dictionaries: Option<Vec<PathBuf>>,
+    /// Settings for files matching glob patterns, in increasing order of
+    /// precedence.
+    pub overrides: Vec<Override>,
+    /// Syntax of the document the settings are for, from its path or
+    /// language id rather than set.
+    #[serde(skip)]
+    pub syntax: Syntax,
+    /// Keywords of the programming language of the document the settings
+    /// are for, from its path or language id rather than set.
+    #[serde(skip)]
+    pub keywords: &'static [&'static str],
+}
+
+impl Config {
+    /// Parse settings sent by the client. Settings may be nested under a
+    /// `zspell` key, as sent by clients that forward their whole settings
+    /// tree.
+    pub fn from_value(value: Value) -> serde_json::Result<Self> {
+        let config: Self = match client_settings(value) {
+            Value::Null => return Ok(Self::default()),
+            value => serde_json::from_value(value)?,
+        };
+        // Reject invalid overrides up front rather than per document
+        for entry in &config.overrides {
+            serde_json::from_value::<Self>(Value::Object(
+                entry.settings.clone(),
+            ))?;
+        }
+        Ok(config)
+    }
+
+    /// Parse client settings layered on top of `base`.
+    pub fn from_layers(
+        base: &Value,
+        client: Value,
+    ) -> serde_json::Result<Self> {
+        let mut value = base.clone();
+        merge(&mut value, client_settings(client));
+        Self::from_value(value)
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length.unwrap_or(DEFAULT_MAX_LINE_LENGTH)
+    }
+
+    pub fn max_file_size(&self) -> usize {
+        self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE)
+    }
+
+    /// The engine checking `language`.
+    pub fn engine(&self, language: &str) -> Engine {
+        self.backends.get(language).copied().unwrap_or_default()
+    }
+
+    /// Whether `path` is selected by the `include` and `exclude` patterns.
+    pub fn is_included(&self, path: &Path) -> bool {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+
+        let path = match_path(path);
+        (self.include.is_empty() || matches_any(&self.include, &path))
+            && !matches_any(&self.exclude, &path)
+    }
+
+    /// Whether the document the settings are for is source code, in a
+    /// programming language whose keywords are known.
+    pub fn is_code(&self) -> bool {
+        !self.keywords.is_empty()
+    }
+
+    /// The settings for a document at `path` with the LSP `language_id`:
+    /// its language selected by `languageIds`, then every matching
+    /// override applied.
+    pub fn for_document(&self, path: &Path, language_id: &str) -> Config {
+        let mut config = match self.language_ids.get(language_id) {
+            Some(language) => {
+                let mut config = self.clone();
+                config.language = Some(language.clone());
+                config.for_path(path)
+            }
+            None => self.for_path(path),
+        };
+        config.syntax = Syntax::of(path, Some(language_id))
+            .with_columns(&config.checked_columns);
+        config.keywords = keywords::of(path, Some(language_id));
+        config
+    }
+
+    /// The settings for `path`, with every matching override applied.
+    pub fn for_path(&self, path: &Path) -> Config {
+        let mut config = self.overridden(path);
+        config.syntax =
+            Syntax::of(path, None).with_columns(&config.checked_columns);
+        config.keywords = keywords::of(path, None);
+        config
+    }
+
+    fn overridden(&self, path: &Path) -> Config {
+        let path = match_path(path);
+        let mut overrides = self
+            .overrides
+            .iter()
+            .filter(|entry| matches_any(&entry.paths, &path))
+            .peekable();
+        if overrides.peek().is_none() {
+            return self.clone();
+        }
+
+        let mut value = match serde_json::to_value(self) {
+            Ok(value) => value,
+            Err(_) => return self.clone(),
+        };
+        for entry in overrides {
+            merge(&mut value, Value::Object(entry.settings.clone()));
+        }
+        serde_json::from_value(value).unwrap_or_else(|err| {
+            warn!("ignoring invalid overrides for {}: {}", path, err);
+            self.clone()
+        })
+    }
+}
+
+/// `path` as matched against glob patterns: relative to the current
+/// directory if below it, with `/` separators.
+fn match_path(path: &Path) -> String {
+    let path = match std::env::current_dir() {
+        Ok(cwd) => {
+            let path = cwd.join(path);
+            match path.strip_prefix(&cwd) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => path,
+            }
+        }
+        Err(_) => path.to_path_buf(),
+    };
+    path.to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+fn matches_any(patterns: &[String], path: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| WildMatch::new(pattern).matches(path))
+}
+
+/// Unwrap settings nested under a `zspell` key.
+fn client_settings(value: Value) -> Value {
+    match value {
+        Value::Object(mut map) if map.contains_key("zspell") => {
+            map.remove("zspell").unwrap()
+        }
+        value => value,
+    }
+}
+
+/// Format of the `dump-config` output.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DumpFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+/// Render the effective configuration.
+pub fn dump(config: &Config, format: DumpFormat) -> Result<String> {
+    Ok(match format {
+        DumpFormat::Toml => toml::to_string(config)?,
+        DumpFormat::Json => serde_json::to_string_pretty(config)? + "\n",
+    })
+}
+
+/// Recursively merge `overlay` into `base`. Objects are merged key by key,
+/// any other value in `overlay` replaces the one in `base`.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (_, Value::Null) => {}
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Location of the global configuration file,
+/// `$XDG_CONFIG_HOME/zspell_lsp/config.toml` or
+/// `~/.config/zspell_lsp/config.toml`.
+pub fn global_file() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_home.join("zspell_lsp").join("config.toml"))
+}
+
+/// Read a TOML configuration file, returning `Null` if it doesn't exist.
+pub fn load_file(path: &Path) -> Result<Value> {
+    if !path.is_file() {
+        return Ok(Value::Null);
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read {}", path.display()))?;
+    toml::from_str(&text)
+        .with_context(|| format!("invalid configuration in {}", path.display()))
+}
+
+/// The global configuration file layered with the project configuration
+/// file of `root`.
+pub fn load_files(root: &Path) -> Result<Value> {
+    let mut value = Value::Null;
+    if let Some(global) = global_file() {
+        merge(&mut value, load_file(&global)?);
+    }
+    merge(&mut value, load_file(&root.join(PROJECT_FILE))?);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn from_value() {
+        let config = Config::from_value(json!({"checkOn": "save"})).unwrap();
+        assert_eq!(config.check_on, CheckOn::Save);
+
+        let config =
+            Config::from_value(json!({"zspell": {"checkOn": "manual"}}))
+                .unwrap();
+        assert_eq!(config.check_on, CheckOn::Manual);
+
+        let config = Config::from_value(serde_json::Value::Null).unwrap();
+        assert_eq!(config.check_on, CheckOn::Change);
+    }
+
+    #[test]
+    fn layers() {
+        let mut base = json!({
+            "checkOn": "save",
+            "substitutions": {"teh": "the"},
+        });
+        merge(&mut base, json!({"substitutions": {"recieve": "receive"}}));
+
+        let config = Config::from_layers(
+            &base,
+            json!({"zspell": {"presentation": "semanticTokens"}}),
+        )
+        .unwrap();
+
+        assert_eq!(config.check_on, CheckOn::Save);
+        assert_eq!(config.presentation, Presentation::SemanticTokens);
+        assert_eq!(config.substitutions.len(), 2);
+    }
+
+    #[test]
+    fn include_exclude() {
+        let config = Config::from_value(json!({
+            "include": ["docs/**", "*.md"],
+            "exclude": ["vendor/**"],
+        }))
+        .unwrap();
+
+        assert!(config.is_included(Path::new("docs/guide.txt")));
+        assert!(config.is_included(Path::new("./README.md")));
+        assert!(config.is_included(Path::new("src/notes.md")));
+        assert!(!config.is_included(Path::new("vendor/lib/README.md")));
+        assert!(!config.is_included(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn overrides() {
+        let config = Config::from_value(json!({
+            "language": "en_US",
+            "checkOn": "save",
+            "overrides": [
+                {"paths": ["docs/de/**"], "language": "de_DE"},
+                {"paths": ["docs/**"], "checkOn": "manual"},
+            ],
+        }))
+        .unwrap();
+
+        let german = config.for_path(Path::new("docs/de/index.md"));
+        assert_eq!(german.language.as_deref(), Some("de_DE"));
+        assert_eq!(german.check_on, CheckOn::Manual);
+
+        let other = config.for_path(Path::new("README.md"));
+        assert_eq!(other.language.as_deref(), Some("en_US"));
+        assert_eq!(other.check_on, CheckOn::Save);
+
+        assert!(Config::from_value(json!({
+            "overrides": [{"paths": ["*"], "checkOn": "never"}],
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn language_ids() {
+        let config = Config::from_value(json!({
+            "language": "en_US",
+            "languageIds": {"latex": "de_DE", "markdown": "sv_SE"},
+            "overrides": [{"paths": ["docs/**"], "language": "fr_FR"}],
+        }))
+        .unwrap();
+
+        let language = |path: &str, language_id: &str| {
+            config.for_document(Path::new(path), language_id).language
+        };
+        assert_eq!(language("a.tex", "latex").as_deref(), Some("de_DE"));
+        assert_eq!(language("a.txt", "plaintext").as_deref(), Some("en_US"));
+        assert_eq!(language("docs/a.md", "markdown").as_deref(), Some("fr_FR"));
+    }
+}
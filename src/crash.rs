@@ -0,0 +1,63 @@
+use crate::logger;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Write the files of a bug report for the fatal `error` to a new
+/// directory in `parent`, and return its path. `state` describes the
+/// server, such as its configuration and dictionaries.
+pub fn write_bundle(
+    parent: &Path,
+    error: &anyhow::Error,
+    state: &Value,
+) -> Result<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let directory = parent.join(format!(
+        "zspell_lsp-crash-{}-{}",
+        time,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&directory)
+        .with_context(|| format!("unable to create {}", directory.display()))?;
+
+    let mut log = logger::recent().join("\n");
+    log.push('\n');
+    let files = [
+        ("error.txt", format!("{:?}\n", error)),
+        ("log.txt", log),
+        ("state.json", serde_json::to_string_pretty(state)?),
+    ];
+    for (name, contents) in files {
+        let path = directory.join(name);
+        std::fs::write(&path, contents)
+            .with_context(|| format!("unable to write {}", path.display()))?;
+    }
+    Ok(directory)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn bundle() {
+        let parent = std::env::temp_dir()
+            .join(format!("zspell_lsp_crash_test_{}", std::process::id()));
+        let error = anyhow!("disconnected").context("unable to send");
+        let state = serde_json::json!({ "lastMethod": "textDocument/didOpen" });
+
+        let directory = write_bundle(&parent, &error, &state).unwrap();
+        let read =
+            |name| std::fs::read_to_string(directory.join(name)).unwrap();
+        assert!(read("error.txt").contains("disconnected"));
+        assert!(read("state.json").contains("textDocument/didOpen"));
+        assert!(directory.join("log.txt").is_file());
+        std::fs::remove_dir_all(parent).unwrap();
+    }
+}
@@ -0,0 +1,859 @@
+use crate::affixes::Affixes;
+use crate::backend::{self, SpellBackend, ZspellBackend};
+use crate::checker::Checker;
+use crate::config::{Config, Engine, DEFAULT_AFFIX, DEFAULT_DICTIONARY};
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use memmap2::Mmap;
+use serde_json::Value;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use zspell::Dictionary;
+
+/// An affix and dictionary file pair found in a search path.
+#[derive(Debug, Clone)]
+pub struct DictionaryFiles {
+    pub locale: String,
+    pub affix: PathBuf,
+    pub dictionary: PathBuf,
+}
+
+impl DictionaryFiles {
+    /// The character encoding declared by the `SET` directive of the affix
+    /// file. Hunspell defaults to ISO8859-1 when none is given.
+    pub fn encoding(&self) -> String {
+        let Ok(affix) = std::fs::read(&self.affix) else {
+            return "unknown".to_string();
+        };
+        String::from_utf8_lossy(&affix)
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("SET "))
+            .map(|encoding| encoding.trim().to_string())
+            .unwrap_or_else(|| "ISO8859-1".to_string())
+    }
+
+    /// The number of entries in the dictionary file, not counting the
+    /// leading line with the approximate count.
+    pub fn entries(&self) -> usize {
+        let Ok(dictionary) = std::fs::read(&self.dictionary) else {
+            return 0;
+        };
+        String::from_utf8_lossy(&dictionary)
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .count()
+    }
+}
+
+/// Directories searched for dictionaries: `extra` first, then `$DICPATH`,
+/// then the usual system and user locations.
+pub fn search_paths(extra: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = extra.to_vec();
+
+    if let Some(dicpath) = std::env::var_os("DICPATH") {
+        paths.extend(std::env::split_paths(&dicpath));
+    }
+
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        paths.push(home.join(".local/share/hunspell"));
+        paths.push(home.join("Library/Spelling"));
+    }
+
+    paths.extend(
+        [
+            "/usr/share/hunspell",
+            "/usr/share/myspell",
+            "/usr/share/myspell/dicts",
+            "/usr/share/mythes",
+            "/usr/local/share/hunspell",
+            "/Library/Spelling",
+        ]
+        .map(PathBuf::from),
+    );
+
+    paths
+}
+
+/// Build a dictionary from an affix and dictionary file, or from the
+/// compiled copy in the cache directory if they were built before. The files
+/// are mapped into memory rather than read.
+pub fn load(affix: &Path, dictionary: &Path) -> Result<Dictionary> {
+    load_cached(affix, dictionary, cache_dir().as_deref())
+}
+
+/// Directory compiled dictionaries are kept in,
+/// `$XDG_CACHE_HOME/zspell_lsp` or `~/.cache/zspell_lsp`. Tests use a
+/// temporary directory instead.
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    if cfg!(test) {
+        return Some(
+            std::env::temp_dir()
+                .join(format!("zspell_lsp_cache_{}", std::process::id())),
+        );
+    }
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".cache"))
+        })?;
+    Some(cache_home.join("zspell_lsp"))
+}
+
+fn load_cached(
+    affix: &Path,
+    dictionary: &Path,
+    cache_dir: Option<&Path>,
+) -> Result<Dictionary> {
+    let affix_map = map(affix).with_context(|| {
+        format!("Unable to open affix file {}", affix.display())
+    })?;
+    let dict_map = map(dictionary).with_context(|| {
+        format!("Unable to open dictionary file {}", dictionary.display())
+    })?;
+    let affix_str = std::str::from_utf8(&affix_map).with_context(|| {
+        format!("Affix file {} is not valid UTF-8", affix.display())
+    })?;
+    let dict_str = std::str::from_utf8(&dict_map).with_context(|| {
+        format!(
+            "Dictionary file {} is not valid UTF-8",
+            dictionary.display()
+        )
+    })?;
+    let cached =
+        cache_dir.map(|dir| dir.join(compiled_name(affix_str, dict_str)));
+
+    if let Some(cached) = &cached {
+        match map(cached) {
+            Ok(compiled) => match std::str::from_utf8(&compiled)
+                .map_err(anyhow::Error::from)
+                .and_then(|compiled| {
+                    build(&compiled_affix(affix_str), compiled)
+                }) {
+                Ok(dict) => return Ok(dict),
+                Err(err) => warn!(
+                    "Ignoring compiled dictionary {}: {:#}",
+                    cached.display(),
+                    err
+                ),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!(
+                "Unable to read compiled dictionary {}: {}",
+                cached.display(),
+                err
+            ),
+        }
+    }
+
+    let dict = build(affix_str, dict_str)?;
+
+    if let Some(cached) = &cached {
+        if let Err(err) = write_compiled(&dict, cached) {
+            warn!(
+                "Unable to write compiled dictionary {}: {:#}",
+                cached.display(),
+                err
+            );
+        }
+    }
+    Ok(dict)
+}
+
+/// A small English dictionary built into the binary, used when no
+/// dictionary files are found.
+#[cfg(any(test, feature = "embedded-dictionary"))]
+fn embedded() -> Result<Dictionary> {
+    build(
+        include_str!("../dictionaries/en_US.aff"),
+        include_str!("../dictionaries/en_US.dic"),
+    )
+}
+
+/// Whether the default dictionary of `config` is one of zspell or hunspell
+/// that can't be found, and English, or not selected by language, so that
+/// the embedded one can take its place.
+#[cfg(feature = "embedded-dictionary")]
+fn missing(config: &Config, paths: &[PathBuf]) -> bool {
+    if let Some(language) = &config.language {
+        let english = language.to_lowercase().starts_with("en");
+        if !english || !config.engine(language).uses_files() {
+            return false;
+        }
+    }
+    match select(config, paths) {
+        Ok((affix, dictionary)) => !affix.is_file() || !dictionary.is_file(),
+        Err(_) => true,
+    }
+}
+
+/// Map the file at `path` into memory, so that large dictionaries are
+/// parsed in place instead of being copied into a string first.
+fn map(path: &Path) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the map only lives while the dictionary is built. Dictionary
+    // files aren't written to while they're in use, and a file truncated
+    // meanwhile would end the process with SIGBUS rather than be misread.
+    unsafe { Mmap::map(&file) }
+}
+
+fn build(affix: &str, dictionary: &str) -> Result<Dictionary> {
+    zspell::builder()
+        .config_str(affix)
+        .dict_str(dictionary)
+        .build()
+        .context("Unable to create dictionary")
+}
+
+/// Affix file of compiled dictionaries, which list every form of every word
+/// and only need flags for the words that are forbidden or not suggested.
+const COMPILED_AFFIX: &str = "SET UTF-8\nFORBIDDENWORD F\nNOSUGGEST N\n";
+
+/// Directives of affix files kept in the affix of their compiled
+/// dictionary, as suggestions and the language depend on them.
+const KEPT_DIRECTIVES: [&str; 4] = ["TRY", "REP", "KEY", "LANG"];
+
+/// [`COMPILED_AFFIX`] with the [`KEPT_DIRECTIVES`] of `affix`.
+fn compiled_affix(affix: &str) -> String {
+    let mut compiled = COMPILED_AFFIX.to_string();
+    for line in affix.lines() {
+        let directive = line.split_whitespace().next().unwrap_or_default();
+        if KEPT_DIRECTIVES.contains(&directive) {
+            compiled += line;
+            compiled.push('\n');
+        }
+    }
+    compiled
+}
+
+/// Version of the format of compiled dictionaries, changed so that those
+/// written by earlier versions are compiled again.
+const COMPILED_FORMAT: u32 = 2;
+
+/// File name of the compiled dictionary of an affix and dictionary file,
+/// unique to their contents and the version of the server.
+fn compiled_name(affix: &str, dictionary: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    COMPILED_FORMAT.hash(&mut hasher);
+    affix.hash(&mut hasher);
+    dictionary.hash(&mut hasher);
+    format!("{:016x}.dic", hasher.finish())
+}
+
+/// Write the words of `dict` as a dictionary file for [`compiled_affix`],
+/// saving the expansion of the affix rules on later loads. The stems of
+/// each form are kept as `st:` fields, after its morphological information.
+fn write_compiled(dict: &Dictionary, path: &Path) -> Result<()> {
+    let lists = [
+        (dict.wordlist(), ""),
+        (dict.wordlist_nosuggest(), "/N"),
+        (dict.wordlist_forbidden(), "/F"),
+    ];
+    let entries: usize = lists.iter().map(|(list, _)| list.inner().len()).sum();
+    let mut compiled = format!("{}\n", entries);
+    for (list, flags) in lists {
+        for word in list.inner().keys() {
+            // Characters with a meaning in dictionary files can't be written
+            // without changing the word
+            if word
+                .contains(|c: char| c == '/' || c == '#' || c.is_whitespace())
+            {
+                return Err(anyhow!("{:?} can't be written", word));
+            }
+            compiled += word;
+            compiled += flags;
+            let entry = dict.entry(word);
+            for morph in entry.analyze().into_iter().flatten() {
+                compiled.push(' ');
+                compiled += &morph.to_string();
+            }
+            for stem in entry.stems().into_iter().flatten() {
+                if stem != &**word {
+                    compiled += " st:";
+                    compiled += stem;
+                }
+            }
+            compiled.push('\n');
+        }
+    }
+
+    let dir = path.parent().unwrap_or(path);
+    std::fs::create_dir_all(dir)?;
+    // Written under another name first, so that servers starting at the
+    // same time never read it half-written
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&partial, compiled)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// The affix and dictionary file selected by `config`: those of its
+/// language if set, otherwise its affix and dictionary files.
+pub fn select(
+    config: &Config,
+    paths: &[PathBuf],
+) -> Result<(PathBuf, PathBuf)> {
+    match &config.language {
+        None => Ok((
+            config
+                .affix
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_AFFIX)),
+            config
+                .dictionary
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_DICTIONARY)),
+        )),
+        Some(language) => resolve_language(config, paths, language)
+            .map(|files| (files.affix, files.dictionary))
+            .ok_or_else(|| not_found(language)),
+    }
+}
+
+/// The backend checking `language`: the engine `config` selects for it, or
+/// zspell with its dictionary from `paths`.
+fn language_backend(
+    config: &Config,
+    paths: &[PathBuf],
+    language: &str,
+) -> Result<Box<dyn SpellBackend>> {
+    match config.engine(language) {
+        Engine::Zspell => {
+            let files = resolve_installed(config, paths, language)?;
+            let dict = load(&files.affix, &files.dictionary)?;
+            Ok(Box::new(ZspellBackend::new(dict)))
+        }
+        Engine::Hunspell => {
+            let files = resolve_installed(config, paths, language)?;
+            backend::hunspell(&files.affix, &files.dictionary)
+        }
+        engine => backend::create(engine, language, config),
+    }
+}
+
+/// The backend for documents without a language override: that of the
+/// language of `config` if set, otherwise zspell with its affix and
+/// dictionary files.
+pub fn default_backend(
+    config: &Config,
+    paths: &[PathBuf],
+) -> Result<Box<dyn SpellBackend>> {
+    #[cfg(feature = "embedded-dictionary")]
+    if missing(config, paths) {
+        warn!(
+            "No dictionary found, using the small embedded English one. \
+             Select an installed dictionary with --language, or its files \
+             with --affix and --dictionary; run list-dictionaries to see \
+             the available ones"
+        );
+        return Ok(Box::new(ZspellBackend::new(embedded()?)));
+    }
+    if let Some(language) = &config.language {
+        return language_backend(config, paths, language);
+    }
+    let (affix, dictionary) = select(config, paths)?;
+    let dict = load(&affix, &dictionary)?;
+    let language = affix_language(&affix);
+    Ok(Box::new(ZspellBackend::new(dict).with_language(language)))
+}
+
+/// The language named by the `LANG` directive of the affix file `affix`.
+fn affix_language(affix: &Path) -> Option<String> {
+    let text = std::fs::read(affix).ok()?;
+    String::from_utf8_lossy(&text).lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        (fields.next() == Some("LANG"))
+            .then(|| fields.next().map(String::from))
+            .flatten()
+    })
+}
+
+/// The dictionaries used with `config`, including those of the languages
+/// selected by its overrides. Dictionaries given by file are named after
+/// the affix file. Languages checked by engines without dictionary files
+/// have none.
+pub fn used(config: &Config) -> Vec<DictionaryFiles> {
+    let paths = search_paths(&config.dictionary_paths);
+    let mut used = Vec::new();
+    let uses_files = |language: &str| config.engine(language).uses_files();
+    let default = config.language.as_deref().is_none_or(uses_files);
+    if let Some((affix, dictionary)) =
+        select(config, &paths).ok().filter(|_| default)
+    {
+        // Named after their files rather than the language, which may have
+        // been replaced by a fallback
+        let locale = affix
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        used.push(DictionaryFiles {
+            locale,
+            affix,
+            dictionary,
+        });
+    }
+    used.extend(
+        override_languages(config)
+            .filter(|language| uses_files(language))
+            .filter_map(|language| resolve_language(config, &paths, language)),
+    );
+    used
+}
+
+/// The prefix and suffix rules of the default dictionary of `config`, if
+/// it's checked with dictionary files that can be read.
+pub fn affixes(config: &Config) -> Option<Affixes> {
+    let uses_files = |language: &str| config.engine(language).uses_files();
+    if !config.language.as_deref().is_none_or(uses_files) {
+        return None;
+    }
+    let paths = search_paths(&config.dictionary_paths);
+    let (affix, _) = select(config, &paths).ok()?;
+    let text = std::fs::read_to_string(affix).ok()?;
+    Some(Affixes::parse(&text))
+}
+
+/// The flags of the entries of `word` in the dictionary file of `config`,
+/// `None` if it has no entry.
+pub fn flags_of(config: &Config, word: &str) -> Option<String> {
+    let uses_files = |language: &str| config.engine(language).uses_files();
+    if !config.language.as_deref().is_none_or(uses_files) {
+        return None;
+    }
+    let paths = search_paths(&config.dictionary_paths);
+    let (_, dictionary) = select(config, &paths).ok()?;
+    let text = std::fs::read_to_string(dictionary).ok()?;
+    let mut found = None;
+    // The first line holds the number of entries
+    for line in text.lines().skip(1) {
+        let entry = line.split_whitespace().next().unwrap_or_default();
+        let (stem, flags) = entry.split_once('/').unwrap_or((entry, ""));
+        if stem == word {
+            found.get_or_insert_with(String::new).push_str(flags);
+        }
+    }
+    found
+}
+
+/// Every affix and dictionary file used with `config`.
+pub fn files(config: &Config) -> Vec<PathBuf> {
+    used(config)
+        .into_iter()
+        .flat_map(|files| [files.affix, files.dictionary])
+        .collect()
+}
+
+/// The languages selected for some documents only, by overrides, by
+/// `languageIds` or by detection.
+fn override_languages(config: &Config) -> impl Iterator<Item = &str> {
+    config
+        .overrides
+        .iter()
+        .filter_map(|entry| {
+            entry.settings.get("language").and_then(Value::as_str)
+        })
+        .chain(config.language_ids.values().map(String::as_str))
+        .chain(config.detect_languages.iter().map(String::as_str))
+}
+
+fn not_found(language: &str) -> anyhow::Error {
+    anyhow!(
+        "No dictionary found for {}, run list-dictionaries to see the \
+         available ones",
+        language
+    )
+}
+
+/// Load the backends of the languages selected by `config` and its
+/// overrides that `checker` doesn't have yet.
+pub fn load_languages(checker: &mut Checker, config: &Config) -> Result<()> {
+    let languages = config
+        .language
+        .as_deref()
+        .into_iter()
+        .chain(override_languages(config));
+
+    let paths = search_paths(&config.dictionary_paths);
+    for language in languages.collect::<Vec<_>>() {
+        if checker.has_language(language) {
+            continue;
+        }
+        let backend = language_backend(config, &paths, language)?;
+        checker.add_language(language.to_string(), backend);
+    }
+
+    Ok(())
+}
+
+/// All dictionaries found in `paths`, in search order. A locale may occur
+/// more than once if it's installed in several locations.
+pub fn find_all(paths: &[PathBuf]) -> Vec<DictionaryFiles> {
+    let mut found = Vec::new();
+
+    for path in paths {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            continue;
+        };
+        let mut dictionaries = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "dic"))
+            .filter_map(|dictionary| {
+                let affix = dictionary.with_extension("aff");
+                let locale = dictionary.file_stem()?.to_str()?.to_string();
+                affix.is_file().then_some(DictionaryFiles {
+                    locale,
+                    affix,
+                    dictionary,
+                })
+            })
+            .collect::<Vec<_>>();
+        dictionaries.sort_by(|a, b| a.locale.cmp(&b.locale));
+        found.extend(dictionaries);
+    }
+
+    found
+}
+
+/// Find the dictionary for `language` in `paths`, or the first of its
+/// fallbacks that is installed. Languages without configured fallbacks
+/// fall back to their base language, e.g. `en` for `en_GB`, and then to
+/// any other variant of it.
+pub fn resolve_language(
+    config: &Config,
+    paths: &[PathBuf],
+    language: &str,
+) -> Option<DictionaryFiles> {
+    let found = find_all(paths);
+    let find = |locale: &str| {
+        found
+            .iter()
+            .find(|files| same_locale(&files.locale, locale))
+            .cloned()
+    };
+    if let Some(files) = find(language) {
+        return Some(files);
+    }
+
+    let fallbacks = config
+        .language_fallbacks
+        .iter()
+        .find(|(locale, _)| same_locale(locale, language));
+    match fallbacks {
+        Some((_, fallbacks)) => {
+            fallbacks.iter().find_map(|locale| find(locale))
+        }
+        None => {
+            let base = base_language(language);
+            find(base).or_else(|| {
+                found
+                    .iter()
+                    .find(|files| {
+                        same_locale(base_language(&files.locale), base)
+                    })
+                    .cloned()
+            })
+        }
+    }
+}
+
+/// [`resolve_language`], logging the dictionary used in its place if
+/// `language` isn't installed.
+fn resolve_installed(
+    config: &Config,
+    paths: &[PathBuf],
+    language: &str,
+) -> Result<DictionaryFiles> {
+    let files = resolve_language(config, paths, language)
+        .ok_or_else(|| not_found(language))?;
+    if !same_locale(&files.locale, language) {
+        info!(
+            "No dictionary found for {}, using {} instead",
+            language, files.locale
+        );
+    }
+    Ok(files)
+}
+
+/// The locale of the dictionary used in place of the language of `config`,
+/// if that isn't installed.
+pub fn fallback(config: &Config) -> Option<String> {
+    let language = config.language.as_deref()?;
+    if !config.engine(language).uses_files() {
+        return None;
+    }
+    let paths = search_paths(&config.dictionary_paths);
+    resolve_language(config, &paths, language)
+        .map(|files| files.locale)
+        .filter(|locale| !same_locale(locale, language))
+}
+
+/// The language part of `locale`, `en` for `en_GB`.
+pub(crate) fn base_language(locale: &str) -> &str {
+    locale.split(['_', '-']).next().unwrap_or(locale)
+}
+
+/// The MyThes thesaurus data file for `language`: that of `thesaurus` if
+/// set, or else the first `th_<locale>_v2.dat` or `th_<locale>.dat` with an
+/// index next to it in the search paths.
+pub fn thesaurus(config: &Config, language: Option<&str>) -> Option<PathBuf> {
+    if let Some(thesaurus) = &config.thesaurus {
+        return Some(thesaurus.clone());
+    }
+    let language = language?;
+    search_paths(&config.dictionary_paths)
+        .into_iter()
+        .filter_map(|path| std::fs::read_dir(path).ok())
+        .flat_map(|entries| {
+            let mut found = entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension().is_some_and(|ext| ext == "dat")
+                        && path.with_extension("idx").is_file()
+                })
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        })
+        .find(|path| {
+            let stem = path.file_stem().and_then(|stem| stem.to_str());
+            stem.and_then(|stem| stem.strip_prefix("th_"))
+                .map(|locale| locale.strip_suffix("_v2").unwrap_or(locale))
+                .is_some_and(|locale| same_locale(locale, language))
+        })
+}
+
+/// Find the first dictionary for `locale` in `paths`. Locales are compared
+/// ignoring case and treating `-` and `_` alike.
+pub fn resolve(paths: &[PathBuf], locale: &str) -> Option<DictionaryFiles> {
+    find_all(paths)
+        .into_iter()
+        .find(|files| same_locale(&files.locale, locale))
+}
+
+fn same_locale(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| s.to_lowercase().replace('-', "_");
+    normalize(a) == normalize(b)
+}
+
+/// Print the dictionaries found in `paths` as a table.
+pub fn list(paths: &[PathBuf]) {
+    for path in paths {
+        log::debug!("searching {}", path.display());
+    }
+    for files in find_all(paths) {
+        println!(
+            "{}\t{}\t{}\t{}",
+            files.locale,
+            files.encoding(),
+            files.affix.display(),
+            files.dictionary.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn find_and_resolve() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_dictionaries_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("en_US.aff"), "SET UTF-8\n").unwrap();
+        std::fs::write(dir.join("en_US.dic"), "0\n").unwrap();
+        std::fs::write(dir.join("de_DE.aff"), "").unwrap();
+        std::fs::write(dir.join("de_DE.dic"), "0\n").unwrap();
+        std::fs::write(dir.join("orphan.dic"), "0\n").unwrap();
+
+        let paths = [dir.clone()];
+        let locales = find_all(&paths)
+            .into_iter()
+            .map(|files| (files.locale.clone(), files.encoding()))
+            .collect::<Vec<_>>();
+        let resolved = resolve(&paths, "en-us").map(|files| files.locale);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            locales,
+            [
+                ("de_DE".to_string(), "ISO8859-1".to_string()),
+                ("en_US".to_string(), "UTF-8".to_string()),
+            ]
+        );
+        assert_eq!(resolved.as_deref(), Some("en_US"));
+    }
+
+    #[test]
+    fn thesauri() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_thesauri_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["th_en_US_v2", "th_de_DE"] {
+            std::fs::write(dir.join(format!("{}.dat", name)), "").unwrap();
+            std::fs::write(dir.join(format!("{}.idx", name)), "").unwrap();
+        }
+        std::fs::write(dir.join("th_sv_SE.dat"), "").unwrap();
+
+        let config = Config {
+            dictionary_paths: vec![dir.clone()],
+            ..Default::default()
+        };
+        let found = |language| {
+            thesaurus(&config, Some(language))
+                .and_then(|path| Some(path.file_name()?.to_owned()))
+        };
+        let en = found("en-US");
+        let de = found("de_DE");
+        let sv = found("sv_SE");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(en.unwrap(), "th_en_US_v2.dat");
+        assert_eq!(de.unwrap(), "th_de_DE.dat");
+        assert_eq!(sv, None);
+        let config = Config {
+            thesaurus: Some(PathBuf::from("th.dat")),
+            ..Default::default()
+        };
+        assert_eq!(thesaurus(&config, None), Some(PathBuf::from("th.dat")));
+    }
+
+    #[test]
+    fn fallbacks() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_fallbacks_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for locale in ["en_AU", "en_US", "de_DE"] {
+            std::fs::write(dir.join(format!("{}.aff", locale)), "").unwrap();
+            std::fs::write(dir.join(format!("{}.dic", locale)), "0\n").unwrap();
+        }
+
+        let paths = [dir.clone()];
+        let resolve = |config: &Config, language: &str| {
+            resolve_language(config, &paths, language).map(|files| files.locale)
+        };
+        let mut config = Config::default();
+        let variant = resolve(&config, "en_GB");
+        let exact = resolve(&config, "en-us");
+        let unrelated = resolve(&config, "fr_FR");
+        std::fs::write(dir.join("en.aff"), "").unwrap();
+        std::fs::write(dir.join("en.dic"), "0\n").unwrap();
+        let base = resolve(&config, "en_GB");
+        config
+            .language_fallbacks
+            .insert("en-GB".into(), vec!["fr_FR".into(), "en_US".into()]);
+        let configured = resolve(&config, "en_GB");
+        config.language_fallbacks.insert("en-GB".into(), Vec::new());
+        let disabled = resolve(&config, "en_GB");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(variant.as_deref(), Some("en_AU"));
+        assert_eq!(exact.as_deref(), Some("en_US"));
+        assert_eq!(unrelated, None);
+        assert_eq!(base.as_deref(), Some("en"));
+        assert_eq!(configured.as_deref(), Some("en_US"));
+        assert_eq!(disabled, None);
+    }
+
+    #[test]
+    fn embedded_english() {
+        let dict = embedded().unwrap();
+        assert!(dict.check(
+            "The children were running and stopped when they saw the \
+             biggest houses, but I don't think it's happening again"
+        ));
+        assert!(dict.check("Configure the server, then try it quickly"));
+        for misspelled in ["childs", "runed", "agreing", "automaticly"] {
+            assert!(!dict.check(misspelled), "{}", misspelled);
+        }
+    }
+
+    #[test]
+    fn mapped() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_mapped_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("empty.aff"), "").unwrap();
+        std::fs::write(dir.join("empty.dic"), "1\nhello\n").unwrap();
+        std::fs::write(dir.join("latin1.dic"), b"1\nh\xe9llo\n").unwrap();
+
+        let dict =
+            load_cached(&dir.join("empty.aff"), &dir.join("empty.dic"), None);
+        let latin1 =
+            load_cached(&dir.join("empty.aff"), &dir.join("latin1.dic"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(dict.unwrap().check("hello"));
+        assert!(latin1.is_err());
+    }
+
+    #[test]
+    fn compiled() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_compiled_{}", std::process::id()));
+        let cache = dir.join("cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let affix = dir.join("en_US.aff");
+        let dictionary = dir.join("en_US.dic");
+        std::fs::write(
+            &affix,
+            "SET UTF-8\nNOSUGGEST !\nFORBIDDENWORD *\nLANG en_US\n\
+             TRY esianrtolcdugmphbyfvkwz\nKEY qwertyuiop|asdfghjkl\n\
+             REP 1\nREP f ph\nSFX S Y 1\nSFX S 0 s .\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &dictionary,
+            "5\nword/S po:noun\nhello\ndarn/!\nwordz/*\nphone\n",
+        )
+        .unwrap();
+
+        let words = |dict: &Dictionary| {
+            let mut words = [
+                dict.wordlist(),
+                dict.wordlist_nosuggest(),
+                dict.wordlist_forbidden(),
+            ]
+            .map(|list| list.inner().keys().cloned().collect::<Vec<_>>());
+            words.iter_mut().for_each(|list| list.sort());
+            words
+        };
+        let kept = compiled_affix(&std::fs::read_to_string(&affix).unwrap());
+        let language = affix_language(&affix);
+        let parsed = load_cached(&affix, &dictionary, Some(&cache)).unwrap();
+        let cached = std::fs::read_dir(&cache).unwrap().count();
+        let compiled = load_cached(&affix, &dictionary, Some(&cache)).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cached, 1);
+        assert_eq!(language.as_deref(), Some("en_US"));
+        assert!(kept.ends_with(
+            "LANG en_US\nTRY esianrtolcdugmphbyfvkwz\n\
+             KEY qwertyuiop|asdfghjkl\nREP 1\nREP f ph\n"
+        ));
+        assert_eq!(words(&compiled), words(&parsed));
+        assert!(compiled.check("words hello darn"));
+        assert!(!compiled.check("wordz"));
+        for word in ["wordd", "fone"] {
+            assert_eq!(
+                compiled.entry(word).suggest(),
+                parsed.entry(word).suggest()
+            );
+        }
+        let backend = ZspellBackend::new(compiled);
+        assert_eq!(backend.stems("words"), ["word"]);
+        assert_eq!(backend.analyze("words"), ["po:noun"]);
+    }
+}
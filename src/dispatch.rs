@@ -0,0 +1,350 @@
+use anyhow::Result;
+use crossbeam_channel::SendError;
+use log::warn;
+use lsp_server::{
+    ErrorCode, Message, Notification, Request, RequestId, Response,
+};
+use serde::Serialize;
+use std::fmt;
+
+/// An error returned to the client in the response to a request with its
+/// own code, rather than the `InternalError` of other handler errors.
+#[derive(Debug)]
+pub struct RequestError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RequestError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Whether `err` comes from sending a message to the client, after which
+/// the server can't go on. Only these errors of handlers stop the server.
+pub fn is_transport_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<SendError<Message>>())
+}
+
+/// The result of a handler which may answer its request later, once the
+/// work is done on another thread.
+pub enum Reply<T> {
+    Now(T),
+    Later,
+}
+
+/// The response to request `id` with the result of its handler. Errors of
+/// handlers are answered with their [`RequestError`], or `InternalError`,
+/// except for transport errors, which are returned.
+pub fn respond<T: Serialize>(
+    method: &str,
+    id: RequestId,
+    result: Result<T>,
+) -> Result<Response> {
+    match result {
+        Ok(result) => Ok(Response::new_ok(id, result)),
+        Err(err) if is_transport_error(&err) => Err(err),
+        Err(err) => match err.downcast::<RequestError>() {
+            Ok(err) => Ok(Response::new_err(id, err.code as i32, err.message)),
+            Err(err) => {
+                warn!("{} failed: {:#}", method, err);
+                Ok(Response::new_err(
+                    id,
+                    ErrorCode::InternalError as i32,
+                    format!("{:#}", err),
+                ))
+            }
+        },
+    }
+}
+
+/// Routes a request to the handler registered for its method with
+/// [`Self::on`], whose result becomes the response.
+pub struct RequestDispatcher<'a, S> {
+    server: &'a mut S,
+    request: Option<Request>,
+    response: Option<Result<Option<Response>>>,
+}
+
+impl<'a, S> RequestDispatcher<'a, S> {
+    pub fn new(server: &'a mut S, request: Request) -> Self {
+        Self {
+            server,
+            request: Some(request),
+            response: None,
+        }
+    }
+
+    pub fn on<R: lsp_types::request::Request>(
+        self,
+        handler: impl FnOnce(&mut S, R::Params) -> Result<R::Result>,
+    ) -> Self {
+        self.on_deferred::<R>(|server, _, params| {
+            handler(server, params).map(Reply::Now)
+        })
+    }
+
+    /// Like [`Self::on`], for handlers answering some requests later,
+    /// given the id of the request to answer.
+    pub fn on_deferred<R: lsp_types::request::Request>(
+        mut self,
+        handler: impl FnOnce(
+            &mut S,
+            &RequestId,
+            R::Params,
+        ) -> Result<Reply<R::Result>>,
+    ) -> Self {
+        let Some(request) =
+            self.request.take_if(|request| request.method == R::METHOD)
+        else {
+            return self;
+        };
+
+        let id = request.id;
+        let params = match serde_json::from_value(request.params) {
+            Ok(params) => params,
+            Err(err) => {
+                self.response = Some(Ok(Some(Response::new_err(
+                    id,
+                    ErrorCode::InvalidParams as i32,
+                    format!("invalid parameters for {}: {}", R::METHOD, err),
+                ))));
+                return self;
+            }
+        };
+        self.response = Some(match handler(self.server, &id, params) {
+            Ok(Reply::Later) => Ok(None),
+            Ok(Reply::Now(result)) => {
+                respond(R::METHOD, id, Ok(result)).map(Some)
+            }
+            Err(err) => respond::<()>(R::METHOD, id, Err(err)).map(Some),
+        });
+        self
+    }
+
+    /// The response, or a `MethodNotFound` error if no handler took the
+    /// request. `None` if the handler answers later.
+    pub fn finish(self) -> Result<Option<Response>> {
+        match (self.response, self.request) {
+            (Some(response), _) => response,
+            (None, Some(request)) => Ok(Some(Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unhandled method: {}", request.method),
+            ))),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Routes a notification to the handler registered for its method with
+/// [`Self::on`]. Notifications without a handler are ignored, and those
+/// with invalid parameters or whose handler fails are logged, as there's
+/// no response to report them in.
+pub struct NotificationDispatcher<'a, S> {
+    server: &'a mut S,
+    notification: Option<Notification>,
+    result: Result<()>,
+}
+
+impl<'a, S> NotificationDispatcher<'a, S> {
+    pub fn new(server: &'a mut S, notification: Notification) -> Self {
+        Self {
+            server,
+            notification: Some(notification),
+            result: Ok(()),
+        }
+    }
+
+    pub fn on<N: lsp_types::notification::Notification>(
+        mut self,
+        handler: impl FnOnce(&mut S, N::Params) -> Result<()>,
+    ) -> Self {
+        let Some(notification) = self
+            .notification
+            .take_if(|notification| notification.method == N::METHOD)
+        else {
+            return self;
+        };
+
+        let params = match serde_json::from_value(notification.params) {
+            Ok(params) => params,
+            Err(err) => {
+                warn!("invalid parameters for {}: {}", N::METHOD, err);
+                return self;
+            }
+        };
+        match handler(self.server, params) {
+            Err(err) if is_transport_error(&err) => self.result = Err(err),
+            Err(err) => warn!("{} failed: {:#}", N::METHOD, err),
+            Ok(()) => {}
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use anyhow::anyhow;
+    use lsp_types::notification::{
+        DidChangeTextDocument, DidCloseTextDocument, Exit,
+    };
+    use lsp_types::request::{
+        DocumentHighlightRequest, HoverRequest, Shutdown, WillSaveWaitUntil,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn requests() {
+        let mut calls = 0;
+        let dispatch = |calls: &mut i32, request| {
+            RequestDispatcher::new(calls, request)
+                .on::<Shutdown>(|calls, ()| {
+                    *calls += 1;
+                    Ok(())
+                })
+                .on::<WillSaveWaitUntil>(|_, _| {
+                    Err(RequestError::new(ErrorCode::RequestFailed, "failed")
+                        .into())
+                })
+                .on::<DocumentHighlightRequest>(|_, _| Err(anyhow!("failed")))
+                .on_deferred::<HoverRequest>(|_, _, _| Ok(Reply::Later))
+                .finish()
+                .unwrap()
+        };
+
+        let response =
+            dispatch(&mut calls, Request::new(1.into(), "shutdown".into(), ()))
+                .unwrap();
+        assert_eq!(calls, 1);
+        assert!(response.error.is_none());
+
+        let response = dispatch(
+            &mut calls,
+            Request::new(2.into(), "textDocument/willSaveWaitUntil".into(), 0),
+        )
+        .unwrap();
+        assert_eq!(
+            response.error.unwrap().code,
+            ErrorCode::InvalidParams as i32
+        );
+
+        let params = json!({
+            "textDocument": {"uri": "file:///a.txt"},
+            "reason": 1,
+        });
+        let response = dispatch(
+            &mut calls,
+            Request::new(
+                3.into(),
+                "textDocument/willSaveWaitUntil".into(),
+                params,
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            response.error.unwrap().code,
+            ErrorCode::RequestFailed as i32
+        );
+
+        let params = json!({
+            "textDocument": {"uri": "file:///a.txt"},
+            "position": {"line": 0, "character": 0},
+        });
+        let response = dispatch(
+            &mut calls,
+            Request::new(
+                4.into(),
+                "textDocument/documentHighlight".into(),
+                params,
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            response.error.unwrap().code,
+            ErrorCode::InternalError as i32
+        );
+
+        let params = json!({
+            "textDocument": {"uri": "file:///a.txt"},
+            "position": {"line": 0, "character": 0},
+        });
+        let response = dispatch(
+            &mut calls,
+            Request::new(5.into(), "textDocument/hover".into(), params),
+        );
+        assert!(response.is_none());
+
+        let response = dispatch(
+            &mut calls,
+            Request::new(6.into(), "textDocument/codeLens".into(), ()),
+        )
+        .unwrap();
+        assert_eq!(
+            response.error.unwrap().code,
+            ErrorCode::MethodNotFound as i32
+        );
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn notifications() {
+        let mut calls = 0;
+        let dispatch = |calls: &mut i32, notification| {
+            NotificationDispatcher::new(calls, notification)
+                .on::<Exit>(|calls, ()| {
+                    *calls += 1;
+                    Ok(())
+                })
+                .on::<DidCloseTextDocument>(|_, _| Ok(()))
+                .on::<DidChangeTextDocument>(|_, _| {
+                    let message = Notification::new("exit".into(), ());
+                    Err(SendError(Message::Notification(message)).into())
+                })
+                .finish()
+        };
+
+        assert!(
+            dispatch(&mut calls, Notification::new("exit".into(), ())).is_ok()
+        );
+        assert_eq!(calls, 1);
+        // Invalid parameters are only logged
+        assert!(dispatch(
+            &mut calls,
+            Notification::new("textDocument/didClose".into(), ())
+        )
+        .is_ok());
+        let params = json!({
+            "textDocument": {"uri": "file:///a.txt", "version": 1},
+            "contentChanges": [],
+        });
+        assert!(dispatch(
+            &mut calls,
+            Notification::new("textDocument/didChange".into(), params)
+        )
+        .is_err());
+        assert!(dispatch(
+            &mut calls,
+            Notification::new("$/unknown".into(), ())
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+use lsp_types::{Position, TextDocumentContentChangeEvent};
+use std::sync::Arc;
+
+pub struct Document {
+    /// Shared with the checks of the document in progress, and only copied
+    /// when changed while they still hold it.
+    pub text: Arc<String>,
+    pub version: i32,
+    /// Set by the server on every change of the text. Unlike `version`,
+    /// which the client may get wrong, it's never the same for two texts
+    /// of a document, even if it's closed and opened again.
+    pub revision: u64,
+    /// The LSP `languageId` the document was opened with, empty if unknown.
+    pub language_id: String,
+    /// Language detected from the text, see `detectLanguages`.
+    pub detected_language: Option<String>,
+    /// Language chosen with the `zspell.setLanguage` command, taking
+    /// precedence over the detected one.
+    pub chosen_language: Option<String>,
+}
+
+impl Document {
+    pub fn new(text: String, version: i32) -> Self {
+        Self {
+            text: Arc::new(text),
+            version,
+            revision: 0,
+            language_id: String::new(),
+            detected_language: None,
+            chosen_language: None,
+        }
+    }
+
+    /// Apply a single change event. Events without a range replace the
+    /// whole document, as sent by clients using full synchronization, and
+    /// take the text of the event without copying it.
+    pub fn apply_change(&mut self, change: TextDocumentContentChangeEvent) {
+        match change.range {
+            None => self.text = Arc::new(change.text),
+            Some(range) => {
+                let start = self.offset_at(range.start);
+                let end = self.offset_at(range.end).max(start);
+                Arc::make_mut(&mut self.text)
+                    .replace_range(start..end, &change.text);
+            }
+        }
+        // Release memory left over from edits that shrank the text, which
+        // counts towards the memory budget
+        if self.text.capacity() > 2 * self.text.len() + 4096 {
+            Arc::make_mut(&mut self.text).shrink_to_fit();
+        }
+    }
+
+    /// Convert an LSP position (UTF-16 code units) into a byte offset,
+    /// clamping positions past the end of a line or the document.
+    pub fn offset_at(&self, position: Position) -> usize {
+        let mut line_start = 0;
+        for _ in 0..position.line {
+            match self.text[line_start..].find('\n') {
+                None => return self.text.len(),
+                Some(i) => line_start += i + 1,
+            }
+        }
+
+        let line = &self.text[line_start..];
+        let line = match line.find('\n') {
+            None => line,
+            Some(i) => &line[..i],
+        };
+
+        let mut units = 0;
+        for (offset, c) in line.char_indices() {
+            if units >= position.character {
+                return line_start + offset;
+            }
+            units += c.len_utf16() as u32;
+        }
+
+        line_start + line.trim_end_matches('\r').len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use lsp_types::Range;
+
+    fn change(
+        start: (u32, u32),
+        end: (u32, u32),
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn incremental_changes() {
+        let mut document =
+            Document::new("Hello wrold\nsecond line\n".into(), 0);
+
+        document.apply_change(change((0, 6), (0, 11), "world"));
+        document.apply_change(change((1, 0), (1, 0), "a "));
+        document.apply_change(change((2, 0), (2, 0), "third"));
+
+        assert_eq!(*document.text, "Hello world\na second line\nthird");
+    }
+
+    #[test]
+    fn shared_text() {
+        let mut document = Document::new("Hello wrold".into(), 0);
+        let checked = document.text.clone();
+
+        document.apply_change(change((0, 6), (0, 11), "world"));
+        let buffer = document.text.as_ptr();
+        document.apply_change(change((0, 0), (0, 1), "J"));
+
+        assert_eq!(*checked, "Hello wrold");
+        assert_eq!(*document.text, "Jello world");
+        assert_eq!(document.text.as_ptr(), buffer);
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let document = Document::new("a😀b\r\nc".into(), 0);
+
+        assert_eq!(document.offset_at(Position::new(0, 3)), 5);
+        assert_eq!(document.offset_at(Position::new(0, 10)), 6);
+        assert_eq!(document.offset_at(Position::new(1, 1)), 9);
+        assert_eq!(document.offset_at(Position::new(5, 0)), 9);
+    }
+}
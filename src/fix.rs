@@ -0,0 +1,178 @@
+use crate::checker::Checker;
+use crate::config::Config;
+use crate::lexer::Word;
+use anyhow::{Context, Result};
+use clap::Args;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct FixArgs {
+    /// Files to fix.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Replace every misspelling that has suggestions with the top
+    /// suggestion, without prompting.
+    #[arg(long)]
+    write: bool,
+}
+
+/// A replacement of the byte range `start..end` of a file.
+struct Replacement {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+enum Choice {
+    Replace(String),
+    Skip,
+    Quit,
+}
+
+/// Step through the misspellings of each file and apply the chosen
+/// replacements in place.
+pub fn fix(checker: &Checker, config: &Config, args: &FixArgs) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+
+    for path in &args.paths {
+        let mut text = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+
+        let config = config.for_path(path);
+        let mut replacements = Vec::new();
+        let mut quit = false;
+        let sections = checker.sections(&text, &config);
+        for word in checker.misspelled_words(&text, &config) {
+            let suggestions = checker.suggest_in(&word, &sections, &config);
+            let choice = if args.write {
+                match suggestions.into_iter().next() {
+                    Some(suggestion) => Choice::Replace(suggestion),
+                    None => Choice::Skip,
+                }
+            } else {
+                let line = text.lines().nth(word.range.start.line as usize);
+                prompt(
+                    &mut input,
+                    path,
+                    line.unwrap_or(""),
+                    &word,
+                    &suggestions,
+                )?
+            };
+
+            match choice {
+                Choice::Replace(replacement) => {
+                    let start =
+                        word.text.as_ptr() as usize - text.as_ptr() as usize;
+                    replacements.push(Replacement {
+                        start,
+                        end: start + word.text.len(),
+                        text: replacement,
+                    });
+                }
+                Choice::Skip => {}
+                Choice::Quit => {
+                    quit = true;
+                    break;
+                }
+            }
+        }
+
+        if !replacements.is_empty() {
+            for replacement in replacements.iter().rev() {
+                text.replace_range(
+                    replacement.start..replacement.end,
+                    &replacement.text,
+                );
+            }
+            std::fs::write(path, &text).with_context(|| {
+                format!("unable to write {}", path.display())
+            })?;
+            eprintln!(
+                "{}: applied {} replacement(s)",
+                path.display(),
+                replacements.len()
+            );
+        }
+
+        if quit {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn prompt(
+    input: &mut impl BufRead,
+    path: &Path,
+    line: &str,
+    word: &Word,
+    suggestions: &[String],
+) -> Result<Choice> {
+    let mut out = std::io::stdout().lock();
+    let start = columns(line, word.range.start.character);
+    let length = word.text.chars().count();
+
+    writeln!(
+        out,
+        "{}:{}:{}: {}",
+        path.display(),
+        word.range.start.line + 1,
+        start + 1,
+        word.text
+    )?;
+    writeln!(out, "    {}", line)?;
+    writeln!(out, "    {}{}", " ".repeat(start), "^".repeat(length))?;
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        writeln!(out, "  {}) {}", i + 1, suggestion)?;
+    }
+
+    loop {
+        write!(out, "[number] replace, [r]eplace with..., [s]kip, [q]uit: ")?;
+        out.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(Choice::Quit);
+        }
+
+        match answer.trim() {
+            "" | "s" => return Ok(Choice::Skip),
+            "q" => return Ok(Choice::Quit),
+            "r" => {
+                write!(out, "replacement: ")?;
+                out.flush()?;
+                let mut replacement = String::new();
+                input.read_line(&mut replacement)?;
+                let replacement = replacement.trim();
+                if !replacement.is_empty() {
+                    return Ok(Choice::Replace(replacement.to_string()));
+                }
+            }
+            answer => {
+                let index = answer.parse::<usize>().ok();
+                if let Some(suggestion) =
+                    index.and_then(|i| suggestions.get(i.wrapping_sub(1)))
+                {
+                    return Ok(Choice::Replace(suggestion.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// The number of characters of `line` before the UTF-16 offset `character`
+/// of an LSP position.
+fn columns(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    line.chars()
+        .take_while(|char| {
+            units += char.len_utf16() as u32;
+            units <= character
+        })
+        .count()
+}
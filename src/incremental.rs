@@ -0,0 +1,188 @@
+//! Results of earlier command line checks, so that repeated runs only
+//! recheck the files that changed.
+
+use crate::config::Config;
+use crate::dictionaries;
+use crate::personal;
+use anyhow::Result;
+use log::warn;
+use lsp_types::Range;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A misspelled word of a cached file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CachedWord {
+    pub range: Range,
+    pub word: String,
+}
+
+/// The results of a file, valid as long as its key is the same.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Entry {
+    key: u64,
+    words: Vec<CachedWord>,
+}
+
+/// The results of the files checked from one directory, kept in a file of
+/// the user cache directory named after it.
+pub struct ResultsCache {
+    path: PathBuf,
+    /// Hash of everything besides a file and its settings that results
+    /// depend on.
+    fingerprint: u64,
+    entries: HashMap<PathBuf, Entry>,
+    /// Results of the files checked since the cache was opened.
+    updated: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl ResultsCache {
+    /// The cache of the current directory for checks with `config`, in the
+    /// user cache directory. `None` if there's no cache directory.
+    pub fn open(config: &Config) -> Option<Self> {
+        let dir = dictionaries::cache_dir()?.join("results");
+        let cwd = std::env::current_dir().ok()?;
+        Some(Self::open_in(&dir, &cwd, config))
+    }
+
+    fn open_in(dir: &Path, cwd: &Path, config: &Config) -> Self {
+        let mut hasher = DefaultHasher::new();
+        cwd.hash(&mut hasher);
+        let path = dir.join(format!("{:016x}.json", hasher.finish()));
+
+        let entries = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+                warn!("Ignoring results cache {}: {}", path.display(), err);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        Self {
+            path,
+            fingerprint: fingerprint(config),
+            entries,
+            updated: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The key of the results of `text` at `path`, checked with `config`.
+    pub fn key(&self, path: &Path, config: &Config, text: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.fingerprint.hash(&mut hasher);
+        path.hash(&mut hasher);
+        serde_json::to_string(config)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        text.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The misspelled words of `path` if it was checked with the same `key`.
+    pub fn get(&self, path: &Path, key: u64) -> Option<Vec<CachedWord>> {
+        let entry = self.entries.get(path)?;
+        (entry.key == key).then(|| entry.words.clone())
+    }
+
+    /// Remember the misspelled `words` of `path`, checked with `key`.
+    pub fn insert(&self, path: &Path, key: u64, words: Vec<CachedWord>) {
+        self.updated
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), Entry { key, words });
+    }
+
+    /// Write the results of the files checked since the cache was opened,
+    /// keeping those of the others.
+    pub fn save(&self) -> Result<()> {
+        let updated = self.updated.lock().unwrap();
+        if updated.is_empty() {
+            return Ok(());
+        }
+        let mut entries = self.entries.clone();
+        entries.extend(
+            updated
+                .iter()
+                .map(|(path, entry)| (path.clone(), entry.clone())),
+        );
+
+        let dir = self.path.parent().unwrap_or(&self.path);
+        std::fs::create_dir_all(dir)?;
+        // Written under another name first, so that runs at the same time
+        // never read it half-written
+        let partial = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&partial, serde_json::to_vec(&entries)?)?;
+        std::fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Hash of the version of the server and the dictionary and wordlist files
+/// used with `config`, by their size and modification time.
+fn fingerprint(config: &Config) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    let files = dictionaries::files(config)
+        .into_iter()
+        .chain(config.personal_dictionary.clone())
+        .chain(config.team_dictionary.clone())
+        .chain(personal::import_paths(config));
+    for file in files {
+        let metadata = std::fs::metadata(&file).ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok());
+        file.hash(&mut hasher);
+        metadata.map(|metadata| metadata.len()).hash(&mut hasher);
+        modified.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use lsp_types::Position;
+
+    #[test]
+    fn results() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_results_{}", std::process::id()));
+        let cwd = Path::new("/project");
+        let config = Config::default();
+        let words = vec![CachedWord {
+            range: Range::new(Position::new(0, 6), Position::new(0, 10)),
+            word: "wrld".to_string(),
+        }];
+
+        let cache = ResultsCache::open_in(&dir, cwd, &config);
+        let key = cache.key(Path::new("a.md"), &config, "hello wrld");
+        let missing = cache.get(Path::new("a.md"), key);
+        cache.insert(Path::new("a.md"), key, words.clone());
+        cache.save().unwrap();
+
+        let cache = ResultsCache::open_in(&dir, cwd, &config);
+        let cached = cache.get(Path::new("a.md"), key);
+        let changed = cache.key(Path::new("a.md"), &config, "hello world");
+        let other = Config {
+            check_identifiers: true,
+            ..Default::default()
+        };
+        let reconfigured = cache.key(Path::new("a.md"), &other, "hello wrld");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(missing, None);
+        assert_eq!(cached, Some(words));
+        assert_eq!(cache.get(Path::new("a.md"), changed), None);
+        assert_eq!(cache.get(Path::new("a.md"), reconfigured), None);
+    }
+}
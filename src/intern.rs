@@ -0,0 +1,56 @@
+//! Sharing of the strings repeated across diagnostics, such as their
+//! messages and the misspelled words, so that each is allocated once rather
+//! than for every diagnostic of every check.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Strings below this many are never dropped.
+const MIN_RETAINED: usize = 1024;
+
+/// A set of shared strings. Strings no longer used outside of the set are
+/// dropped whenever it doubles in size.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+    /// Number of strings after unused ones were last dropped.
+    retained: usize,
+}
+
+impl Interner {
+    /// The shared copy of `string`.
+    pub fn intern(&mut self, string: &str) -> Arc<str> {
+        if let Some(shared) = self.strings.get(string) {
+            return shared.clone();
+        }
+        if self.strings.len() >= 2 * self.retained.max(MIN_RETAINED) {
+            self.strings.retain(|shared| Arc::strong_count(shared) > 1);
+            self.retained = self.strings.len();
+        }
+        let shared = Arc::<str>::from(string);
+        self.strings.insert(shared.clone());
+        shared
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let mut interner = Interner::default();
+        let word = interner.intern("wrld");
+
+        assert!(Arc::ptr_eq(&word, &interner.intern("wrld")));
+        assert!(!Arc::ptr_eq(&word, &interner.intern("teh")));
+
+        // Unused strings are dropped once the set has doubled
+        for index in 0..2 * MIN_RETAINED {
+            interner.intern(&index.to_string());
+        }
+        assert!(interner.strings.len() < MIN_RETAINED);
+        assert!(Arc::ptr_eq(&word, &interner.intern("wrld")));
+    }
+}
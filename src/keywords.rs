@@ -0,0 +1,149 @@
+//! Keywords of programming languages, never reported as misspelled parts
+//! of identifiers with `checkIdentifiers`.
+
+use std::path::Path;
+
+const C: &[&str] = &[
+    "alignas",
+    "alignof",
+    "auto",
+    "const",
+    "constexpr",
+    "enum",
+    "extern",
+    "goto",
+    "inline",
+    "nullptr",
+    "restrict",
+    "sizeof",
+    "struct",
+    "typedef",
+    "typeof",
+    "uint",
+    "void",
+    "volatile",
+];
+
+const CPP: &[&str] = &[
+    "alignas",
+    "alignof",
+    "auto",
+    "constexpr",
+    "consteval",
+    "constinit",
+    "const",
+    "decltype",
+    "enum",
+    "extern",
+    "goto",
+    "inline",
+    "noexcept",
+    "nullptr",
+    "operator",
+    "sizeof",
+    "struct",
+    "typedef",
+    "typeid",
+    "typename",
+    "uint",
+    "void",
+    "volatile",
+];
+
+const GO: &[&str] = &[
+    "chan",
+    "const",
+    "defer",
+    "fallthrough",
+    "func",
+    "goroutine",
+    "iota",
+    "struct",
+    "uint",
+    "uintptr",
+];
+
+const JAVA: &[&str] = &[
+    "enum",
+    "extends",
+    "implements",
+    "instanceof",
+    "goto",
+    "strictfp",
+    "synchronized",
+    "throws",
+    "void",
+    "volatile",
+];
+
+const JAVASCRIPT: &[&str] = &[
+    "async",
+    "const",
+    "enum",
+    "extends",
+    "instanceof",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+];
+
+const PYTHON: &[&str] = &[
+    "args", "async", "def", "del", "elif", "init", "kwargs", "lambda",
+    "nonlocal", "repr", "str",
+];
+
+const RUST: &[&str] = &[
+    "async", "const", "dyn", "enum", "extern", "fn", "impl", "isize", "mut",
+    "pub", "struct", "usize",
+];
+
+const SHELL: &[&str] = &["elif", "esac", "fi", "readonly", "typeset"];
+
+const TYPESCRIPT: &[&str] = &[
+    "async",
+    "const",
+    "enum",
+    "extends",
+    "implements",
+    "instanceof",
+    "keyof",
+    "readonly",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+];
+
+/// The keywords of the programming language of the document at `path`
+/// with the LSP `language_id`, if it's one of those known. The language id
+/// wins over the extension of the path unless it's empty.
+pub fn of(path: &Path, language_id: Option<&str>) -> &'static [&'static str] {
+    let language = language_id.filter(|id| !id.is_empty());
+    let language = language.unwrap_or_else(|| {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("c" | "h") => "c",
+            Some("cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx") => "cpp",
+            Some("go") => "go",
+            Some("java") => "java",
+            Some("js" | "jsx" | "mjs" | "cjs") => "javascript",
+            Some("py" | "pyi") => "python",
+            Some("rs") => "rust",
+            Some("sh" | "bash" | "zsh") => "shellscript",
+            Some("ts" | "tsx" | "mts" | "cts") => "typescript",
+            _ => "",
+        }
+    });
+    match language {
+        "c" => C,
+        "cpp" => CPP,
+        "go" => GO,
+        "java" => JAVA,
+        "javascript" | "javascriptreact" => JAVASCRIPT,
+        "python" => PYTHON,
+        "rust" => RUST,
+        "shellscript" | "sh" | "bash" => SHELL,
+        "typescript" | "typescriptreact" => TYPESCRIPT,
+        _ => &[],
+    }
+}
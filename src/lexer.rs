@@ -1,9 +1,16 @@
+use crate::segment;
+use crate::syntax::{Block, Syntax};
 use lsp_types::{Position, Range};
+use std::collections::VecDeque;
+use std::ops;
 use std::str::{CharIndices, Lines};
 use streaming_iterator::StreamingIterator;
 
+#[derive(Clone, Copy)]
 pub struct Word<'a> {
     pub text: &'a str,
+    /// The range of the word, counting characters in UTF-16 code units
+    /// like LSP positions.
     pub range: Range,
 }
 
@@ -19,23 +26,128 @@ struct CharPosIter<'a> {
     current_line: &'a str,
     chars: CharIndices<'a>,
     position: Position,
+    max_line_length: usize,
+    /// The character last yielded.
+    previous: char,
+    /// Whether the characters yielded are within double quotes on the
+    /// current line, as string literals are.
+    quoted: bool,
+    /// The `\` or `%` last yielded if it starts an escape sequence or
+    /// format specifier, that is if it isn't itself escaped, as the second
+    /// of `\\` or `%%`.
+    escape: Option<char>,
+    syntax: Syntax,
+    /// Byte ranges of the current line left out, yielded as spaces.
+    masked: Vec<ops::Range<usize>>,
+    /// What the lines before the current one left open.
+    block: Block,
 }
 
 impl<'a> CharPosIter<'a> {
-    pub fn new(text: &'a str) -> Option<Self> {
+    pub fn new(text: &'a str, max_line_length: usize) -> Option<Self> {
         let mut lines = text.lines();
-        let (current_line, chars) = match lines.next() {
-            None => return None,
-            Some(line) => (line, line.char_indices()),
-        };
+        let current_line = lines.next()?;
 
         Some(Self {
             lines,
-            chars,
+            chars: line_chars(current_line, max_line_length),
             current_line,
             position: Position::default(),
+            max_line_length,
+            previous: '\n',
+            quoted: false,
+            escape: None,
+            syntax: Syntax::Plain,
+            masked: Vec::new(),
+            block: Block::None,
         })
     }
+
+    /// The character starting the escape sequence or format specifier the
+    /// next character is part of, if it's within double quotes.
+    fn escape(&self) -> Option<char> {
+        self.escape.filter(|_| self.quoted)
+    }
+
+    fn set_syntax(&mut self, syntax: Syntax) {
+        self.syntax = syntax;
+        self.block = Block::None;
+        self.masked = self.syntax.masked(self.current_line, &mut self.block);
+    }
+}
+
+/// The characters of `line`, none if it's longer than `max_line_length`
+/// bytes.
+fn line_chars(line: &str, max_line_length: usize) -> CharIndices<'_> {
+    if line.len() > max_line_length {
+        "".char_indices()
+    } else {
+        line.char_indices()
+    }
+}
+
+/// `text` with what the lexer leaves out blanked: the parts that aren't
+/// prose in `syntax`, URLs and lines longer than `max_line_length` bytes.
+/// Blanked characters become as many spaces as they have bytes, so that
+/// offsets into it are offsets into `text`.
+pub fn prose(text: &str, syntax: &Syntax, max_line_length: usize) -> String {
+    let mut prose = String::with_capacity(text.len());
+    let mut block = Block::None;
+    let blank = |prose: &mut String, part: &str| {
+        prose.extend(std::iter::repeat_n(' ', part.len()));
+    };
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n').trim_end_matches('\r');
+        let masked = syntax.masked(content, &mut block);
+        if content.len() > max_line_length {
+            blank(&mut prose, content);
+        } else {
+            for (offset, char) in content.char_indices() {
+                if masked.iter().any(|range| range.contains(&offset)) {
+                    prose.extend(std::iter::repeat_n(' ', char.len_utf8()));
+                } else {
+                    prose.push(char);
+                }
+            }
+        }
+        prose.push_str(&line[content.len()..]);
+    }
+
+    // URLs, which whole text checkers would take for misspellings
+    let mut blanked = String::with_capacity(prose.len());
+    let mut copied = 0;
+    for (start, token) in tokens(&prose) {
+        if token.contains("://") || token.starts_with("www.") {
+            blanked.push_str(&prose[copied..start]);
+            blank(&mut blanked, token);
+            copied = start + token.len();
+        }
+    }
+    if copied == 0 {
+        return prose;
+    }
+    blanked.push_str(&prose[copied..]);
+    blanked
+}
+
+/// The whitespace delimited tokens of `text` with their offsets.
+fn tokens(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(char::is_whitespace)
+        .filter(|token| !token.is_empty())
+        .map(move |token| {
+            (token.as_ptr() as usize - text.as_ptr() as usize, token)
+        })
+}
+
+/// The lines of `text` longer than `max_line_length` bytes.
+pub fn long_lines(
+    text: &str,
+    max_line_length: usize,
+) -> impl Iterator<Item = u32> + '_ {
+    text.lines()
+        .enumerate()
+        .filter(move |(_, line)| line.len() > max_line_length)
+        .map(|(index, _)| index as u32)
 }
 
 impl<'a> Iterator for CharPosIter<'a> {
@@ -44,22 +156,50 @@ impl<'a> Iterator for CharPosIter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.chars.next() {
             Some((offset, char)) => {
+                let position = self.position;
+                self.position.character += char.len_utf16() as u32;
+                let char = if self
+                    .masked
+                    .iter()
+                    .any(|range| range.contains(&offset))
+                {
+                    ' '
+                } else {
+                    char
+                };
                 let charpos = CharPos {
                     char,
-                    position: self.position,
+                    position,
                     offset,
                 };
-                self.position.character += 1;
+                if char == '"' && self.escape != Some('\\') {
+                    self.quoted = !self.quoted;
+                }
+                self.escape = match char {
+                    '\\' | '%' if self.escape.is_none() => Some(char),
+                    _ => None,
+                };
+                self.previous = char;
                 Some(charpos)
             }
             None => match self.lines.next() {
                 None => None,
                 Some(line) => {
+                    // Yield the line break so that words never span lines
+                    let charpos = CharPos {
+                        char: '\n',
+                        position: self.position,
+                        offset: self.current_line.len(),
+                    };
                     self.current_line = line;
-                    self.chars = line.char_indices();
+                    self.chars = line_chars(line, self.max_line_length);
+                    self.masked = self.syntax.masked(line, &mut self.block);
                     self.position.line += 1;
                     self.position.character = 0;
-                    self.next()
+                    self.previous = '\n';
+                    self.quoted = false;
+                    self.escape = None;
+                    Some(charpos)
                 }
             },
         }
@@ -69,18 +209,77 @@ impl<'a> Iterator for CharPosIter<'a> {
 pub struct Lexer<'a> {
     iter: CharPosIter<'a>,
     current_word: Option<Word<'a>>,
+    /// The line of the documentation tag whose name comes next, such as
+    /// the `x` of `@param x`.
+    tag_name: Option<u32>,
+    /// The first character of the next word, along with the character
+    /// before it and the escape it's in, when it ended the last one.
+    pending: Option<(CharPos, char, Option<char>)>,
+    /// Split runs of scripts written without spaces into words rather
+    /// than skipping them.
+    segment: bool,
+    /// Split camelCase words into the words they're made of.
+    identifiers: bool,
+    /// Read the text as source code, with string literals and
+    /// documentation tags.
+    code: bool,
+    /// The parts of the last word split but not yet yielded.
+    parts: VecDeque<Word<'a>>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(text: &'a str) -> Option<Self> {
-        let iter = CharPosIter::new(text);
-        match iter {
-            None => None,
-            Some(iter) => Some(Self {
-                iter,
-                current_word: None,
-            }),
-        }
+        Self::with_max_line_length(text, usize::MAX)
+    }
+
+    /// A lexer skipping the lines longer than `max_line_length` bytes,
+    /// such as those of minified files, without looking at their
+    /// characters.
+    pub fn with_max_line_length(
+        text: &'a str,
+        max_line_length: usize,
+    ) -> Option<Self> {
+        CharPosIter::new(text, max_line_length).map(|iter| Self {
+            iter,
+            current_word: None,
+            tag_name: None,
+            pending: None,
+            segment: false,
+            identifiers: false,
+            code: false,
+            parts: VecDeque::new(),
+        })
+    }
+
+    /// Leave out the parts of the text that aren't prose in `syntax`.
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.iter.set_syntax(syntax);
+        self
+    }
+
+    /// Split runs of scripts written without spaces between words, such as
+    /// Chinese and Japanese, into words with [`segment::words`] rather than
+    /// skipping them.
+    pub fn with_segmentation(mut self, segment: bool) -> Self {
+        self.segment = segment;
+        self
+    }
+
+    /// Split camelCase words, as in identifiers, into the words they're
+    /// made of, such as `parse` and `Html` of `parseHtml` and `HTML` and
+    /// `Parser` of `HTMLParser`.
+    pub fn with_identifiers(mut self, identifiers: bool) -> Self {
+        self.identifiers = identifiers;
+        self
+    }
+
+    /// Read the text as source code: within double quotes, as in string
+    /// literals, escape sequences and format specifiers are left out of the
+    /// words they touch, and Doxygen and Javadoc tags of comments are
+    /// skipped along with the names following them.
+    pub fn with_code(mut self, code: bool) -> Self {
+        self.code = code;
+        self
     }
 
     fn make_word_at_line(
@@ -99,67 +298,313 @@ impl<'a> Lexer<'a> {
 }
 
 fn is_wordchar(c: char) -> bool {
-    c.is_alphabetic() || c == '\''
+    (c.is_alphabetic() || c == '\'') && !is_letter_symbol(c)
+}
+
+/// Whether `c` is a letter used as a symbol or emoji, such as `ℹ`, `Ⓜ` or
+/// `🅰`, rather than in words.
+fn is_letter_symbol(c: char) -> bool {
+    matches!(
+        c,
+        '\u{2139}' | '\u{24B6}'..='\u{24E9}' | '\u{1F130}'..='\u{1F189}'
+    )
+}
+
+/// Whether `c` continues a word without starting one: combining marks not
+/// counted as alphabetic, such as Hebrew cantillation and the accents of
+/// decomposed letters, and the Hebrew geresh and gershayim of `צה״ל`.
+fn is_word_continuation(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{0483}'..='\u{0489}'
+            | '\u{0591}'..='\u{05BD}'
+            | '\u{05BF}'
+            | '\u{05C1}'..='\u{05C2}'
+            | '\u{05C4}'..='\u{05C5}'
+            | '\u{05C7}'
+            | '\u{05F3}'..='\u{05F4}'
+            | '\u{0610}'..='\u{061A}'
+            | '\u{064B}'..='\u{065F}'
+            | '\u{06D6}'..='\u{06DC}'
+            | '\u{06DF}'..='\u{06E8}'
+            | '\u{06EA}'..='\u{06ED}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    ) || is_wordchar(c)
+}
+
+/// Whether `c` belongs to a script written without spaces between words:
+/// Han, Hiragana, Katakana, Hangul, Thai, Lao, Khmer or Myanmar. Words are
+/// never made of these and other letters together.
+fn is_unspaced(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0E00}'..='\u{0EFF}'
+            | '\u{1000}'..='\u{109F}'
+            | '\u{1100}'..='\u{11FF}'
+            | '\u{1780}'..='\u{17FF}'
+            | '\u{3005}'
+            | '\u{3040}'..='\u{30FF}'
+            | '\u{3130}'..='\u{318F}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF66}'..='\u{FF9F}'
+            | '\u{20000}'..='\u{3134F}'
+    )
+}
+
+/// Whether `c` is a zero-width joiner or non-joiner, part of a word only
+/// between its letters, as in the Persian `می‌خواهم`.
+fn is_joiner(c: char) -> bool {
+    matches!(c, '\u{200C}' | '\u{200D}')
+}
+
+/// Doxygen and Javadoc tags followed by the name of what they document or
+/// refer to, or by code.
+const NAMING_TAGS: &[&str] = &[
+    "addtogroup",
+    "c",
+    "class",
+    "def",
+    "defgroup",
+    "exception",
+    "fn",
+    "ingroup",
+    "link",
+    "linkplain",
+    "p",
+    "param",
+    "retval",
+    "sa",
+    "see",
+    "struct",
+    "throw",
+    "throws",
+    "tparam",
+    "var",
+];
+
+/// Other Doxygen and Javadoc tags.
+const TAGS: &[&str] = &[
+    "author",
+    "brief",
+    "code",
+    "deprecated",
+    "details",
+    "endcode",
+    "example",
+    "file",
+    "inheritDoc",
+    "internal",
+    "note",
+    "post",
+    "pre",
+    "return",
+    "returns",
+    "since",
+    "todo",
+    "value",
+    "version",
+    "warning",
+];
+
+impl<'a> Lexer<'a> {
+    /// The next word along with the character before it. Within quotes in
+    /// source code, the escape sequence or format specifier the word starts
+    /// with is left out, which may leave it empty.
+    fn next_word(&mut self) -> Option<(Word<'a>, char)> {
+        let (mut begin, previous, escape) = match self.pending.take() {
+            Some(pending) => pending,
+            None => loop {
+                let previous = self.iter.previous;
+                let escape = self.iter.escape();
+                let charpos = self.iter.next()?;
+                if is_wordchar(charpos.char) {
+                    break (charpos, previous, escape);
+                }
+            },
+        };
+
+        let current_line = self.iter.current_line;
+        let unspaced = is_unspaced(begin.char);
+
+        // Joiners are passed over, so that the word only includes those
+        // followed by more of it
+        let mut end = begin;
+        loop {
+            let previous = self.iter.previous;
+            let escape = self.iter.escape();
+            let Some(charpos) = self.iter.next() else {
+                break;
+            };
+            if is_joiner(charpos.char) {
+                continue;
+            }
+            if !is_word_continuation(charpos.char) {
+                break;
+            }
+            if is_unspaced(charpos.char) != unspaced {
+                self.pending = Some((charpos, previous, escape));
+                break;
+            }
+            end = charpos;
+        }
+
+        end.position.character += end.char.len_utf16() as u32;
+        end.offset += end.char.len_utf8();
+
+        if let Some(escape) = escape.filter(|_| self.code) {
+            // Escapes and specifiers are ASCII, one code unit per byte
+            let text = &current_line[begin.offset..end.offset];
+            let length = escape_length(escape, text);
+            begin.offset += length;
+            begin.position.character += length as u32;
+        }
+        let word = Self::make_word_at_line(current_line, begin, end);
+        Some((word, previous))
+    }
+}
+
+/// The length of the escape sequence or format specifier at the start of
+/// `word` after `escape`, such as the `n` of `\nHello` or the `s` of
+/// `%sHello`.
+fn escape_length(escape: char, word: &str) -> usize {
+    let bytes = word.as_bytes();
+    let hex = |max| {
+        bytes[1..]
+            .iter()
+            .take(max)
+            .take_while(|byte| byte.is_ascii_hexdigit())
+            .count()
+    };
+    match (escape, bytes[0]) {
+        ('\\', b'x') => 1 + hex(2),
+        ('\\', b'u') => 1 + hex(4),
+        ('\\', b'U') => 1 + hex(8),
+        ('\\', b'a' | b'b' | b'e' | b'f' | b'n' | b'r' | b't' | b'v') => 1,
+        ('%', _) => {
+            let modifiers = bytes
+                .iter()
+                .take_while(|byte| b"hlLqjzt".contains(byte))
+                .count();
+            match bytes.get(modifiers) {
+                Some(byte) if b"diouxXeEfFgGaAcspn".contains(byte) => {
+                    modifiers + 1
+                }
+                _ => 0,
+            }
+        }
+        _ => 0,
+    }
 }
 
 impl<'a> StreamingIterator for Lexer<'a> {
     type Item = Word<'a>;
 
     fn get(&self) -> Option<&Self::Item> {
-        match &self.current_word {
-            None => None,
-            Some(word) => Some(&word),
-        }
+        self.current_word.as_ref()
     }
 
     fn advance(&mut self) {
-        let begin = loop {
-            match self.iter.next() {
-                None => {
-                    self.current_word = None;
-                    return;
-                }
-                Some(charpos) => {
-                    if is_wordchar(charpos.char) {
-                        break charpos;
-                    }
+        self.current_word = loop {
+            if let Some(word) = self.parts.pop_front() {
+                break Some(word);
+            }
+            let Some((word, previous)) = self.next_word() else {
+                break None;
+            };
+            if word.text.starts_with(is_unspaced) {
+                if self.segment {
+                    let spans = segment::words(word.text).unwrap_or_default();
+                    self.parts.extend(parts(word, spans));
                 }
+                continue;
+            }
+            // Skip the letters of ordinals and numbers with units, such as
+            // `2nd`, `10px` and `3.5mm`
+            if previous.is_numeric() || word.text.is_empty() {
+                continue;
             }
-        };
-
-        let current_line = self.iter.current_line;
 
-        let mut end = 'find_end: {
-            let mut tmp = match self.iter.next() {
-                None => break 'find_end begin,
-                Some(charpos) => {
-                    if is_wordchar(charpos.char) {
-                        charpos
-                    } else {
-                        break 'find_end begin;
-                    }
+            // Skip documentation tags and the names following them, but
+            // not their descriptions
+            let line = word.range.start.line;
+            if self.tag_name.take() == Some(line) {
+                if previous == '[' {
+                    // The direction of `@param[in] x`
+                    self.tag_name = Some(line);
                 }
-            };
-
-            loop {
-                match self.iter.next() {
-                    None => break 'find_end tmp,
-                    Some(charpos) => {
-                        if !is_wordchar(charpos.char) {
-                            break 'find_end tmp;
-                        }
-                        tmp = charpos;
-                    }
+                continue;
+            }
+            if self.code && matches!(previous, '@' | '\\') {
+                if NAMING_TAGS.contains(&word.text) {
+                    self.tag_name = Some(line);
+                    continue;
+                }
+                if TAGS.contains(&word.text) {
+                    continue;
+                }
+            }
+            if self.identifiers {
+                let spans = case_parts(word.text);
+                if spans.len() > 1 {
+                    self.parts.extend(parts(word, spans));
+                    continue;
                 }
             }
+            break Some(word);
         };
+    }
+}
 
-        end.position.character += 1;
-        end.offset += end.char.len_utf8();
-
-        self.current_word =
-            Some(Self::make_word_at_line(current_line, begin, end));
+/// The byte ranges of the camelCase parts of `word`, split before an
+/// uppercase letter following a lowercase one, or preceding one after
+/// another uppercase letter, unless that's the `s` of a plural as `URLs`.
+fn case_parts(word: &str) -> Vec<ops::Range<usize>> {
+    let chars = word.char_indices().collect::<Vec<_>>();
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for index in 1..chars.len() {
+        let (offset, char) = chars[index];
+        let previous = chars[index - 1].1;
+        let next = chars.get(index + 1).map(|&(_, next)| next);
+        let plural = next == Some('s') && index + 2 == chars.len();
+        let boundary = char.is_uppercase()
+            && (previous.is_lowercase()
+                || previous.is_uppercase()
+                    && next.is_some_and(char::is_lowercase)
+                    && !plural);
+        if boundary {
+            spans.push(start..offset);
+            start = offset;
+        }
     }
+    spans.push(start..word.len());
+    spans
+}
+
+/// The words at the byte ranges `spans` of `word`.
+fn parts(
+    word: Word,
+    spans: Vec<ops::Range<usize>>,
+) -> impl Iterator<Item = Word> {
+    let position = move |offset| Position {
+        line: word.range.start.line,
+        character: word.range.start.character
+            + word.text[..offset].encode_utf16().count() as u32,
+    };
+    spans.into_iter().map(move |span| Word {
+        text: &word.text[span.clone()],
+        range: Range {
+            start: position(span.start),
+            end: position(span.end),
+        },
+    })
 }
 
 #[cfg(test)]
@@ -167,7 +612,7 @@ mod test {
 
     use super::*;
 
-    fn case<'a>(lexer: &'a mut Lexer, expected_word: &str, line: u32) {
+    fn case(lexer: &mut Lexer, expected_word: &str, line: u32) {
         let word = lexer.next().unwrap();
         assert_eq!(word.text, expected_word);
         assert_eq!(word.range.start.line, line);
@@ -195,6 +640,280 @@ mod test {
         case(&mut lexer, "the", 2);
         case(&mut lexer, "first", 2);
         case(&mut lexer, "line", 2);
-        assert!(matches!(lexer.next(), None));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn line_breaks() {
+        let mut lexer = Lexer::new("first\nsecond\r\nthird").unwrap();
+
+        case(&mut lexer, "first", 0);
+        case(&mut lexer, "second", 1);
+        case(&mut lexer, "third", 2);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn long_lines() {
+        let text = "first\nwrld wrld wrld\nthird";
+        let mut lexer = Lexer::with_max_line_length(text, 10).unwrap();
+
+        case(&mut lexer, "first", 0);
+        case(&mut lexer, "third", 2);
+        assert!(lexer.next().is_none());
+        assert_eq!(super::long_lines(text, 10).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let mut lexer = Lexer::new("😀 \u{1d400}b c").unwrap();
+
+        let word = lexer.next().unwrap();
+        assert_eq!(word.text, "\u{1d400}b");
+        assert_eq!(word.range.start.character, 3);
+        assert_eq!(word.range.end.character, 6);
+        assert_eq!(lexer.next().unwrap().range.start.character, 7);
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let text = concat!(
+            "\"Hello\\nWorld\\t\\xffwrld\"\n",
+            "printf(\"%sHello %lu\\n\", name);\n",
+            "\\newline %sure\n",
+            "\"C:\\\\Users\\\\wrld\"\n",
+            "\"\\\\network path\"\n",
+            "let a = \"\\\\\"; \"don't \\n stop\"",
+        );
+        let mut lexer = Lexer::new(text).unwrap().with_code(true);
+
+        case(&mut lexer, "Hello", 0);
+        case(&mut lexer, "World", 0);
+        case(&mut lexer, "wrld", 0);
+        case(&mut lexer, "printf", 1);
+        case(&mut lexer, "Hello", 1);
+        case(&mut lexer, "name", 1);
+        case(&mut lexer, "newline", 2);
+        case(&mut lexer, "sure", 2);
+        case(&mut lexer, "C", 3);
+        case(&mut lexer, "Users", 3);
+        case(&mut lexer, "wrld", 3);
+        case(&mut lexer, "network", 4);
+        case(&mut lexer, "path", 4);
+        case(&mut lexer, "let", 5);
+        case(&mut lexer, "a", 5);
+        case(&mut lexer, "don't", 5);
+        case(&mut lexer, "stop", 5);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn quoted_prose() {
+        let text = "She said \"%does\" and \"\\table\"";
+        let words = Lexer::new(text)
+            .unwrap()
+            .map_deref(|word| word.text)
+            .collect::<Vec<_>>();
+
+        assert_eq!(words, ["She", "said", "does", "and", "table"]);
+    }
+
+    #[test]
+    fn documentation_tags() {
+        let text = concat!(
+            "/**\n",
+            " * @brief Opne a file.\n",
+            " * @param[in] pth The fiel.\n",
+            " * @return {@link Handel} or \\c null\n",
+            " * @param\n",
+            " * wrld\n",
+            " */",
+        );
+        let words = Lexer::new(text)
+            .unwrap()
+            .with_code(true)
+            .map_deref(|word| word.text)
+            .collect::<Vec<_>>();
+
+        assert_eq!(words, ["Opne", "a", "file", "The", "fiel", "or", "wrld"]);
+    }
+
+    #[test]
+    fn tags_in_prose() {
+        let text = "Ping me @param or see \\return wrld";
+        let words = Lexer::new(text)
+            .unwrap()
+            .map_deref(|word| word.text)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            words,
+            ["Ping", "me", "param", "or", "see", "return", "wrld"]
+        );
+    }
+
+    #[test]
+    fn rustdoc() {
+        let text = concat!(
+            "/// Maek a [`Wrld`] from [Vec] or [the docs](crate::wrld).\n",
+            "/// See [wrld][Wrld] and `wrld`.\n",
+            "///\n",
+            "/// ```\n",
+            "/// let wrld = Wrld::new();\n",
+            "/// ```\n",
+            "fn wrld() {}",
+        );
+        let words = Lexer::new(text)
+            .unwrap()
+            .with_syntax(Syntax::Rust)
+            .map_deref(|word| word.text)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            words,
+            [
+                "Maek", "a", "from", "or", "the", "docs", "See", "wrld", "and",
+                "fn", "wrld"
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_with_units() {
+        let text = "the 1st and 2nd, 10px wide 4GB of 3.5mm\nwrld 90's";
+        let mut lexer = Lexer::new(text).unwrap();
+
+        case(&mut lexer, "the", 0);
+        case(&mut lexer, "and", 0);
+        case(&mut lexer, "wide", 0);
+        case(&mut lexer, "of", 0);
+        case(&mut lexer, "wrld", 1);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn unspaced_scripts() {
+        let text = "hello世界 and こんにちは, wrld\n안녕하세요 สวัสดี カタカナx";
+        let mut lexer = Lexer::new(text).unwrap();
+
+        case(&mut lexer, "hello", 0);
+        case(&mut lexer, "and", 0);
+        case(&mut lexer, "wrld", 0);
+        case(&mut lexer, "x", 1);
+        assert!(lexer.next().is_none());
+    }
+
+    #[cfg(feature = "segmentation")]
+    #[test]
+    fn segmentation() {
+        let text = "a😀안녕하세요 세계 カタカナx";
+        let mut lexer = Lexer::new(text).unwrap().with_segmentation(true);
+        let mut words = Vec::new();
+        while let Some(word) = lexer.next() {
+            words.push((word.text, word.range.start.character));
+        }
+
+        assert_eq!(
+            words,
+            [
+                ("a", 0),
+                ("안녕하세요", 3),
+                ("세계", 9),
+                ("カタカナ", 12),
+                ("x", 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        let text = "parseHtml HTMLParser get_userName iPhone URLs Élan";
+        let mut lexer = Lexer::new(text).unwrap().with_identifiers(true);
+        let mut words = Vec::new();
+        while let Some(word) = lexer.next() {
+            words.push((word.text, word.range.start.character));
+        }
+
+        assert_eq!(
+            words,
+            [
+                ("parse", 0),
+                ("Html", 5),
+                ("HTML", 10),
+                ("Parser", 14),
+                ("get", 21),
+                ("user", 25),
+                ("Name", 29),
+                ("i", 34),
+                ("Phone", 35),
+                ("URLs", 41),
+                ("Élan", 46)
+            ]
+        );
+    }
+
+    #[test]
+    fn right_to_left() {
+        let text = concat!(
+            "\u{200F}שָׁלוֹם 😀 hello \u{2067}עוֹלָם\u{2069} צה״ל\n",
+            "وَرَقَة and بِسْمِ\n",
+            "می\u{200C}خواهم \u{200C}x\u{200C} e\u{0301}te\n",
+            "בְּרֵאשִׁ֖ית"
+        );
+        let expected = [
+            "שָׁלוֹם",
+            "hello",
+            "עוֹלָם",
+            "צה״ל",
+            "وَرَقَة",
+            "and",
+            "بِسْمِ",
+            "می\u{200C}خواهم",
+            "x",
+            "e\u{0301}te",
+            "בְּרֵאשִׁ֖ית",
+        ];
+        assert_words(text, &expected);
+    }
+
+    #[test]
+    fn emoji() {
+        let text = concat!(
+            "wrld😀 👍🏽wrld ℹ️ 🅰️wrld Ⓜ\n",
+            "1️⃣ wrld #️⃣wrld 👨\u{200D}👩\u{200D}👧 wrld\u{200D}👧\n",
+            "🇸🇪wrld🏴\u{E0067}\u{E0062}\u{E0065}\u{E006E}\u{E0067}\u{E007F} ✈\u{FE0E}wrld"
+        );
+        assert_words(text, &["wrld"; 8]);
+    }
+
+    /// Assert that the words of `text` are `expected`, and that their
+    /// ranges pick them out of their lines in UTF-16 code units.
+    fn assert_words(text: &str, expected: &[&str]) {
+        let lines = text.lines().collect::<Vec<_>>();
+        let mut lexer = Lexer::new(text).unwrap();
+        for &expected in expected {
+            let word = lexer.next().unwrap();
+            assert_eq!(word.text, expected);
+            let line = lines[word.range.start.line as usize]
+                .encode_utf16()
+                .collect::<Vec<_>>();
+            let start = word.range.start.character as usize;
+            let end = word.range.end.character as usize;
+            assert_eq!(
+                String::from_utf16(&line[start..end]).unwrap(),
+                expected
+            );
+        }
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn prose_only() {
+        let text = "Hi,\r\n> tihs quote\nsee https://teh.io, ok\n";
+        assert_eq!(
+            prose(text, &Syntax::Email, usize::MAX),
+            "Hi,\r\n            \nsee                 ok\n"
+        );
+        assert_eq!(prose("éa\nab", &Syntax::Plain, 2), "   \nab");
     }
 }
@@ -0,0 +1,36 @@
+//! A language server checking spelling with zspell. The `zspell_lsp`
+//! binary is a thin command line wrapper around this library, which can
+//! also be used to embed the server through
+//! [`Server::run_with_connection`](server::Server::run_with_connection).
+
+pub mod affixes;
+pub mod backend;
+mod baseline;
+pub mod batch;
+mod cache;
+mod casing;
+mod changes;
+pub mod checker;
+pub mod config;
+mod crash;
+pub mod dictionaries;
+mod dispatch;
+pub mod document;
+pub mod fix;
+mod incremental;
+mod intern;
+mod keywords;
+pub mod lexer;
+pub mod logger;
+pub mod metrics;
+mod personal;
+mod publisher;
+mod report;
+mod segment;
+pub mod server;
+mod skip;
+pub mod syntax;
+mod thesaurus;
+pub mod transport;
+pub mod words;
+mod workspace;
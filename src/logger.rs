@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of recent records kept for crash reports.
+const RECENT_RECORDS: usize = 200;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// The most recent log records, oldest first.
+pub fn recent() -> Vec<String> {
+    RECENT
+        .lock()
+        .map_or_else(|_| Vec::new(), |recent| recent.iter().cloned().collect())
+}
+
+/// Logger writing timestamped records with their module target to standard
+/// error or to a file.
+pub struct Logger {
+    level: LevelFilter,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Logger {
+    pub fn init(level: LevelFilter, file: Option<&Path>) -> Result<()> {
+        let output: Box<dyn Write + Send> = match file {
+            None => Box::new(std::io::stderr()),
+            Some(path) => Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| {
+                        format!("unable to open log file {}", path.display())
+                    })?,
+            ),
+        };
+
+        log::set_boxed_logger(Box::new(Logger {
+            level,
+            output: Mutex::new(output),
+        }))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {}: {}",
+            timestamp(SystemTime::now()),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if let Ok(mut output) = self.output.lock() {
+            _ = writeln!(output, "{}", line);
+        }
+        if let Ok(mut recent) = RECENT.lock() {
+            if recent.len() == RECENT_RECORDS {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut output) = self.output.lock() {
+            _ = output.flush();
+        }
+    }
+}
+
+/// Format a time as an RFC 3339 UTC timestamp with millisecond precision.
+fn timestamp(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = duration.as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since the epoch, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        duration.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_713_571_200_250);
+        assert_eq!(timestamp(time), "2024-04-20T00:00:00.250Z");
+
+        let time = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(timestamp(time), "2000-02-29T12:34:56.000Z");
+    }
+}
@@ -1,85 +1,218 @@
-use clap::Parser;
-use log::{error, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
-use server::Server;
+use clap::{Parser, Subcommand};
+use log::{error, LevelFilter};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
 use std::process;
-
-mod lexer;
-mod server;
+use std::time::Duration;
+use zspell_lsp::checker::Checker;
+use zspell_lsp::config::{self, Config};
+use zspell_lsp::logger::Logger;
+use zspell_lsp::server::{self, Server};
+use zspell_lsp::words::{self, WordsCommand};
+use zspell_lsp::{batch, dictionaries, fix};
 
 #[derive(Parser, Debug)]
 #[command(about, long_about = None)]
 pub struct Options {
-    /// Specify affix file.
-    #[arg(short, long, default_value_t = String::from("./index.aff"))]
-    affix: String,
+    /// Specify affix file [default: ./index.aff].
+    #[arg(short, long, global = true, env = "ZSPELL_LSP_AFFIX")]
+    affix: Option<PathBuf>,
 
-    /// Specify dictionary file.
-    #[arg(short, long, default_value_t = String::from("./index.dic"))]
-    dictionary: String,
-}
+    /// Specify dictionary file [default: ./index.dic].
+    #[arg(short, long, global = true, env = "ZSPELL_LSP_DICTIONARY")]
+    dictionary: Option<PathBuf>,
 
-struct Logger {}
-static LOGGER: Logger = Logger {};
+    /// Load the dictionary for this locale (e.g. en_US) from the search
+    /// paths instead of --affix and --dictionary.
+    #[arg(short, long, global = true, env = "ZSPELL_LSP_LANGUAGE")]
+    language: Option<String>,
 
-impl Logger {
-    fn init() -> Result<(), SetLoggerError> {
-        log::set_logger(&LOGGER)
-            .map(|()| log::set_max_level(LevelFilter::Debug))
-    }
+    /// Additional directory to search for dictionaries, may be repeated.
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        env = "ZSPELL_LSP_DICTIONARY_PATH"
+    )]
+    dictionary_path: Vec<PathBuf>,
+
+    /// Write log records to this file instead of standard error.
+    #[arg(long, global = true, env = "ZSPELL_LSP_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Maximum level of log records (off, error, warn, info, debug, trace).
+    #[arg(
+        long,
+        global = true,
+        default_value_t = LevelFilter::Warn,
+        env = "ZSPELL_LSP_LOG_LEVEL"
+    )]
+    log_level: LevelFilter,
+
+    /// Log a summary of the server metrics at the info level every this
+    /// many seconds.
+    #[arg(long, value_name = "SECONDS", env = "ZSPELL_LSP_METRICS_INTERVAL")]
+    metrics_interval: Option<u64>,
+
+    /// Memory for cached results, in MiB, less what open documents take.
+    /// Cached results are evicted to stay within it, open documents are
+    /// kept whatever their size.
+    #[arg(
+        long,
+        value_name = "MIB",
+        default_value_t = server::DEFAULT_MEMORY_BUDGET >> 20,
+        env = "ZSPELL_LSP_MEMORY_BUDGET"
+    )]
+    memory_budget: usize,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl Log for Logger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Debug
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check files and print misspellings instead of running the server.
+    Check(batch::CheckArgs),
+    /// Step through misspellings and fix them in place.
+    Fix(fix::FixArgs),
+    /// List the dictionaries available in the search paths.
+    ListDictionaries,
+    /// Review the words added to the personal and team dictionaries.
+    Words {
+        #[command(subcommand)]
+        command: WordsCommand,
+    },
+    /// Print the effective configuration after merging the configuration
+    /// files and command line options.
+    DumpConfig {
+        /// Output format.
+        #[arg(long, value_enum, default_value_t)]
+        format: config::DumpFormat,
+    },
+}
 
-    fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            eprintln!("[{}]: {}", record.level(), record.args());
+impl Options {
+    /// Settings given on the command line, as a configuration layer.
+    fn settings(&self) -> Value {
+        let mut settings = serde_json::Map::new();
+        if let Some(language) = &self.language {
+            settings.insert("language".into(), json!(language));
+        }
+        if let Some(affix) = &self.affix {
+            settings.insert("affix".into(), json!(affix));
+        }
+        if let Some(dictionary) = &self.dictionary {
+            settings.insert("dictionary".into(), json!(dictionary));
         }
+        if !self.dictionary_path.is_empty() {
+            settings
+                .insert("dictionaryPaths".into(), json!(self.dictionary_path));
+        }
+        Value::Object(settings)
     }
-
-    fn flush(&self) {}
 }
 
 fn main() {
-    Logger::init().unwrap();
-
     let options = Options::parse();
-    _ = options;
 
-    let affix_str = match std::fs::read_to_string(&options.affix) {
-        Ok(affix) => affix,
-        Err(e) => {
-            error!("Unable to open affix file {}: {}", options.affix, e);
+    if let Err(err) =
+        Logger::init(options.log_level, options.log_file.as_deref())
+    {
+        eprintln!("Unable to initialize logging: {:#}", err);
+        process::exit(-1);
+    }
+
+    let mut settings = match config::load_files(Path::new(".")) {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!("{:#}", err);
             process::exit(-1);
         }
     };
+    config::merge(&mut settings, options.settings());
 
-    let dict_str = match std::fs::read_to_string(&options.dictionary) {
-        Ok(dict) => dict,
-        Err(e) => {
-            error!(
-                "Unable to open dictionary file {}: {}",
-                options.dictionary, e
-            );
+    let mut config = match Config::from_value(settings.clone()) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Invalid configuration: {}", err);
             process::exit(-1);
         }
     };
+    config
+        .affix
+        .get_or_insert_with(|| PathBuf::from(config::DEFAULT_AFFIX));
+    config
+        .dictionary
+        .get_or_insert_with(|| PathBuf::from(config::DEFAULT_DICTIONARY));
 
-    let dict = match zspell::builder()
-        .config_str(&affix_str)
-        .dict_str(&dict_str)
-        .build()
-    {
-        Ok(dict) => dict,
+    if let Some(Command::DumpConfig { format }) = &options.command {
+        match config::dump(&config, *format) {
+            Ok(text) => print!("{}", text),
+            Err(err) => {
+                error!("{:#}", err);
+                process::exit(-1);
+            }
+        }
+        process::exit(0);
+    }
+
+    let search_paths = dictionaries::search_paths(&config.dictionary_paths);
+
+    if let Some(Command::ListDictionaries) = &options.command {
+        dictionaries::list(&search_paths);
+        process::exit(0);
+    }
+
+    if let Some(Command::Words { command }) = &options.command {
+        if let Err(err) = words::run(&config, command) {
+            error!("{:#}", err);
+            process::exit(2);
+        }
+        process::exit(0);
+    }
+
+    let backend = match dictionaries::default_backend(&config, &search_paths) {
+        Ok(backend) => backend,
         Err(err) => {
-            error!("Unable to create dictionary: {}", err);
+            error!("{:#}", err);
             process::exit(-1);
         }
     };
 
-    let mut server = match Server::new(dict) {
+    let mut checker = Checker::new(backend, config.language.clone());
+    if let Err(err) = dictionaries::load_languages(&mut checker, &config) {
+        error!("{:#}", err);
+        process::exit(-1);
+    }
+    // The server loads the wordlists of each workspace folder itself
+    if matches!(options.command, Some(Command::Check(_) | Command::Fix(_))) {
+        let affixes = dictionaries::affixes(&config);
+        if let Err(err) = checker.load_wordlists(&config, affixes.as_ref()) {
+            error!("{:#}", err);
+            process::exit(-1);
+        }
+    }
+
+    if let Some(Command::Check(args)) = &options.command {
+        match batch::check(&checker, &config, args) {
+            Ok(count) if args.passed(count) => process::exit(0),
+            Ok(_) => process::exit(1),
+            Err(err) => {
+                error!("{:#}", err);
+                process::exit(2);
+            }
+        }
+    }
+
+    if let Some(Command::Fix(args)) = &options.command {
+        if let Err(err) = fix::fix(&checker, &config, args) {
+            error!("{:#}", err);
+            process::exit(2);
+        }
+        process::exit(0);
+    }
+
+    let mut server = match Server::new(checker, options.settings()) {
         Ok(server) => server,
         Err(e) => {
             error!("Couldn't initialize server: {}", e);
@@ -87,6 +220,11 @@ fn main() {
         }
     };
 
+    server.set_memory_budget(options.memory_budget << 20);
+    if let Some(interval) = options.metrics_interval {
+        server.log_metrics_every(Duration::from_secs(interval));
+    }
+
     if let Err(err) = server.run() {
         error!("Server error: {}", err);
         process::exit(-1);
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Counters and timings of the server, shared by every thread through
+/// [`metrics`].
+pub struct Metrics {
+    checks: AtomicU64,
+    /// Time spent checking documents, in microseconds.
+    check_time: AtomicU64,
+    handlers: Mutex<BTreeMap<String, Handler>>,
+    caches: Mutex<BTreeMap<&'static str, Cache>>,
+}
+
+/// Messages of one method handled by the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Handler {
+    pub count: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Lookups in one cache.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Cache {
+    pub hits: u64,
+    pub misses: u64,
+    /// The fraction of lookups that were hits.
+    pub hit_rate: f64,
+}
+
+/// A copy of the metrics at one point in time, the result of the
+/// `zspell/metrics` request.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// Requests and notifications handled.
+    pub messages: u64,
+    /// Document versions checked.
+    pub checks: u64,
+    pub check_time_ms: f64,
+    /// Messages handled and their latencies by method.
+    pub handlers: BTreeMap<String, Handler>,
+    /// Lookups in each cache by name.
+    pub caches: BTreeMap<String, Cache>,
+}
+
+static METRICS: Metrics = Metrics::new();
+
+/// The metrics of the process.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            checks: AtomicU64::new(0),
+            check_time: AtomicU64::new(0),
+            handlers: Mutex::new(BTreeMap::new()),
+            caches: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Record a request or notification handled in `elapsed`.
+    pub fn handled(&self, method: &str, elapsed: Duration) {
+        let mut handlers = self.handlers.lock().unwrap();
+        let handler = handlers.entry(method.to_string()).or_default();
+        handler.count += 1;
+        handler.total_ms += millis(elapsed);
+        handler.max_ms = handler.max_ms.max(millis(elapsed));
+    }
+
+    /// Record a document checked in `elapsed`.
+    pub fn checked(&self, elapsed: Duration) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        self.check_time
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Record a lookup in the cache named `cache`.
+    pub fn cache_lookup(&self, cache: &'static str, hit: bool) {
+        let mut caches = self.caches.lock().unwrap();
+        let cache = caches.entry(cache).or_default();
+        if hit {
+            cache.hits += 1;
+        } else {
+            cache.misses += 1;
+        }
+        let lookups = cache.hits + cache.misses;
+        cache.hit_rate = cache.hits as f64 / lookups as f64;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let handlers = self.handlers.lock().unwrap().clone();
+        let caches = self
+            .caches
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, cache)| (name.to_string(), *cache))
+            .collect();
+        Snapshot {
+            messages: handlers.values().map(|handler| handler.count).sum(),
+            checks: self.checks.load(Ordering::Relaxed),
+            check_time_ms: self.check_time.load(Ordering::Relaxed) as f64
+                / 1000.0,
+            handlers,
+            caches,
+        }
+    }
+}
+
+impl Snapshot {
+    /// A one line summary for the log.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} messages, {} checks in {:.1}ms",
+            self.messages, self.checks, self.check_time_ms
+        );
+        let slowest = self
+            .handlers
+            .iter()
+            .max_by(|(_, a), (_, b)| a.max_ms.total_cmp(&b.max_ms));
+        if let Some((method, handler)) = slowest {
+            summary +=
+                &format!(", slowest {} in {:.1}ms", method, handler.max_ms);
+        }
+        for (name, cache) in &self.caches {
+            summary += &format!(
+                ", {} cache hit rate {:.0}%",
+                name,
+                cache.hit_rate * 100.0
+            );
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn snapshot() {
+        let metrics = Metrics::new();
+        metrics.handled("a", Duration::from_millis(2));
+        metrics.handled("a", Duration::from_millis(4));
+        metrics.handled("b", Duration::from_millis(1));
+        metrics.checked(Duration::from_millis(3));
+        metrics.cache_lookup("paragraphs", true);
+        metrics.cache_lookup("paragraphs", true);
+        metrics.cache_lookup("paragraphs", false);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.messages, 3);
+        assert_eq!(snapshot.checks, 1);
+        assert_eq!(snapshot.handlers["a"].count, 2);
+        assert_eq!(snapshot.handlers["a"].max_ms, 4.0);
+        assert_eq!(
+            snapshot.summary(),
+            "3 messages, 1 checks in 3.0ms, slowest a in 4.0ms, \
+             paragraphs cache hit rate 67%"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::affixes::Affixes;
+use crate::config::Config;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Words accepted in addition to the dictionary, optionally persisted to a
+/// plain wordlist file with one word per line. Words may be followed by
+/// affix flags as in dictionary files, `word/SD`, to accept their
+/// inflected forms as well.
+#[derive(Default, Clone)]
+pub struct PersonalDictionary {
+    path: Option<PathBuf>,
+    words: HashSet<String>,
+    /// The words as added, without their inflected forms.
+    entries: Vec<String>,
+    /// Affix flags of the words added with some.
+    flags: HashMap<String, String>,
+}
+
+impl PersonalDictionary {
+    /// Read the words of the file at `path`, inflecting those with flags by
+    /// the rules of `affixes`. Without them only the words themselves are
+    /// accepted.
+    pub fn load(
+        path: Option<PathBuf>,
+        affixes: Option<&Affixes>,
+    ) -> Result<Self> {
+        let mut personal = Self {
+            path,
+            ..Default::default()
+        };
+        let Some(path) = &personal.path else {
+            return Ok(personal);
+        };
+        if !path.exists() {
+            return Ok(personal);
+        }
+        let text = std::fs::read_to_string(path)?;
+        for line in text.lines().map(str::trim) {
+            let (word, flags) = line.split_once('/').unwrap_or((line, ""));
+            if word.is_empty() {
+                continue;
+            }
+            if personal.words.insert(word.to_string()) {
+                personal.entries.push(word.to_string());
+            }
+            if flags.is_empty() {
+                continue;
+            }
+            if let Some(affixes) = affixes {
+                personal.words.extend(affixes.forms(word, flags));
+            }
+            personal.flags.insert(word.to_string(), flags.to_string());
+        }
+        Ok(personal)
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    /// Accept the words of a personal dictionary of another spell checker,
+    /// returning how many it has. They aren't written to the backing file.
+    pub fn import(&mut self, path: &Path) -> Result<usize> {
+        let text = std::fs::read_to_string(path)?;
+        let words = parse_import(&text);
+        let count = words.len();
+        self.words.extend(words);
+        Ok(count)
+    }
+
+    /// The number of words added, not counting their inflected forms.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The words as added, with their affix flags if they have some.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.entries.iter().map(|word| {
+            (word.as_str(), self.flags.get(word).map(String::as_str))
+        })
+    }
+
+    /// Add a word with affix `flags`, possibly none, accepting its inflected
+    /// `forms` as well. The word is appended to the backing file if there is
+    /// one, unless it's already there with the same flags.
+    pub fn add(
+        &mut self,
+        word: &str,
+        flags: &str,
+        forms: &[String],
+    ) -> Result<()> {
+        let added = self.words.insert(word.to_string());
+        if added {
+            self.entries.push(word.to_string());
+        }
+        self.words.extend(forms.iter().cloned());
+        let flagged = !flags.is_empty()
+            && self.flags.get(word).map(String::as_str) != Some(flags);
+        if !added && !flagged {
+            return Ok(());
+        }
+        if flagged {
+            self.flags.insert(word.to_string(), flags.to_string());
+        }
+        if let Some(path) = &self.path {
+            let mut file =
+                OpenOptions::new().create(true).append(true).open(path)?;
+            match flags {
+                "" => writeln!(file, "{}", word)?,
+                flags => writeln!(file, "{}/{}", word, flags)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The dictionaries `config` imports words from that exist.
+pub fn import_paths(config: &Config) -> Vec<PathBuf> {
+    let paths = match &config.import_dictionaries {
+        Some(paths) => paths.clone(),
+        None => default_imports(config.language.as_deref()),
+    };
+    paths.into_iter().filter(|path| path.is_file()).collect()
+}
+
+/// The default locations of the personal dictionaries of hunspell and
+/// LibreOffice.
+fn default_imports(language: Option<&str>) -> Vec<PathBuf> {
+    const WORDBOOK: &str = "LibreOffice/4/user/wordbook/standard.dic";
+    let mut paths = Vec::new();
+    paths.extend(std::env::var_os("WORDLIST").map(PathBuf::from));
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        paths.push(home.join(".hunspell_default"));
+        if let Some(language) = language {
+            paths.push(home.join(format!(".hunspell_{}", language)));
+        }
+        paths.push(home.join(".config").join(WORDBOOK.to_lowercase()));
+        paths.push(home.join("Library/Application Support").join(WORDBOOK));
+    }
+    if let Some(app_data) = std::env::var_os("APPDATA") {
+        paths.push(PathBuf::from(app_data).join(WORDBOOK));
+    }
+    paths
+}
+
+/// The accepted words of a LibreOffice user dictionary, recognized by its
+/// `OOoUserDict1` header, or of a hunspell personal dictionary.
+fn parse_import(text: &str) -> Vec<String> {
+    let mut lines = text.lines().map(str::trim);
+    if text.starts_with("OOoUserDict1") {
+        // Negative dictionaries list words to reject instead
+        let mut negative = false;
+        for line in lines.by_ref().take_while(|line| *line != "---") {
+            negative |= line == "type: negative";
+        }
+        if negative {
+            return Vec::new();
+        }
+        // `=` marks hyphenation points, `[]` hyphenation patterns
+        return lines
+            .filter(|line| !line.is_empty() && !line.contains('['))
+            .map(|line| line.replace('=', ""))
+            .collect();
+    }
+    // `*word` forbids a word, `word/model` inflects it like another one
+    lines
+        .filter(|line| !line.is_empty() && !line.starts_with('*'))
+        .filter_map(|line| line.split('/').next())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn imports() {
+        let libreoffice = "OOoUserDict1\nlang: <none>\ntype: positive\n---\n\
+                           zspell\nhy=phen\nZeit[r=ung]\n";
+        let negative = "OOoUserDict1\nlang: en-US\ntype: negative\n---\nteh\n";
+        let hunspell = "zspell\n*teh\nlsp/server\n";
+
+        assert_eq!(parse_import(libreoffice), ["zspell", "hyphen"]);
+        assert!(parse_import(negative).is_empty());
+        assert_eq!(parse_import(hunspell), ["zspell", "lsp"]);
+    }
+}
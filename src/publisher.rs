@@ -0,0 +1,109 @@
+//! Coalescing of `textDocument/publishDiagnostics` notifications, so that
+//! rechecking every open document after a settings or dictionary change
+//! doesn't flood the client.
+
+use lsp_types::Url;
+use std::time::{Duration, Instant};
+
+/// The diagnostics `D` of a document waiting to be published.
+#[derive(Debug)]
+pub struct Pending<D> {
+    pub uri: Url,
+    pub version: Option<i32>,
+    pub diagnostics: Vec<D>,
+}
+
+/// Diagnostics waiting to be published, at most one set per document,
+/// sent together no more often than every `interval`.
+pub struct Publisher<D> {
+    /// In the order their documents were first queued.
+    pending: Vec<Pending<D>>,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl<D> Publisher<D> {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            pending: Vec::new(),
+            interval,
+            last: None,
+        }
+    }
+
+    /// Queue the diagnostics of `uri`, replacing those still waiting.
+    pub fn push(
+        &mut self,
+        uri: Url,
+        version: Option<i32>,
+        diagnostics: Vec<D>,
+    ) {
+        let queued = Pending {
+            uri,
+            version,
+            diagnostics,
+        };
+        match self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.uri == queued.uri)
+        {
+            Some(pending) => *pending = queued,
+            None => self.pending.push(queued),
+        }
+    }
+
+    /// When the queued diagnostics are due, `None` if there are none.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(match self.last {
+            Some(last) => last + self.interval,
+            None => Instant::now(),
+        })
+    }
+
+    /// The queued diagnostics if they are due at `now`, emptying the
+    /// queue.
+    pub fn take_due(&mut self, now: Instant) -> Vec<Pending<D>> {
+        let due = self.last.is_none_or(|last| last + self.interval <= now);
+        if self.pending.is_empty() || !due {
+            return Vec::new();
+        }
+        self.last = Some(now);
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn coalescing() {
+        let interval = Duration::from_millis(100);
+        let mut publisher = Publisher::<()>::new(interval);
+        let uri = |name| Url::parse(&format!("file:///{}", name)).unwrap();
+        let start = Instant::now();
+
+        assert_eq!(publisher.deadline(), None);
+        publisher.push(uri("a"), Some(0), Vec::new());
+        assert_eq!(publisher.take_due(start).len(), 1);
+
+        publisher.push(uri("a"), Some(1), Vec::new());
+        publisher.push(uri("b"), Some(0), Vec::new());
+        publisher.push(uri("a"), Some(2), Vec::new());
+        assert_eq!(publisher.deadline(), Some(start + interval));
+        assert!(publisher.take_due(start + interval / 2).is_empty());
+
+        let published = publisher
+            .take_due(start + interval)
+            .into_iter()
+            .map(|pending| (pending.uri, pending.version))
+            .collect::<Vec<_>>();
+        assert_eq!(published, [(uri("a"), Some(2)), (uri("b"), Some(0))]);
+        assert_eq!(publisher.deadline(), None);
+    }
+}
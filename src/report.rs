@@ -0,0 +1,489 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use lsp_types::Range;
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Output formats of the command line checker.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// `path:line:column: word`
+    #[default]
+    Plain,
+    /// SARIF 2.1.0 report, as consumed by code scanning services.
+    Sarif,
+    /// One JSON object per finding and line.
+    Jsonl,
+    /// `path:line:column: warning: unknown word 'word'`, as understood by
+    /// quickfix and errorformat parsers.
+    Gcc,
+    /// JUnit XML with one test case per checked file.
+    Junit,
+    /// Each finding with the line it's on and suggestions, for reading in
+    /// a terminal.
+    Pretty,
+}
+
+impl Format {
+    pub fn needs_suggestions(self) -> bool {
+        matches!(self, Format::Sarif | Format::Jsonl | Format::Pretty)
+    }
+}
+
+/// ANSI escape codes of the styles of pretty output.
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const GREEN: &str = "\x1b[32m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+/// Width of tabs in pretty output.
+const TAB_WIDTH: usize = 4;
+
+/// A misspelling found by the command line checker.
+pub struct Finding {
+    pub path: PathBuf,
+    pub range: Range,
+    pub word: String,
+    pub suggestions: Vec<String>,
+    /// The text of the line the word is on.
+    pub line: String,
+}
+
+/// Write a report of `findings`. `checked` lists every checked file,
+/// including those without findings. Pretty output is colored if `color`.
+pub fn write(
+    out: &mut impl Write,
+    format: Format,
+    checked: &[PathBuf],
+    findings: &[Finding],
+    color: bool,
+) -> Result<()> {
+    match format {
+        Format::Plain => write_plain(out, findings),
+        Format::Sarif => write_sarif(out, findings),
+        Format::Jsonl => write_jsonl(out, findings),
+        Format::Gcc => write_gcc(out, findings),
+        Format::Junit => write_junit(out, checked, findings),
+        Format::Pretty => write_pretty(out, findings, color),
+    }
+}
+
+fn write_plain(out: &mut impl Write, findings: &[Finding]) -> Result<()> {
+    for finding in findings {
+        writeln!(
+            out,
+            "{}:{}:{}: {}",
+            finding.path.display(),
+            finding.range.start.line + 1,
+            finding.range.start.character + 1,
+            finding.word
+        )?;
+    }
+    Ok(())
+}
+
+fn write_pretty(
+    out: &mut impl Write,
+    findings: &[Finding],
+    color: bool,
+) -> Result<()> {
+    let paint = |style: &str, text: &str| match color {
+        true => format!("{}{}{}", style, text, RESET),
+        false => text.to_string(),
+    };
+
+    for (index, finding) in findings.iter().enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        let number = (finding.range.start.line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        let location = format!(
+            "{}:{}:{}",
+            finding.path.display(),
+            number,
+            finding.range.start.character + 1
+        );
+        writeln!(
+            out,
+            "{}: unknown word '{}'",
+            paint(BOLD, &location),
+            paint(RED, &finding.word)
+        )?;
+
+        // Columns are counted in UTF-16 code units, as in the range
+        let start = finding.range.start.character as usize;
+        let mut line = String::new();
+        let (mut units, mut width, mut indent) = (0, 0, 0);
+        for c in finding.line.chars() {
+            if units <= start {
+                indent = width;
+            }
+            units += c.len_utf16();
+            match c {
+                '\t' => {
+                    line.push_str(&" ".repeat(TAB_WIDTH));
+                    width += TAB_WIDTH;
+                }
+                c => {
+                    line.push(c);
+                    width += 1;
+                }
+            }
+        }
+        if units <= start {
+            indent = width;
+        }
+        let underline = "^".repeat(finding.word.chars().count().max(1));
+        let mut marker = paint(RED, &underline);
+        if !finding.suggestions.is_empty() {
+            let suggestions = finding.suggestions.join(", ");
+            marker.push_str(&format!(" {}", paint(GREEN, &suggestions)));
+        }
+
+        writeln!(out, "{} {}", gutter, paint(BLUE, "|"))?;
+        writeln!(
+            out,
+            "{} {} {}",
+            paint(BLUE, &number),
+            paint(BLUE, "|"),
+            line
+        )?;
+        writeln!(
+            out,
+            "{} {} {}{}",
+            gutter,
+            paint(BLUE, "|"),
+            " ".repeat(indent),
+            marker
+        )?;
+    }
+    Ok(())
+}
+
+fn write_gcc(out: &mut impl Write, findings: &[Finding]) -> Result<()> {
+    for finding in findings {
+        writeln!(
+            out,
+            "{}:{}:{}: warning: unknown word '{}'",
+            finding.path.display(),
+            finding.range.start.line + 1,
+            finding.range.start.character + 1,
+            finding.word
+        )?;
+    }
+    Ok(())
+}
+
+fn write_jsonl(out: &mut impl Write, findings: &[Finding]) -> Result<()> {
+    for finding in findings {
+        let object = json!({
+            "path": finding.path,
+            "range": finding.range,
+            "word": finding.word,
+            "suggestions": finding.suggestions,
+        });
+        writeln!(out, "{}", object)?;
+    }
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_junit(
+    out: &mut impl Write,
+    checked: &[PathBuf],
+    findings: &[Finding],
+) -> Result<()> {
+    let failures = checked
+        .iter()
+        .filter(|path| findings.iter().any(|finding| &finding.path == *path))
+        .count();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuites name="{}" tests="{}" failures="{}">"#,
+        env!("CARGO_PKG_NAME"),
+        checked.len(),
+        failures
+    )?;
+    writeln!(
+        out,
+        r#"  <testsuite name="spelling" tests="{}" failures="{}">"#,
+        checked.len(),
+        failures
+    )?;
+
+    for path in checked {
+        let name = escape_xml(&path.display().to_string());
+        let file_findings = findings
+            .iter()
+            .filter(|finding| &finding.path == path)
+            .collect::<Vec<_>>();
+
+        if file_findings.is_empty() {
+            writeln!(
+                out,
+                r#"    <testcase classname="spelling" name="{}"/>"#,
+                name
+            )?;
+            continue;
+        }
+
+        writeln!(
+            out,
+            r#"    <testcase classname="spelling" name="{}">"#,
+            name
+        )?;
+        let noun = if file_findings.len() == 1 {
+            "misspelling"
+        } else {
+            "misspellings"
+        };
+        write!(
+            out,
+            r#"      <failure type="misspelling" message="{} {}">"#,
+            file_findings.len(),
+            noun
+        )?;
+        for finding in file_findings {
+            let line = format!(
+                "{}:{}:{}: unknown word '{}'",
+                path.display(),
+                finding.range.start.line + 1,
+                finding.range.start.character + 1,
+                finding.word
+            );
+            writeln!(out, "{}", escape_xml(&line))?;
+        }
+        writeln!(out, "</failure>")?;
+        writeln!(out, "    </testcase>")?;
+    }
+
+    writeln!(out, "  </testsuite>")?;
+    writeln!(out, "</testsuites>")?;
+    Ok(())
+}
+
+fn artifact_uri(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn sarif_region(range: Range) -> serde_json::Value {
+    json!({
+        "startLine": range.start.line + 1,
+        "startColumn": range.start.character + 1,
+        "endLine": range.end.line + 1,
+        "endColumn": range.end.character + 1,
+    })
+}
+
+fn write_sarif(out: &mut impl Write, findings: &[Finding]) -> Result<()> {
+    let results = findings
+        .iter()
+        .map(|finding| {
+            let uri = artifact_uri(&finding.path);
+            let fixes = finding
+                .suggestions
+                .iter()
+                .map(|suggestion| {
+                    json!({
+                        "description": {
+                            "text": format!("Replace with '{}'", suggestion),
+                        },
+                        "artifactChanges": [{
+                            "artifactLocation": { "uri": uri },
+                            "replacements": [{
+                                "deletedRegion": sarif_region(finding.range),
+                                "insertedContent": { "text": suggestion },
+                            }],
+                        }],
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({
+                "ruleId": "misspelling",
+                "ruleIndex": 0,
+                "level": "warning",
+                "message": {
+                    "text": format!("Unknown word '{}'", finding.word),
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": uri },
+                        "region": sarif_region(finding.range),
+                    },
+                }],
+                "fixes": fixes,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let report = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/ludviggunne/zspell_lsp",
+                    "rules": [{
+                        "id": "misspelling",
+                        "name": "Misspelling",
+                        "shortDescription": {
+                            "text": "Word not found in the dictionary",
+                        },
+                        "defaultConfiguration": { "level": "warning" },
+                    }],
+                },
+            },
+            // Ranges count UTF-16 code units, as in the language server
+            "columnKind": "utf16CodeUnits",
+            "results": results,
+        }],
+    });
+
+    serde_json::to_writer_pretty(&mut *out, &report)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use lsp_types::Position;
+
+    fn findings() -> Vec<Finding> {
+        vec![Finding {
+            path: PathBuf::from("docs/intro.md"),
+            range: Range::new(Position::new(2, 4), Position::new(2, 8)),
+            word: "wrld".to_string(),
+            suggestions: vec!["world".to_string()],
+            line: "hi \twrld".to_string(),
+        }]
+    }
+
+    fn render(format: Format) -> String {
+        let checked = [
+            PathBuf::from("docs/intro.md"),
+            PathBuf::from("docs/<clean>.md"),
+        ];
+        let mut out = Vec::new();
+        write(&mut out, format, &checked, &findings(), false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(render(Format::Plain), "docs/intro.md:3:5: wrld\n");
+    }
+
+    #[test]
+    fn pretty() {
+        assert_eq!(
+            render(Format::Pretty),
+            "docs/intro.md:3:5: unknown word 'wrld'\n  \
+             |\n\
+             3 | hi     wrld\n  \
+             |        ^^^^ world\n"
+        );
+
+        let mut out = Vec::new();
+        write_pretty(&mut out, &findings(), true).unwrap();
+        let colored = String::from_utf8(out).unwrap();
+        assert!(colored.contains("\x1b[1;31mwrld\x1b[0m"));
+    }
+
+    #[test]
+    fn gcc() {
+        assert_eq!(
+            render(Format::Gcc),
+            "docs/intro.md:3:5: warning: unknown word 'wrld'\n"
+        );
+    }
+
+    #[test]
+    fn jsonl() {
+        let output = render(Format::Jsonl);
+        let object: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(output.lines().count(), 1);
+        assert_eq!(object["path"], "docs/intro.md");
+        assert_eq!(object["range"]["start"]["line"], 2);
+        assert_eq!(object["word"], "wrld");
+        assert_eq!(object["suggestions"][0], "world");
+    }
+
+    #[test]
+    fn junit() {
+        let output = render(Format::Junit);
+
+        assert!(output.contains(r#"tests="2" failures="1""#));
+        assert!(output.contains(
+            r#"<failure type="misspelling" message="1 misspelling">"#
+        ));
+        assert!(
+            output.contains("docs/intro.md:3:5: unknown word &apos;wrld&apos;")
+        );
+        assert!(output.contains(
+            r#"<testcase classname="spelling" name="docs/&lt;clean&gt;.md"/>"#
+        ));
+    }
+
+    #[test]
+    fn sarif() {
+        let report: serde_json::Value =
+            serde_json::from_str(&render(Format::Sarif)).unwrap();
+        let result = &report["runs"][0]["results"][0];
+        let location = &result["locations"][0]["physicalLocation"];
+
+        assert_eq!(report["version"], "2.1.0");
+        assert_eq!(location["artifactLocation"]["uri"], "docs/intro.md");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert_eq!(location["region"]["startColumn"], 5);
+        assert_eq!(
+            result["fixes"][0]["artifactChanges"][0]["replacements"][0]
+                ["insertedContent"]["text"],
+            "world"
+        );
+    }
+
+    #[test]
+    fn sarif_utf16_columns() {
+        let finding = Finding {
+            path: PathBuf::from("notes.txt"),
+            range: Range::new(Position::new(0, 3), Position::new(0, 7)),
+            word: "wrld".to_string(),
+            suggestions: Vec::new(),
+            line: "😀 wrld".to_string(),
+        };
+        let mut out = Vec::new();
+        write_sarif(&mut out, &[finding]).unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let region = &report["runs"][0]["results"][0]["locations"][0]
+            ["physicalLocation"]["region"];
+
+        // The emoji is two UTF-16 code units but one code point
+        assert_eq!(report["runs"][0]["columnKind"], "utf16CodeUnits");
+        assert_eq!(region["startColumn"], 4);
+        assert_eq!(region["endColumn"], 8);
+    }
+}
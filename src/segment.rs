@@ -0,0 +1,25 @@
+//! Splitting runs of scripts written without spaces between words, such as
+//! Chinese and Japanese, for `segmentUnspacedScripts`.
+
+use std::ops;
+
+/// The byte ranges of the words of `run`, by the word boundaries of
+/// Unicode Standard Annex #29. `None` if built without the `segmentation`
+/// feature, so that runs are skipped.
+#[cfg(feature = "segmentation")]
+pub fn words(run: &str) -> Option<Vec<ops::Range<usize>>> {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    let words = run
+        .split_word_bound_indices()
+        .filter(|(_, word)| word.chars().any(char::is_alphabetic))
+        .map(|(start, word)| start..start + word.len())
+        .collect();
+    Some(words)
+}
+
+#[cfg(not(feature = "segmentation"))]
+pub fn words(run: &str) -> Option<Vec<ops::Range<usize>>> {
+    _ = run;
+    None
+}
@@ -1,53 +1,999 @@
-use crate::lexer::Lexer;
-use anyhow::Result;
-use log::info;
-use lsp_server::{Connection, Message, Notification};
+use crate::affixes::AffixKind;
+use crate::backend::Issue;
+use crate::changes::{self, ChangedLines};
+use crate::checker::{self, Checker, Sections};
+use crate::config::{
+    self, CheckOn, Config, Layer, Presentation, ReportDuplicates, Severity,
+};
+use crate::crash;
+use crate::dictionaries;
+use crate::dispatch::{
+    self, NotificationDispatcher, Reply, RequestDispatcher, RequestError,
+};
+use crate::document::Document;
+use crate::intern::Interner;
+use crate::lexer::{self, Word};
+use crate::metrics::{self, metrics};
+use crate::personal;
+use crate::publisher::Publisher;
+use crate::skip::Skip;
+use crate::syntax::{Block, Syntax};
+use crate::thesaurus::Thesaurus;
+use crate::transport;
+use crate::words::UserWord;
+use crate::workspace;
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use lsp_server::{
+    Connection, ErrorCode, Message, Notification, Request, RequestId, Response,
+};
+use lsp_types::notification::{
+    Cancel, DidChangeConfiguration, DidChangeTextDocument,
+    DidChangeWatchedFiles, DidChangeWorkspaceFolders, DidCloseTextDocument,
+    DidOpenTextDocument, DidSaveTextDocument, LogTrace, Notification as _,
+    PublishDiagnostics, SetTrace, ShowMessage,
+};
+use lsp_types::request::{
+    ApplyWorkspaceEdit, CodeActionRequest, CodeLensRequest,
+    DocumentHighlightRequest, ExecuteCommand, HoverRequest, RegisterCapability,
+    Request as _, SemanticTokensFullRequest, UnregisterCapability,
+    WillSaveWaitUntil, WorkspaceConfiguration,
+};
 use lsp_types::{
-    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
-    DidOpenTextDocumentParams, PublishDiagnosticsParams, ServerCapabilities,
-    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+    ApplyWorkspaceEditParams, CodeAction, CodeActionKind, CodeActionParams,
+    CodeActionProviderCapability, CodeActionResponse, CodeLens,
+    CodeLensOptions, Command, ConfigurationItem, ConfigurationParams,
+    DiagnosticSeverity, DidChangeConfigurationParams,
+    DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+    DidChangeWatchedFilesRegistrationOptions, DidChangeWorkspaceFoldersParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, DocumentHighlight, DocumentHighlightKind,
+    ExecuteCommandOptions, ExecuteCommandParams, FileSystemWatcher,
+    GlobPattern, Hover, HoverContents, HoverProviderCapability,
+    InitializeParams, LogTraceParams, MarkupContent, MarkupKind, MessageType,
+    NumberOrString, OneOf, Position, Registration, RegistrationParams,
+    SaveOptions, SemanticToken, SemanticTokenType, SemanticTokens,
+    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
+    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities,
+    ShowMessageParams, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions,
+    TextEdit, TraceValue, Unregistration, UnregistrationParams, Url,
+    WorkspaceEdit, WorkspaceFoldersServerCapabilities,
+    WorkspaceServerCapabilities,
 };
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use streaming_iterator::StreamingIterator;
-use zspell::Dictionary;
+
+pub const CHECK_DOCUMENT_COMMAND: &str = "zspell.checkDocument";
+pub const FIX_ALL_COMMAND: &str = "zspell.fixAll";
+pub const ADD_TO_DICTIONARY_COMMAND: &str = "zspell.addToDictionary";
+pub const ADD_ALL_TO_DICTIONARY_COMMAND: &str = "zspell.addAllToDictionary";
+pub const CORRECT_ALL_COMMAND: &str = "zspell.correctAll";
+pub const CORRECT_WORKSPACE_COMMAND: &str = "zspell.correctWorkspace";
+pub const CHECK_WORKSPACE_COMMAND: &str = "zspell.checkWorkspace";
+pub const SET_LANGUAGE_COMMAND: &str = "zspell.setLanguage";
+pub const MISSPELLING_TOKEN_TYPE: &str = "misspelling";
+pub const INFO_REQUEST: &str = "zspell/info";
+pub const ADD_WORD_WITH_FLAGS_REQUEST: &str = "zspell/addWordWithFlags";
+pub const LIST_USER_WORDS_REQUEST: &str = "zspell/listUserWords";
+pub const AFFIX_FLAGS_REQUEST: &str = "zspell/affixFlags";
+pub const METRICS_REQUEST: &str = "zspell/metrics";
+pub const PING_REQUEST: &str = "zspell/ping";
+pub const STATUS_NOTIFICATION: &str = "zspell/status";
+pub const DOCUMENT_LANGUAGE_NOTIFICATION: &str = "zspell/documentLanguage";
+const WATCHED_FILES_REGISTRATION: &str = "zspell/watchedFiles";
+
+/// State reported to the client through `zspell/status`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    Loading,
+    Idle,
+    Checking,
+    Error,
+}
+
+/// Parameters of the `zspell/status` notification.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+struct Status {
+    state: State,
+    /// Language of the default dictionary, or the name of its file if it
+    /// wasn't selected by language.
+    language: String,
+    /// Locale of the dictionary used in place of `language` when that
+    /// isn't installed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dictionary: Option<String>,
+    /// Details of the last error, if `state` is `error`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+/// Parameters of the `zspell/documentLanguage` notification, sent when the
+/// language of a document is detected or chosen.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentLanguage {
+    pub uri: Url,
+    /// The language checking the document, `None` if it has none.
+    pub language: Option<String>,
+    pub source: LanguageSource,
+}
+
+/// Where the language of a document comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LanguageSource {
+    /// The settings, as nothing was detected or chosen.
+    Configured,
+    /// Detected among the `detectLanguages` from the text.
+    Detected,
+    /// Chosen with the `zspell.setLanguage` command.
+    Chosen,
+}
+
+/// The `zspell/info` request, answered with the server version, settings
+/// and dictionaries.
+pub enum InfoRequest {}
+
+impl lsp_types::request::Request for InfoRequest {
+    type Params = ();
+    type Result = Value;
+    const METHOD: &'static str = INFO_REQUEST;
+}
+
+/// The `zspell/addWordWithFlags` request: list the affix flags inflecting a
+/// word, or add it with some of them to the personal dictionary.
+pub enum AddWordWithFlagsRequest {}
+
+impl lsp_types::request::Request for AddWordWithFlagsRequest {
+    type Params = AddWordWithFlagsParams;
+    type Result = AddWordWithFlags;
+    const METHOD: &'static str = ADD_WORD_WITH_FLAGS_REQUEST;
+}
+
+/// Parameters of the `zspell/addWordWithFlags` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AddWordWithFlagsParams {
+    /// Document whose settings select the dictionary and its affix file.
+    pub uri: Url,
+    pub word: String,
+    /// Flags to add the word with, written as in dictionary files, e.g.
+    /// `"SD"`. The word is only listed flags for if `None`.
+    #[serde(default)]
+    pub flags: Option<String>,
+    /// A word of the dictionary to inflect the word like, e.g. `"run"`:
+    /// the word is added with the affix flags of its entry, and `flags`.
+    #[serde(default)]
+    pub like: Option<String>,
+    /// The wordlist to add the word to.
+    #[serde(default)]
+    pub layer: Layer,
+}
+
+/// Result of the `zspell/addWordWithFlags` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AddWordWithFlags {
+    /// The flags of the affix file inflecting the word.
+    pub flags: Vec<AffixFlag>,
+    /// The forms accepted since the word was added, empty if it wasn't.
+    pub added: Vec<String>,
+}
+
+/// An affix flag that can be added to a word.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AffixFlag {
+    pub flag: String,
+    pub kind: AffixKind,
+    /// The comment of the rule in the affix file, or the affixes it adds.
+    pub description: String,
+    /// The forms of the word with this flag alone.
+    pub forms: Vec<String>,
+}
+
+/// The `zspell/affixFlags` request, answered with the affix flags of the
+/// dictionary of a document, for clients to let users pick some when adding
+/// words with `zspell/addWordWithFlags`.
+pub enum AffixFlagsRequest {}
+
+impl lsp_types::request::Request for AffixFlagsRequest {
+    type Params = AffixFlagsParams;
+    type Result = Vec<AffixFlagInfo>;
+    const METHOD: &'static str = AFFIX_FLAGS_REQUEST;
+}
+
+/// Parameters of the `zspell/affixFlags` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AffixFlagsParams {
+    /// Document whose settings select the dictionary and its affix file.
+    pub uri: Url,
+}
+
+/// An affix flag of the affix file, as listed by `zspell/affixFlags`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AffixFlagInfo {
+    pub flag: String,
+    pub kind: AffixKind,
+    /// The comment of the rule in the affix file, or the affixes it adds.
+    pub description: String,
+    /// The affixes the rule adds, such as `"un-"` or `"-ies"`.
+    pub affixes: Vec<String>,
+    /// Whether the rule combines with rules of the other kind.
+    pub combines: bool,
+}
+
+/// The `zspell/listUserWords` request, answered with the words added to the
+/// personal and team dictionaries of every folder.
+pub enum ListUserWordsRequest {}
+
+impl lsp_types::request::Request for ListUserWordsRequest {
+    type Params = ();
+    type Result = Vec<UserWord>;
+    const METHOD: &'static str = LIST_USER_WORDS_REQUEST;
+}
+
+/// The `zspell/metrics` request, answered with a [`metrics::Snapshot`].
+pub enum MetricsRequest {}
+
+impl lsp_types::request::Request for MetricsRequest {
+    type Params = ();
+    type Result = metrics::Snapshot;
+    const METHOD: &'static str = METRICS_REQUEST;
+}
+
+/// The `zspell/ping` request, answered right away unless the server is
+/// stuck.
+pub enum PingRequest {}
+
+impl lsp_types::request::Request for PingRequest {
+    type Params = ();
+    type Result = Ping;
+    const METHOD: &'static str = PING_REQUEST;
+}
+
+/// Result of the `zspell/ping` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Ping {
+    /// Time since the server was created, in milliseconds.
+    pub uptime_ms: u64,
+    /// Language of the default dictionary, as in `zspell/status`.
+    pub language: String,
+    /// Number of documents waiting to be checked.
+    pub queue_depth: usize,
+}
+
+/// Rule id of misspelled words.
+pub const MISSPELLING_RULE: &str = "misspelling";
+
+/// Rule id of words the dictionary forbids.
+pub const FORBIDDEN_RULE: &str = "forbidden";
+
+/// Rule id of misspelled words of identifiers, see `checkIdentifiers`.
+pub const IDENTIFIER_RULE: &str = "identifier";
+
+/// Rule id of the note on lines too long to be checked.
+pub const LONG_LINE_RULE: &str = "long-line";
+
+/// Rule id of the note on files too large to be checked.
+pub const FILE_SIZE_RULE: &str = "file-size";
+
+/// The `data` attached to every diagnostic. Fields are only ever added,
+/// `version` is bumped if one changes meaning or is removed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticData {
+    pub version: u32,
+    /// Id of the rule that produced the diagnostic.
+    pub rule: String,
+    /// The word as it appears in the document.
+    pub word: String,
+    /// The word in lowercase, for grouping occurrences regardless of case.
+    pub normalized: String,
+    /// Whether there are suggestions for the word, or `None` if unknown.
+    /// Suggestions for misspellings are only looked for by code actions.
+    pub suggestions: Option<bool>,
+}
+
+impl DiagnosticData {
+    pub const VERSION: u32 = 2;
+}
+
+/// A diagnostic until it is published. Its strings are shared with the
+/// diagnostics of the same word or rule, and it only becomes an LSP
+/// diagnostic when sent, so that results superseded before that cost
+/// little.
+#[derive(Debug, Clone)]
+struct Finding {
+    range: lsp_types::Range,
+    severity: DiagnosticSeverity,
+    rule: Arc<str>,
+    message: Arc<str>,
+    word: Arc<str>,
+    normalized: Arc<str>,
+    suggestions: Option<bool>,
+    /// Other occurrences of the word, see `reportDuplicates`.
+    related: Vec<lsp_types::Range>,
+}
+
+impl Finding {
+    /// Whether the finding is of a misspelled or forbidden word, rather
+    /// than a note or grammar issue.
+    fn is_misspelling(&self) -> bool {
+        [MISSPELLING_RULE, FORBIDDEN_RULE, IDENTIFIER_RULE]
+            .contains(&&*self.rule)
+    }
+
+    /// The finding as an LSP diagnostic of the document `uri`, borrowing
+    /// its strings so that they're only copied once, into the JSON of the
+    /// notification.
+    fn as_diagnostic<'a>(&'a self, uri: &'a Url) -> DiagnosticRef<'a> {
+        DiagnosticRef {
+            range: self.range,
+            severity: self.severity,
+            code: &self.rule,
+            source: "zspell",
+            message: &self.message,
+            related_information: self
+                .related
+                .iter()
+                .map(|&range| RelatedRef {
+                    location: LocationRef { uri, range },
+                    message: "Another occurrence",
+                })
+                .collect(),
+            data: DiagnosticDataRef {
+                version: DiagnosticData::VERSION,
+                rule: &self.rule,
+                word: &self.word,
+                normalized: &self.normalized,
+                suggestions: self.suggestions,
+            },
+        }
+    }
+}
+
+/// A [`lsp_types::Diagnostic`] with borrowed strings, serialized the same way.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DiagnosticRef<'a> {
+    range: lsp_types::Range,
+    severity: DiagnosticSeverity,
+    code: &'a str,
+    source: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related_information: Vec<RelatedRef<'a>>,
+    data: DiagnosticDataRef<'a>,
+}
+
+/// A [`lsp_types::DiagnosticRelatedInformation`] with borrowed strings.
+#[derive(Serialize, Debug)]
+struct RelatedRef<'a> {
+    location: LocationRef<'a>,
+    message: &'a str,
+}
+
+/// A [`lsp_types::Location`] with a borrowed URI.
+#[derive(Serialize, Debug)]
+struct LocationRef<'a> {
+    uri: &'a Url,
+    range: lsp_types::Range,
+}
+
+/// [`DiagnosticData`] with borrowed strings.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DiagnosticDataRef<'a> {
+    version: u32,
+    rule: &'a str,
+    word: &'a str,
+    normalized: &'a str,
+    suggestions: Option<bool>,
+}
+
+/// [`lsp_types::PublishDiagnosticsParams`] of borrowed diagnostics.
+#[derive(Serialize, Debug)]
+struct PublishDiagnosticsRef<'a> {
+    uri: &'a Url,
+    diagnostics: Vec<DiagnosticRef<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<i32>,
+}
+
+/// Capacity of the queue of documents waiting to be checked. Handling
+/// messages blocks while it's full.
+const CHECK_QUEUE: usize = 64;
+
+/// Memory the open documents and the caches of the checker may take
+/// unless set otherwise.
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 << 20;
+
+/// Documents with more lines than this are checked in chunks of as many
+/// lines, publishing the diagnostics found so far after each.
+const CHUNK_LINES: usize = 10_000;
+
+/// Time without changes to configuration files or wordlists waited for
+/// before reloading them.
+const RELOAD_DELAY: Duration = Duration::from_millis(100);
+
+/// Minimum time between two batches of published diagnostics.
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Meanings of a word shown on hover, with their synonyms.
+const MAX_MEANINGS: usize = 5;
+
+/// Suggestions for a misspelled word whose stems are shown on hover.
+const MAX_STEMMED_SUGGESTIONS: usize = 3;
+
+/// A version of a document to check on the checking thread.
+struct CheckJob {
+    uri: Url,
+    version: i32,
+    revision: u64,
+    /// The text of the document, shared rather than copied.
+    text: Arc<String>,
+    /// The text at the last commit, if only problems on lines changed
+    /// since are reported.
+    head: Option<Arc<String>>,
+    /// The settings of the document, without the language detected for
+    /// it.
+    config: Config,
+    /// Whether to detect the language of the text among the
+    /// `detectLanguages` of `config`, as none was chosen.
+    detect: bool,
+    /// Memory left for the caches of the checker.
+    cache_budget: usize,
+}
+
+/// The diagnostics of a checked version of a document.
+struct CheckResult {
+    uri: Url,
+    version: i32,
+    revision: u64,
+    /// The language detected for the text, if detection was asked for.
+    detected: Option<Option<String>>,
+    diagnostics: Vec<Finding>,
+}
+
+/// Work for a request done on the checking thread.
+struct Task {
+    /// The request answered once done, `None` for the work of
+    /// notifications.
+    id: Option<RequestId>,
+    method: &'static str,
+    cancelled: Arc<AtomicBool>,
+    work: Work,
+}
+
+/// The work of a task.
+enum Work {
+    /// Reading the checker of the checking thread.
+    Read(Box<ReadWork>),
+    /// Changing the checker of the server, such as loading dictionaries or
+    /// adding words, after which the checking thread forks it again.
+    Update(Box<dyn FnOnce(&mut Checker) -> Finish + Send>),
+}
+
+/// Work reading the checker of the checking thread, given whether the
+/// request was cancelled since.
+type ReadWork = dyn FnOnce(&Checker, &AtomicBool) -> Finish + Send;
+
+/// A word to add to a wordlist, with its affix flags and the inflected
+/// forms they give.
+struct NewWord {
+    word: String,
+    flags: String,
+    forms: Vec<String>,
+}
+
+/// What's left of a task once done on the checking thread, given the
+/// server, whose result answers the request.
+type Finish = Box<dyn FnOnce(&mut Server, &Connection) -> Result<Value> + Send>;
+
+/// A hover made on the checking thread, without the synonyms of its word,
+/// which are looked up by the server.
+struct HoverDraft {
+    value: String,
+    range: lsp_types::Range,
+    /// The correct word hovered and its language, if it's not misspelled.
+    synonyms_of: Option<(String, Option<String>)>,
+    /// Whether there's nothing to show but synonyms.
+    empty: bool,
+}
+
+/// Something for the checking thread to do, in order.
+enum Job {
+    Check(Box<CheckJob>),
+    Task(Task),
+}
+
+/// Something the checking thread is done with.
+enum Done {
+    Check(CheckResult),
+    Task(Option<RequestId>, &'static str, Finish),
+}
+
+/// A file of the workspace to check on the checking thread.
+struct WorkspaceFile {
+    uri: Url,
+    /// Empty for open documents that aren't files.
+    path: PathBuf,
+    config: Config,
+    /// The text of the document if it's open, which takes precedence over
+    /// the contents of the file.
+    text: Option<Arc<String>>,
+}
+
+impl WorkspaceFile {
+    fn read(&self) -> Option<Arc<String>> {
+        self.text
+            .clone()
+            .or_else(|| std::fs::read_to_string(&self.path).ok().map(Arc::new))
+    }
+}
+
+/// A workspace folder and its settings.
+struct Folder {
+    root: PathBuf,
+    /// The latest settings received from the client for this folder,
+    /// layered on top of everything else.
+    client_settings: Value,
+    config: Config,
+}
+
+impl Folder {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            client_settings: Value::Null,
+            config: Config::default(),
+        }
+    }
+}
 
 pub struct Server {
-    dict: Dictionary,
+    /// Forked for the thread checking documents, or shared with it if the
+    /// engine can't be forked. Only changed by that thread once it runs,
+    /// see [`Work::Update`].
+    checker: Arc<Mutex<Checker>>,
+    /// Work for the checking thread.
+    check_jobs: crossbeam_channel::Sender<Job>,
+    /// Requests answered by the checking thread, with the flags cancelling
+    /// them.
+    tasks: HashMap<RequestId, Arc<AtomicBool>>,
+    /// Settings given on the command line, layered on top of the
+    /// configuration files.
+    command_line: Value,
+    documents: HashMap<Url, Document>,
+    /// The last revision given to a document.
+    revision: u64,
+    next_request_id: i32,
+    trace: TraceValue,
+    /// Whether settings are pulled through `workspace/configuration`.
+    pull_configuration: bool,
+    /// The pending `workspace/configuration` request and the roots of the
+    /// folders it asked about.
+    configuration_request: Option<(RequestId, Vec<PathBuf>)>,
+    /// The workspace folders, or the current directory if the client sent
+    /// none. Each folder has its own configuration files and settings.
+    folders: Vec<Folder>,
+    config_events: crossbeam_channel::Sender<()>,
+    config_watcher: Option<RecommendedWatcher>,
+    wordlist_events: crossbeam_channel::Sender<()>,
+    wordlist_watcher: Option<RecommendedWatcher>,
+    /// Wordlist files watched by `wordlist_watcher`.
+    watched_wordlists: Vec<PathBuf>,
+    /// Whether the client accepts registrations of watched files.
+    register_watched_files: bool,
+    /// Files the client was last asked to watch.
+    watched_files: Vec<PathBuf>,
+    /// The status last sent to the client.
+    status: RefCell<Option<Status>>,
+    /// Diagnostics waiting to be published.
+    publisher: RefCell<Publisher<Finding>>,
+    /// The text of open documents at the last commit for
+    /// `onlyChangedLines`, `None` for those not committed. Read when first
+    /// needed and again after the document is saved.
+    heads: RefCell<HashMap<Url, Option<Arc<String>>>>,
+    /// Thesauri by data file, `None` for those that couldn't be read.
+    /// Opened when first needed for hover.
+    thesauri: RefCell<HashMap<PathBuf, Option<Arc<Thesaurus>>>>,
+    /// How often a summary of the metrics is logged.
+    metrics_interval: Option<Duration>,
+    /// Memory in bytes for the caches of the checker, less what the open
+    /// documents take. Documents are kept whatever their size, so only the
+    /// caches are bounded by it.
+    memory_budget: usize,
+    started: Instant,
+    /// Method of the last request or notification received, for crash
+    /// reports.
+    last_method: Option<String>,
     did_shutdown: bool,
-    did_exit: bool,
 }
 
 impl Server {
-    pub fn new(dict: Dictionary) -> Result<Self> {
+    pub fn new(checker: Checker, command_line: Value) -> Result<Self> {
+        let (config_events, _) = crossbeam_channel::unbounded();
+        let (wordlist_events, _) = crossbeam_channel::unbounded();
+        let (check_jobs, _) = crossbeam_channel::bounded(CHECK_QUEUE);
         Ok(Self {
-            dict,
+            checker: Arc::new(Mutex::new(checker)),
+            check_jobs,
+            tasks: HashMap::new(),
+            command_line,
+            documents: HashMap::new(),
+            revision: 0,
+            next_request_id: 0,
+            trace: TraceValue::Off,
+            pull_configuration: false,
+            configuration_request: None,
+            folders: vec![Folder::new(std::env::current_dir()?)],
+            config_events,
+            config_watcher: None,
+            wordlist_events,
+            wordlist_watcher: None,
+            watched_wordlists: Vec::new(),
+            register_watched_files: false,
+            watched_files: Vec::new(),
+            status: RefCell::new(None),
+            publisher: RefCell::new(Publisher::new(PUBLISH_INTERVAL)),
+            heads: RefCell::new(HashMap::new()),
+            thesauri: RefCell::new(HashMap::new()),
+            metrics_interval: None,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            started: Instant::now(),
+            last_method: None,
             did_shutdown: false,
-            did_exit: false,
         })
     }
 
+    /// Log a summary of the metrics every `interval` while running.
+    pub fn log_metrics_every(&mut self, interval: Duration) {
+        self.metrics_interval = Some(interval);
+    }
+
+    /// Limit the memory taken by cached results to about `budget` bytes,
+    /// less what open documents take.
+    pub fn set_memory_budget(&mut self, budget: usize) {
+        self.memory_budget = budget;
+    }
+
+    /// The memory budget left for caches by the open documents.
+    fn cache_budget(&self) -> usize {
+        let documents = self
+            .documents
+            .values()
+            .map(|document| document.text.capacity())
+            .sum::<usize>();
+        self.memory_budget.saturating_sub(documents)
+    }
+
+    /// Queue `job` on the checking thread.
+    fn queue(&self, job: Job) -> Result<()> {
+        self.check_jobs
+            .send(job)
+            .map_err(|_| anyhow!("the checking thread stopped"))
+    }
+
+    /// Answer request `id` once `work` is done on the checking thread,
+    /// unless it's cancelled first.
+    fn queue_task(
+        &mut self,
+        id: &RequestId,
+        method: &'static str,
+        work: impl FnOnce(&Checker, &AtomicBool) -> Finish + Send + 'static,
+    ) -> Result<()> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.tasks.insert(id.clone(), cancelled.clone());
+        self.queue(Job::Task(Task {
+            id: Some(id.clone()),
+            method,
+            cancelled,
+            work: Work::Read(Box::new(work)),
+        }))
+    }
+
+    /// Change the checker of the server with `work` on the checking
+    /// thread, in order with the checks and tasks queued, then answer
+    /// request `id`, if any.
+    fn queue_update(
+        &mut self,
+        id: Option<&RequestId>,
+        method: &'static str,
+        work: impl FnOnce(&mut Checker) -> Finish + Send + 'static,
+    ) -> Result<()> {
+        self.queue(Job::Task(Task {
+            id: id.cloned(),
+            method,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            work: Work::Update(Box::new(work)),
+        }))
+    }
+
+    /// Answer the request of a task done on the checking thread. Tasks of
+    /// notifications have nothing to answer, and like their handlers only
+    /// stop the server on transport errors.
+    fn finish_task(
+        &mut self,
+        connection: &Connection,
+        id: Option<RequestId>,
+        method: &'static str,
+        finish: Finish,
+    ) -> Result<()> {
+        let result = finish(self, connection);
+        let Some(id) = id else {
+            return match result {
+                Err(err) if dispatch::is_transport_error(&err) => Err(err),
+                Err(err) => {
+                    warn!("{} failed: {:#}", method, err);
+                    Ok(())
+                }
+                Ok(_) => Ok(()),
+            };
+        };
+        self.tasks.remove(&id);
+        let response = dispatch::respond(method, id, result)?;
+        self.send(connection, Message::Response(response))
+    }
+
+    /// Serve a client over standard input and output.
     pub fn run(&mut self) -> Result<()> {
-        let (connection, iothreads) = Connection::stdio();
-        _ = iothreads;
+        let (connection, io_threads) = transport::stdio();
+        self.run_with_connection(connection)?;
+        io_threads.join()?;
+        Ok(())
+    }
 
-        let mut server_capabilities = ServerCapabilities::default();
-        server_capabilities.text_document_sync =
-            Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL));
-        let _ = connection
-            .initialize(serde_json::to_value(server_capabilities)?)?;
+    /// Serve the client at the other end of `connection`, from the
+    /// `initialize` request until it shuts the server down. This is how
+    /// the server is embedded in other tools, with a connection created by
+    /// [`Connection::memory`] for example.
+    pub fn run_with_connection(
+        &mut self,
+        connection: Connection,
+    ) -> Result<()> {
+        let result = self.serve(&connection);
+        if let Err(err) = &result {
+            self.report_crash(&connection, err);
+        }
+        result
+    }
+
+    fn serve(&mut self, connection: &Connection) -> Result<()> {
+        let (id, params) = connection.initialize_start()?;
+        let params = serde_json::from_value::<InitializeParams>(params)?;
+        #[allow(deprecated)]
+        let root_uri = params.root_uri.clone();
+        let folders = match &params.workspace_folders {
+            Some(folders) => folders.iter().map(|folder| &folder.uri).collect(),
+            None => root_uri.iter().collect::<Vec<_>>(),
+        };
+        self.set_folders(folders);
+        let options = params.initialization_options.unwrap_or_default();
+        for index in 0..self.folders.len() {
+            self.folders[index].client_settings = options.clone();
+            self.folders[index].config = self.folder_config(index)?;
+            // The checking thread isn't running yet
+            let mut checker = self.checker.lock().unwrap();
+            let config = &self.folders[index].config;
+            checker.load_wordlists(
+                config,
+                dictionaries::affixes(config).as_ref(),
+            )?;
+            dictionaries::load_languages(&mut checker, config)?;
+        }
+        self.trace = params.trace.unwrap_or(TraceValue::Off);
+        self.pull_configuration = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        self.register_watched_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        // Only the initial settings are known when capabilities are
+        // declared, enabling autocorrection later takes a restart
+        let autocorrect_on_save = self
+            .folders
+            .iter()
+            .any(|folder| folder.config.autocorrect_on_save);
+        let server_capabilities = ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
+                    save: Some(TextDocumentSyncSaveOptions::SaveOptions(
+                        SaveOptions {
+                            include_text: Some(true),
+                        },
+                    )),
+                    will_save_wait_until: Some(autocorrect_on_save),
+                    ..Default::default()
+                },
+            )),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(
+                true,
+            )),
+            code_lens_provider: Some(CodeLensOptions {
+                resolve_provider: Some(false),
+            }),
+            document_highlight_provider: Some(OneOf::Left(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensOptions(
+                    SemanticTokensOptions {
+                        legend: SemanticTokensLegend {
+                            token_types: vec![SemanticTokenType::new(
+                                MISSPELLING_TOKEN_TYPE,
+                            )],
+                            token_modifiers: Vec::new(),
+                        },
+                        full: Some(SemanticTokensFullOptions::Bool(true)),
+                        ..Default::default()
+                    },
+                ),
+            ),
+            workspace: Some(WorkspaceServerCapabilities {
+                workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                    supported: Some(true),
+                    change_notifications: Some(OneOf::Left(true)),
+                }),
+                file_operations: None,
+            }),
+            execute_command_provider: Some(ExecuteCommandOptions {
+                commands: vec![
+                    CHECK_DOCUMENT_COMMAND.to_string(),
+                    FIX_ALL_COMMAND.to_string(),
+                    ADD_TO_DICTIONARY_COMMAND.to_string(),
+                    ADD_ALL_TO_DICTIONARY_COMMAND.to_string(),
+                    CORRECT_ALL_COMMAND.to_string(),
+                    CORRECT_WORKSPACE_COMMAND.to_string(),
+                    CHECK_WORKSPACE_COMMAND.to_string(),
+                    SET_LANGUAGE_COMMAND.to_string(),
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        connection.initialize_finish(
+            id,
+            serde_json::json!({ "capabilities": server_capabilities }),
+        )?;
+        self.request_configuration(connection)?;
+        self.register_watched_files(connection)?;
+        self.set_status(connection, State::Idle, None)?;
 
-        while !self.did_exit {
-            let message = connection.receiver.recv()?;
+        // Documents are checked, and workspaces scanned, on another thread
+        // with a fork of the checker, so that messages are handled meanwhile
+        let (check_jobs, jobs) = crossbeam_channel::bounded(CHECK_QUEUE);
+        let (results, check_results) = crossbeam_channel::unbounded();
+        self.check_jobs = check_jobs;
+        let checker = self.checker.clone();
+        std::thread::spawn(move || run_checks(&checker, jobs, results));
+
+        let (config_sender, config_events) = crossbeam_channel::unbounded();
+        self.config_events = config_sender;
+        self.watch_config_files();
+        let (wordlist_sender, wordlist_events) = crossbeam_channel::unbounded();
+        self.wordlist_events = wordlist_sender;
+        self.watch_wordlists();
+
+        // Editors tend to emit several events per save, reloading waits for
+        // them to settle
+        let mut config_reload = None;
+        let mut wordlist_reload = None;
+        let at = |deadline: Option<Instant>| match deadline {
+            Some(deadline) => crossbeam_channel::at(deadline),
+            None => crossbeam_channel::never(),
+        };
+
+        let metrics_ticks = match self.metrics_interval {
+            Some(interval) => crossbeam_channel::tick(interval),
+            None => crossbeam_channel::never(),
+        };
+
+        while !self.did_shutdown {
+            let publish = match self.publisher.borrow().deadline() {
+                Some(deadline) => crossbeam_channel::at(deadline),
+                None => crossbeam_channel::never(),
+            };
+            let message = crossbeam_channel::select! {
+                recv(connection.receiver) -> message => match message {
+                    Ok(message) => message,
+                    Err(_) => {
+                        // Clients closing the connection without `exit`
+                        // are done with the server just the same
+                        info!("client disconnected, stopping");
+                        break;
+                    }
+                },
+                recv(check_results) -> done => {
+                    match done? {
+                        Done::Check(result) => {
+                            self.publish_checked(connection, result)?
+                        }
+                        Done::Task(id, method, finish) => {
+                            self.finish_task(connection, id, method, finish)?
+                        }
+                    }
+                    continue;
+                }
+                recv(config_events) -> _ => {
+                    config_reload = Some(Instant::now() + RELOAD_DELAY);
+                    continue;
+                }
+                recv(at(config_reload)) -> _ => {
+                    config_reload = None;
+                    info!("configuration files changed, reloading");
+                    self.reload_config(connection)?;
+                    continue;
+                }
+                recv(wordlist_events) -> _ => {
+                    wordlist_reload = Some(Instant::now() + RELOAD_DELAY);
+                    continue;
+                }
+                recv(at(wordlist_reload)) -> _ => {
+                    wordlist_reload = None;
+                    info!("wordlists changed, reloading");
+                    self.reload_wordlists()?;
+                    continue;
+                }
+                recv(publish) -> _ => {
+                    self.flush_diagnostics(connection)?;
+                    continue;
+                }
+                recv(metrics_ticks) -> _ => {
+                    info!("metrics: {}", metrics().snapshot().summary());
+                    continue;
+                }
+            };
+            self.log_trace(connection, "Received", &message)?;
 
+            let start = Instant::now();
             match message {
                 Message::Notification(notification) => {
-                    let response = self.handle_notification(notification)?;
-                    if let Some(response) = response {
-                        connection
-                            .sender
-                            .send(Message::Notification(response))?;
+                    let method = notification.method.clone();
+                    self.last_method = Some(method.clone());
+                    self.handle_notification(connection, notification)?;
+                    metrics().handled(&method, start.elapsed());
+                }
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        self.did_shutdown = true;
+                        continue;
                     }
+                    let method = request.method.clone();
+                    self.last_method = Some(method.clone());
+                    if let Some(response) =
+                        self.handle_request(connection, request)?
+                    {
+                        self.send(connection, Message::Response(response))?;
+                    }
+                    metrics().handled(&method, start.elapsed());
+                }
+                Message::Response(response) => {
+                    self.handle_response(connection, response)?;
                 }
-                _ => {}
             }
         }
 
@@ -56,70 +1002,2780 @@ impl Server {
 
     fn handle_notification(
         &mut self,
+        connection: &Connection,
         notification: Notification,
-    ) -> Result<Option<Notification>> {
+    ) -> Result<()> {
         info!(
             "received notification with method: {}",
             notification.method.as_str()
         );
-        match notification.method.as_str() {
-            "textDocument/didOpen" => {
-                let params = serde_json::from_value::<DidOpenTextDocumentParams>(
-                    notification.params,
-                )?;
-                let uri = params.text_document.uri;
-                let text = params.text_document.text;
-                self.make_diagnostics(uri, text.as_str())
-            }
-            "textDocument/didChange" => {
-                let params = serde_json::from_value::<
-                    DidChangeTextDocumentParams,
-                >(notification.params)?;
-                let uri = params.text_document.uri;
-                let text = params.content_changes[0].text.as_str();
-                self.make_diagnostics(uri, text)
-            }
-            "shutdown" => {
-                // TODO: handle shutdown correctly
-                self.did_shutdown = true;
-                self.did_exit = true;
-                Ok(None)
-            }
-            "exit" => Ok(None),
-            _ => Ok(None),
+        NotificationDispatcher::new(self, notification)
+            .on::<DidOpenTextDocument>(|server, params| server.did_open(params))
+            .on::<DidChangeTextDocument>(|server, params| {
+                server.did_change(params)
+            })
+            .on::<DidSaveTextDocument>(|server, params| server.did_save(params))
+            .on::<DidCloseTextDocument>(|server, params| {
+                server.did_close(params)
+            })
+            .on::<SetTrace>(|server, params| {
+                server.trace = params.value;
+                Ok(())
+            })
+            .on::<DidChangeConfiguration>(|server, params| {
+                server.did_change_configuration(connection, params)
+            })
+            .on::<DidChangeWorkspaceFolders>(|server, params| {
+                server.did_change_workspace_folders(connection, params)
+            })
+            .on::<DidChangeWatchedFiles>(|server, params| {
+                server.did_change_watched_files(connection, params)
+            })
+            .on::<Cancel>(|server, params| {
+                let id = match params.id {
+                    NumberOrString::Number(id) => RequestId::from(id),
+                    NumberOrString::String(id) => RequestId::from(id),
+                };
+                if let Some(cancelled) = server.tasks.get(&id) {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            })
+            .finish()
+    }
+
+    fn did_open(&mut self, params: DidOpenTextDocumentParams) -> Result<()> {
+        let uri = params.text_document.uri;
+        if self.documents.contains_key(&uri) {
+            warn!("received open for already open document {}", uri);
+        }
+        let mut document = Document::new(
+            params.text_document.text,
+            params.text_document.version,
+        );
+        document.language_id = params.text_document.language_id;
+        self.revision += 1;
+        document.revision = self.revision;
+        self.documents.insert(uri.clone(), document);
+        let text = self.documents[&uri].text.as_bytes();
+        if let Some(skip) = Skip::of(text, &self.config_for(&uri)) {
+            info!("not checking {} file {}", skip, uri);
+        }
+        if self.config_for(&uri).check_on != CheckOn::Manual {
+            self.check_document(&uri)?;
         }
+        Ok(())
     }
 
-    fn make_diagnostics(
+    fn did_change(
         &mut self,
-        uri: Url,
-        text: &str,
-    ) -> Result<Option<Notification>> {
-        let mut lexer = match Lexer::new(text) {
-            None => return Ok(None),
-            Some(lexer) => lexer,
+        params: DidChangeTextDocumentParams,
+    ) -> Result<()> {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        let mut changes = params.content_changes;
+        let document = match self.documents.get_mut(&uri) {
+            Some(document) => document,
+            None => {
+                // Only changes replacing the whole text can be applied
+                let Some(full) =
+                    changes.iter().rposition(|change| change.range.is_none())
+                else {
+                    warn!("received change for unopened document {}", uri);
+                    return Ok(());
+                };
+                warn!(
+                    "received change for unopened document {}, opening it",
+                    uri
+                );
+                let text = std::mem::take(&mut changes[full].text);
+                changes.drain(..=full);
+                self.documents
+                    .entry(uri.clone())
+                    .or_insert(Document::new(text, version))
+            }
         };
+        if version < document.version {
+            // The ranges of stale changes are relative to another text, only
+            // replacing the whole text is safe
+            let Some(full) =
+                changes.iter().rposition(|change| change.range.is_none())
+            else {
+                warn!(
+                    "ignoring version {} of {} received after version {}",
+                    version, uri, document.version
+                );
+                return Ok(());
+            };
+            warn!(
+                "received version {} of {} after version {}, replacing its text",
+                version, uri, document.version
+            );
+            changes.drain(..full);
+        }
+        for change in changes {
+            document.apply_change(change);
+        }
+        document.version = document.version.max(version);
+        self.revision += 1;
+        document.revision = self.revision;
+        if self.config_for(&uri).check_on == CheckOn::Change {
+            self.check_document(&uri)?;
+        }
+        Ok(())
+    }
+
+    fn did_save(&mut self, params: DidSaveTextDocumentParams) -> Result<()> {
+        let uri = params.text_document.uri;
+        if let (Some(text), Some(document)) =
+            (params.text, self.documents.get_mut(&uri))
+        {
+            document.text = Arc::new(text);
+            self.revision += 1;
+            document.revision = self.revision;
+        }
+        // The document may have been committed too
+        self.heads.get_mut().remove(&uri);
+        if self.config_for(&uri).check_on == CheckOn::Save {
+            self.check_document(&uri)?;
+        }
+        Ok(())
+    }
+
+    fn did_close(&mut self, params: DidCloseTextDocumentParams) -> Result<()> {
+        let uri = params.text_document.uri;
+        if self.documents.remove(&uri).is_none() {
+            warn!("received close for unopened document {}", uri);
+        }
+        self.heads.get_mut().remove(&uri);
+        self.publish(uri, None, Vec::new());
+        Ok(())
+    }
+
+    fn did_change_configuration(
+        &mut self,
+        connection: &Connection,
+        params: DidChangeConfigurationParams,
+    ) -> Result<()> {
+        // Clients pulling settings send a notification without them
+        if self.pull_configuration {
+            return self.request_configuration(connection);
+        }
+        for folder in &mut self.folders {
+            folder.client_settings = params.settings.clone();
+        }
+        self.reload_config(connection)
+    }
 
-        let mut params = PublishDiagnosticsParams {
-            uri,
-            diagnostics: Vec::new(),
-            version: None,
+    fn did_change_workspace_folders(
+        &mut self,
+        connection: &Connection,
+        params: DidChangeWorkspaceFoldersParams,
+    ) -> Result<()> {
+        let mut folders = self
+            .folders
+            .iter()
+            .filter_map(|folder| Url::from_directory_path(&folder.root).ok())
+            .collect::<Vec<_>>();
+        // Folder URIs may or may not end in a slash
+        let same = |a: &Url, b: &Url| {
+            a.as_str().trim_end_matches('/') == b.as_str().trim_end_matches('/')
         };
+        folders.retain(|folder| {
+            !params
+                .event
+                .removed
+                .iter()
+                .any(|removed| same(folder, &removed.uri))
+        });
+        folders.extend(params.event.added.into_iter().map(|folder| folder.uri));
+        self.set_folders(&folders);
+        self.watch_config_files();
+        self.reload_config(connection)
+    }
 
-        while let Some(word) = lexer.next() {
-            if !self.dict.check(word.text) {
-                params.diagnostics.push(Diagnostic {
-                    range: word.range,
-                    message: "Incorrect spelling".to_string(),
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    ..Default::default()
+    fn did_change_watched_files(
+        &mut self,
+        connection: &Connection,
+        params: DidChangeWatchedFilesParams,
+    ) -> Result<()> {
+        let dictionaries = self.dictionary_files();
+        let dictionary_changed = params.changes.iter().any(|change| {
+            change
+                .uri
+                .to_file_path()
+                .is_ok_and(|path| dictionaries.contains(&path))
+        });
+        if !dictionary_changed {
+            return self.reload_config(connection);
+        }
+        info!("dictionary files changed, reloading");
+        self.set_status(connection, State::Loading, None)?;
+        self.thesauri.borrow_mut().clear();
+        let configs = self.folder_configs();
+        self.queue_update(None, "dictionary reload", move |checker| {
+            if let Err(err) = reload_dictionaries(checker, &configs) {
+                warn!("{:#}", err);
+                let message = format!("{:#}", err);
+                return Box::new(move |server, connection| {
+                    server.set_status(
+                        connection,
+                        State::Error,
+                        Some(message),
+                    )?;
+                    Ok(Value::Null)
                 });
             }
+            Box::new(|server, connection| {
+                server.reload_config(connection)?;
+                Ok(Value::Null)
+            })
+        })
+    }
+
+    /// The settings of a folder: the global configuration file, the
+    /// folder's project configuration file, the command line, and the
+    /// client settings for the folder. The personal, team and imported
+    /// dictionaries are resolved against the folder root.
+    fn folder_config(&self, index: usize) -> Result<Config> {
+        let folder = &self.folders[index];
+        let mut settings = config::load_files(&folder.root)?;
+        config::merge(&mut settings, self.command_line.clone());
+        let mut config =
+            Config::from_layers(&settings, folder.client_settings.clone())?;
+        let imports = config.import_dictionaries.iter_mut().flatten();
+        for path in
+            [&mut config.personal_dictionary, &mut config.team_dictionary]
+                .into_iter()
+                .flatten()
+                .chain(imports)
+        {
+            *path = folder.root.join(&path);
+        }
+        Ok(config)
+    }
+
+    /// Layer the settings of every folder again, load the dictionaries
+    /// they select on the checking thread, then recheck open documents.
+    /// Invalid settings are logged and the previous configuration of the
+    /// folder is kept.
+    fn reload_config(&mut self, connection: &Connection) -> Result<()> {
+        self.set_status(connection, State::Loading, None)?;
+        let mut error = None;
+        for index in 0..self.folders.len() {
+            match self.folder_config(index) {
+                Ok(config) => self.folders[index].config = config,
+                Err(err) => {
+                    let message = format!(
+                        "ignoring invalid settings for {}: {:#}",
+                        self.folders[index].root.display(),
+                        err
+                    );
+                    warn!("{}", message);
+                    error = Some(message);
+                }
+            }
+        }
+        let configs = self.folder_configs();
+        self.queue_update(None, "configuration reload", move |checker| {
+            for config in &configs {
+                let affixes = dictionaries::affixes(config);
+                let loaded =
+                    checker.load_wordlists(config, affixes.as_ref()).and_then(
+                        |()| dictionaries::load_languages(checker, config),
+                    );
+                if let Err(err) = loaded {
+                    warn!("{:#}", err);
+                    error = Some(format!("{:#}", err));
+                }
+            }
+            Box::new(move |server, connection| {
+                server.register_watched_files(connection)?;
+                server.watch_wordlists();
+                server.check_open_documents(connection)?;
+                if let Some(message) = error {
+                    server.set_status(
+                        connection,
+                        State::Error,
+                        Some(message),
+                    )?;
+                }
+                Ok(Value::Null)
+            })
+        })
+    }
+
+    /// Read the wordlists of every folder again on the checking thread, as
+    /// edited by other programs, then recheck open documents.
+    fn reload_wordlists(&mut self) -> Result<()> {
+        let configs = self.folder_configs();
+        self.queue_update(None, "wordlist reload", move |checker| {
+            for config in &configs {
+                let affixes = dictionaries::affixes(config);
+                if let Err(err) =
+                    checker.load_wordlists(config, affixes.as_ref())
+                {
+                    warn!("{:#}", err);
+                }
+            }
+            Box::new(|server, connection| {
+                server.check_open_documents(connection)?;
+                Ok(Value::Null)
+            })
+        })
+    }
+
+    /// The settings of every folder, for work on the checking thread.
+    fn folder_configs(&self) -> Vec<Config> {
+        self.folders
+            .iter()
+            .map(|folder| folder.config.clone())
+            .collect()
+    }
+
+    /// Send `zspell/status` if the state, language or message changed
+    /// since the last one.
+    fn set_status(
+        &self,
+        connection: &Connection,
+        state: State,
+        message: Option<String>,
+    ) -> Result<()> {
+        let status = Status {
+            state,
+            language: self.language(),
+            dictionary: dictionaries::fallback(&self.folders[0].config),
+            message,
+        };
+        if self.status.borrow().as_ref() == Some(&status) {
+            return Ok(());
+        }
+        self.send(
+            connection,
+            Message::Notification(Notification::new(
+                STATUS_NOTIFICATION.to_string(),
+                &status,
+            )),
+        )?;
+        *self.status.borrow_mut() = Some(status);
+        Ok(())
+    }
+
+    /// Write a bug report bundle for the fatal error `err` and tell the
+    /// user where to find it.
+    fn report_crash(&self, connection: &Connection, err: &anyhow::Error) {
+        // The checking thread may hold the checker, or have panicked with it
+        let wordlists = match self.checker.try_lock() {
+            Ok(checker) => self
+                .folders
+                .iter()
+                .map(|folder| wordlists(&checker, &folder.config))
+                .collect(),
+            Err(_) => vec![Vec::new(); self.folders.len()],
+        };
+        let mut state = self.make_info(&wordlists);
+        state["lastMethod"] = serde_json::json!(self.last_method);
+        let mut message = format!("zspell_lsp stopped: {:#}", err);
+        match crash::write_bundle(&std::env::temp_dir(), err, &state) {
+            Ok(path) => {
+                error!("crash report written to {}", path.display());
+                message += &format!(
+                    ". Please attach the files in {} to bug reports.",
+                    path.display()
+                );
+            }
+            Err(err) => warn!("unable to write crash report: {:#}", err),
+        }
+        _ = self.send(
+            connection,
+            Message::Notification(Notification::new(
+                ShowMessage::METHOD.to_string(),
+                ShowMessageParams {
+                    typ: MessageType::ERROR,
+                    message,
+                },
+            )),
+        );
+    }
+
+    /// Language of the default dictionary, or the name of its file if it
+    /// wasn't selected by language.
+    fn language(&self) -> String {
+        let config = &self.folders[0].config;
+        config.language.clone().unwrap_or_else(|| {
+            let affix = config.affix.as_deref();
+            affix
+                .unwrap_or(Path::new(config::DEFAULT_AFFIX))
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+    }
+
+    /// The affix and dictionary files used by any folder.
+    fn dictionary_files(&self) -> Vec<PathBuf> {
+        let mut files = self
+            .folders
+            .iter()
+            .flat_map(|folder| dictionaries::files(&folder.config))
+            .map(|file| absolute_path(&file))
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Ask the client to watch the dictionaries, the wordlists,
+    /// and the configuration files, for setups where the server can't
+    /// watch them itself. Registrations are only renewed when the set of
+    /// files changes.
+    fn register_watched_files(
+        &mut self,
+        connection: &Connection,
+    ) -> Result<()> {
+        if !self.register_watched_files {
+            return Ok(());
+        }
+
+        let mut files = self.dictionary_files();
+        files.extend(config::global_file());
+        files.extend(self.wordlist_files());
+        for folder in &self.folders {
+            files.push(folder.root.join(config::PROJECT_FILE));
+        }
+        if files == self.watched_files {
+            return Ok(());
+        }
+
+        if !self.watched_files.is_empty() {
+            self.send_request::<UnregisterCapability>(
+                connection,
+                UnregistrationParams {
+                    unregisterations: vec![Unregistration {
+                        id: WATCHED_FILES_REGISTRATION.to_string(),
+                        method: "workspace/didChangeWatchedFiles".to_string(),
+                    }],
+                },
+            )?;
         }
 
-        Ok(Some(Notification::new(
-            "textDocument/publishDiagnostics".to_string(),
-            params,
-        )))
+        let watchers = files
+            .iter()
+            .map(|file| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(
+                    file.to_string_lossy().into_owned(),
+                ),
+                kind: None,
+            })
+            .collect();
+        self.send_request::<RegisterCapability>(
+            connection,
+            RegistrationParams {
+                registrations: vec![Registration {
+                    id: WATCHED_FILES_REGISTRATION.to_string(),
+                    method: "workspace/didChangeWatchedFiles".to_string(),
+                    register_options: Some(serde_json::to_value(
+                        DidChangeWatchedFilesRegistrationOptions { watchers },
+                    )?),
+                }],
+            },
+        )?;
+        self.watched_files = files;
+        Ok(())
+    }
+
+    fn handle_request(
+        &mut self,
+        connection: &Connection,
+        request: Request,
+    ) -> Result<Option<Response>> {
+        info!("received request with method: {}", request.method.as_str());
+        RequestDispatcher::new(self, request)
+            .on::<WillSaveWaitUntil>(|server, params| {
+                let uri = &params.text_document.uri;
+                Ok(Some(server.make_autocorrections(uri)))
+            })
+            .on_deferred::<DocumentHighlightRequest>(|server, id, params| {
+                server.highlights(id, params.text_document_position_params)
+            })
+            .on_deferred::<HoverRequest>(|server, id, params| {
+                server.hover(id, params.text_document_position_params)
+            })
+            .on_deferred::<CodeActionRequest>(|server, id, params| {
+                server.code_actions(id, params)
+            })
+            .on_deferred::<CodeLensRequest>(|server, id, params| {
+                server.code_lenses(id, params.text_document.uri)
+            })
+            .on_deferred::<SemanticTokensFullRequest>(|server, id, params| {
+                server.semantic_tokens(id, params.text_document.uri)
+            })
+            .on_deferred::<InfoRequest>(|server, id, ()| server.info(id))
+            .on_deferred::<ListUserWordsRequest>(|server, id, ()| {
+                server.user_words(id)
+            })
+            .on_deferred::<AddWordWithFlagsRequest>(|server, id, params| {
+                server.add_word_with_flags(id, params)
+            })
+            .on::<AffixFlagsRequest>(|server, params| {
+                Ok(server.make_affix_flags(&params.uri))
+            })
+            .on::<MetricsRequest>(|_, ()| Ok(metrics().snapshot()))
+            .on::<PingRequest>(|server, ()| {
+                Ok(Ping {
+                    uptime_ms: server.started.elapsed().as_millis() as u64,
+                    language: server.language(),
+                    queue_depth: server.check_jobs.len(),
+                })
+            })
+            .on_deferred::<ExecuteCommand>(|server, id, params| {
+                server.execute_command(connection, id, params)
+            })
+            .finish()
+    }
+
+    /// Run a command. Those computing suggestions or scanning documents
+    /// are run on the checking thread, and answered later.
+    fn execute_command(
+        &mut self,
+        connection: &Connection,
+        id: &RequestId,
+        params: ExecuteCommandParams,
+    ) -> Result<Reply<Option<Value>>> {
+        const METHOD: &str = ExecuteCommand::METHOD;
+        match params.command.as_str() {
+            CHECK_DOCUMENT_COMMAND => {
+                for argument in params.arguments {
+                    let uri = command_arguments::<Url>(argument)?;
+                    self.check_document(&uri)?;
+                }
+                Ok(Reply::Now(None))
+            }
+            FIX_ALL_COMMAND => {
+                let mut documents = Vec::new();
+                for argument in params.arguments {
+                    let uri = command_arguments::<Url>(argument)?;
+                    let text = self
+                        .checked_document(&uri)
+                        .map(|document| document.text.clone());
+                    documents.push((uri.clone(), text, self.config_for(&uri)));
+                }
+                self.queue_task(id, METHOD, move |checker, _| {
+                    let edits = documents
+                        .into_iter()
+                        .map(|(uri, text, config)| {
+                            let edits = text.map_or_else(Vec::new, |text| {
+                                make_fixes(checker, &text, &config)
+                            });
+                            (uri, edits)
+                        })
+                        .collect::<Vec<_>>();
+                    Box::new(move |server, connection| {
+                        for (uri, edits) in edits {
+                            let edit = WorkspaceEdit {
+                                changes: Some(HashMap::from([(uri, edits)])),
+                                ..Default::default()
+                            };
+                            server.send_request::<ApplyWorkspaceEdit>(
+                                connection,
+                                ApplyWorkspaceEditParams {
+                                    label: Some("Fix all misspellings".into()),
+                                    edit,
+                                },
+                            )?;
+                        }
+                        Ok(Value::Null)
+                    })
+                })?;
+                Ok(Reply::Later)
+            }
+            CORRECT_ALL_COMMAND => {
+                let (uri, word, replacement) =
+                    command_arguments::<(Url, String, String)>(Value::Array(
+                        params.arguments,
+                    ))?;
+                let text = self
+                    .checked_document(&uri)
+                    .map(|document| document.text.clone());
+                let config = self.config_for(&uri);
+                self.queue_task(id, METHOD, move |checker, _| {
+                    let edits = text.map_or_else(Vec::new, |text| {
+                        make_replace_all(
+                            checker,
+                            &text,
+                            &config,
+                            &word,
+                            &replacement,
+                        )
+                    });
+                    Box::new(move |server, connection| {
+                        let edit = WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri, edits)])),
+                            ..Default::default()
+                        };
+                        server.send_request::<ApplyWorkspaceEdit>(
+                            connection,
+                            ApplyWorkspaceEditParams {
+                                label: Some(format!(
+                                    "Change all '{}' to '{}'",
+                                    word, replacement
+                                )),
+                                edit,
+                            },
+                        )?;
+                        Ok(Value::Null)
+                    })
+                })?;
+                Ok(Reply::Later)
+            }
+            CHECK_WORKSPACE_COMMAND => {
+                self.check_workspace(connection, id)?;
+                Ok(Reply::Later)
+            }
+            CORRECT_WORKSPACE_COMMAND => {
+                let (word, replacement) = command_arguments::<(String, String)>(
+                    Value::Array(params.arguments),
+                )?;
+                // Open documents take precedence over their contents on disk
+                let mut files = self
+                    .documents
+                    .iter()
+                    .filter(|(uri, _)| self.is_checked(uri))
+                    .map(|(uri, document)| WorkspaceFile {
+                        uri: uri.clone(),
+                        path: PathBuf::new(),
+                        config: self.config_for(uri),
+                        text: Some(document.text.clone()),
+                    })
+                    .collect::<Vec<_>>();
+                files.extend(
+                    self.workspace_files_to_check()
+                        .into_iter()
+                        .filter(|file| file.text.is_none()),
+                );
+                self.queue_task(id, METHOD, move |checker, cancelled| {
+                    let Some(edit) = make_workspace_replace_all(
+                        checker,
+                        &files,
+                        &word,
+                        &replacement,
+                        cancelled,
+                    ) else {
+                        return cancelled_task();
+                    };
+                    Box::new(move |server, connection| {
+                        server.send_request::<ApplyWorkspaceEdit>(
+                            connection,
+                            ApplyWorkspaceEditParams {
+                                label: Some(format!(
+                                    "Change all '{}' to '{}' in workspace",
+                                    word, replacement
+                                )),
+                                edit,
+                            },
+                        )?;
+                        Ok(Value::Null)
+                    })
+                })?;
+                Ok(Reply::Later)
+            }
+            ADD_TO_DICTIONARY_COMMAND => {
+                let (uri, word, layer) =
+                    command_arguments::<(Url, String, Layer)>(Value::Array(
+                        params.arguments,
+                    ))?;
+                let config = self.config_for(&uri);
+                let word = NewWord {
+                    word,
+                    flags: String::new(),
+                    forms: Vec::new(),
+                };
+                self.add_word(id, METHOD, config, layer, word, Value::Null)?;
+                Ok(Reply::Later)
+            }
+            ADD_ALL_TO_DICTIONARY_COMMAND => {
+                let mut documents = Vec::new();
+                for argument in params.arguments {
+                    let uri = command_arguments::<Url>(argument)?;
+                    let Some(document) = self.checked_document(&uri) else {
+                        continue;
+                    };
+                    let text = document.text.clone();
+                    documents.push((text, self.config_for(&uri)));
+                }
+                self.queue_update(Some(id), METHOD, move |checker| {
+                    for (text, config) in documents {
+                        let words = checker
+                            .misspelled_words(&text, &config)
+                            .iter()
+                            .map(|word| word.text.to_string())
+                            .collect::<HashSet<_>>();
+                        for word in words {
+                            if let Err(err) =
+                                checker.add_personal(&config, &word)
+                            {
+                                return failed_task(err);
+                            }
+                        }
+                    }
+                    Box::new(|server, connection| {
+                        server.check_open_documents(connection)?;
+                        Ok(Value::Null)
+                    })
+                })?;
+                Ok(Reply::Later)
+            }
+            SET_LANGUAGE_COMMAND => {
+                let (uri, language) = command_arguments::<(Url, Option<String>)>(
+                    Value::Array(params.arguments),
+                )?;
+                self.set_language(connection, id, &uri, language)
+            }
+            command => Err(RequestError::new(
+                ErrorCode::InvalidParams,
+                format!("unknown command: {}", command),
+            )
+            .into()),
+        }
+    }
+
+    fn send_request<R: lsp_types::request::Request>(
+        &mut self,
+        connection: &Connection,
+        params: R::Params,
+    ) -> Result<RequestId> {
+        let id = RequestId::from(self.next_request_id);
+        self.next_request_id += 1;
+        self.send(
+            connection,
+            Message::Request(Request::new(
+                id.clone(),
+                R::METHOD.to_string(),
+                params,
+            )),
+        )?;
+        Ok(id)
+    }
+
+    /// Ask the client for the `zspell` settings section, if it supports
+    /// `workspace/configuration`.
+    fn request_configuration(&mut self, connection: &Connection) -> Result<()> {
+        if !self.pull_configuration {
+            return Ok(());
+        }
+        let roots = self
+            .folders
+            .iter()
+            .map(|folder| folder.root.clone())
+            .collect::<Vec<_>>();
+        let params = ConfigurationParams {
+            items: roots
+                .iter()
+                .map(|root| ConfigurationItem {
+                    scope_uri: Url::from_directory_path(root).ok(),
+                    section: Some("zspell".to_string()),
+                })
+                .collect(),
+        };
+        let id =
+            self.send_request::<WorkspaceConfiguration>(connection, params)?;
+        self.configuration_request = Some((id, roots));
+        Ok(())
+    }
+
+    fn handle_response(
+        &mut self,
+        connection: &Connection,
+        response: Response,
+    ) -> Result<()> {
+        let roots = match self.configuration_request.take() {
+            Some((id, roots)) if id == response.id => roots,
+            pending => {
+                self.configuration_request = pending;
+                return Ok(());
+            }
+        };
+
+        if let Some(err) = response.error {
+            warn!("workspace/configuration failed: {}", err.message);
+            return Ok(());
+        }
+        let Some(Value::Array(sections)) = response.result else {
+            warn!("unexpected workspace/configuration result");
+            return Ok(());
+        };
+        // Folders may have changed while the request was pending
+        for (root, settings) in roots.iter().zip(sections) {
+            if let Some(folder) =
+                self.folders.iter_mut().find(|folder| &folder.root == root)
+            {
+                folder.client_settings = settings;
+            }
+        }
+        self.reload_config(connection)
+    }
+
+    fn send(&self, connection: &Connection, message: Message) -> Result<()> {
+        self.log_trace(connection, "Sending", &message)?;
+        connection.sender.send(message)?;
+        Ok(())
+    }
+
+    /// Report a message to the client through `$/logTrace`, according to
+    /// the trace value set by the client.
+    fn log_trace(
+        &self,
+        connection: &Connection,
+        direction: &str,
+        message: &Message,
+    ) -> Result<()> {
+        if self.trace == TraceValue::Off {
+            return Ok(());
+        }
+
+        let (description, payload) = match message {
+            Message::Request(request) => (
+                format!(
+                    "{} request '{} - ({})'.",
+                    direction, request.method, request.id
+                ),
+                &request.params,
+            ),
+            Message::Response(response) => (
+                format!("{} response '({})'.", direction, response.id),
+                response.result.as_ref().unwrap_or(&serde_json::Value::Null),
+            ),
+            Message::Notification(notification) => (
+                format!(
+                    "{} notification '{}'.",
+                    direction, notification.method
+                ),
+                &notification.params,
+            ),
+        };
+
+        let verbose = match self.trace {
+            TraceValue::Verbose => Some(serde_json::to_string_pretty(payload)?),
+            _ => None,
+        };
+
+        connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                LogTrace::METHOD.to_string(),
+                LogTraceParams {
+                    message: description,
+                    verbose,
+                },
+            )))?;
+        Ok(())
+    }
+
+    fn check_open_documents(&self, connection: &Connection) -> Result<()> {
+        self.set_status(connection, State::Checking, None)?;
+        for uri in self.documents.keys() {
+            if self.config_for(uri).check_on != CheckOn::Manual {
+                self.check_document(uri)?;
+            }
+        }
+        self.set_status(connection, State::Idle, None)
+    }
+
+    /// The files of the workspace selected by `include` and `exclude`, with
+    /// their settings.
+    fn workspace_files_to_check(&self) -> Vec<WorkspaceFile> {
+        self.workspace_files()
+            .into_iter()
+            .filter_map(|path| {
+                let (folder, relative) = self.locate(&path);
+                if !folder.config.is_included(&relative) {
+                    return None;
+                }
+                let uri = Url::from_file_path(&path).ok()?;
+                Some(WorkspaceFile {
+                    config: self.config_for(&uri),
+                    text: self
+                        .documents
+                        .get(&uri)
+                        .map(|document| document.text.clone()),
+                    uri,
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    /// Check every text file in the workspace on the checking thread,
+    /// publishing diagnostics for each of them once done, and answer
+    /// request `id` with a summary of the findings.
+    fn check_workspace(
+        &mut self,
+        connection: &Connection,
+        id: &RequestId,
+    ) -> Result<()> {
+        self.set_status(connection, State::Checking, None)?;
+        let files = self.workspace_files_to_check();
+        self.queue_task(
+            id,
+            ExecuteCommand::METHOD,
+            move |checker, cancelled| {
+                let Some((report, published)) =
+                    scan_workspace(checker, files, cancelled)
+                else {
+                    return Box::new(|server, connection| {
+                        server.set_status(connection, State::Idle, None)?;
+                        cancelled_task()(server, connection)
+                    });
+                };
+                Box::new(move |server, connection| {
+                    for (uri, diagnostics) in published {
+                        server.publish(uri, None, diagnostics);
+                    }
+                    server.set_status(connection, State::Idle, None)?;
+                    Ok(report)
+                })
+            },
+        )
+    }
+
+    fn check_document(&self, uri: &Url) -> Result<()> {
+        let Some(document) = self.documents.get(uri) else {
+            warn!("requested check of unopened document {}", uri);
+            return Ok(());
+        };
+        if !self.is_checked(uri) {
+            let config = self.config_for(uri);
+            let diagnostics = file_size_diagnostic(
+                document.text.len(),
+                &config,
+                &mut Interner::default(),
+            );
+            self.publish(
+                uri.clone(),
+                Some(document.version),
+                diagnostics.into_iter().collect(),
+            );
+            return Ok(());
+        }
+        let config = self.undetected_config_for(uri);
+        self.queue(Job::Check(Box::new(CheckJob {
+            uri: uri.clone(),
+            version: document.version,
+            revision: document.revision,
+            text: document.text.clone(),
+            head: self.head_text(uri, &config),
+            detect: document.chosen_language.is_none()
+                && !config.detect_languages.is_empty(),
+            config,
+            cache_budget: self.cache_budget(),
+        })))
+    }
+
+    /// Queue the code actions for the range of `params` on the checking
+    /// thread.
+    fn code_actions(
+        &mut self,
+        id: &RequestId,
+        params: CodeActionParams,
+    ) -> Result<Reply<Option<CodeActionResponse>>> {
+        let uri = params.text_document.uri;
+        let Some(document) = self.checked_document(&uri) else {
+            return Ok(Reply::Now(Some(CodeActionResponse::new())));
+        };
+        let text = document.text.clone();
+        let config = self.config_for(&uri);
+        let range = params.range;
+        self.queue_task(
+            id,
+            CodeActionRequest::METHOD,
+            move |checker, cancelled| {
+                // Clients ask again as the cursor moves, cancelling the
+                // requests for where it was
+                if cancelled.load(Ordering::Relaxed) {
+                    return cancelled_task();
+                }
+                let actions =
+                    make_code_actions(checker, &uri, &text, &config, range);
+                Box::new(move |_, _| Ok(serde_json::to_value(Some(actions))?))
+            },
+        )?;
+        Ok(Reply::Later)
+    }
+
+    /// Queue the highlights of the misspelling at `position` on the
+    /// checking thread.
+    fn highlights(
+        &mut self,
+        id: &RequestId,
+        position: TextDocumentPositionParams,
+    ) -> Result<Reply<Option<Vec<DocumentHighlight>>>> {
+        let uri = position.text_document.uri;
+        let Some(document) = self.checked_document(&uri) else {
+            return Ok(Reply::Now(Some(Vec::new())));
+        };
+        let text = document.text.clone();
+        let config = self.config_for(&uri);
+        let position = position.position;
+        self.queue_task(
+            id,
+            DocumentHighlightRequest::METHOD,
+            move |checker, cancelled| {
+                // Asked again as the cursor moves, like code actions
+                if cancelled.load(Ordering::Relaxed) {
+                    return cancelled_task();
+                }
+                let highlights =
+                    make_highlights(checker, &text, &config, position);
+                Box::new(move |_, _| Ok(serde_json::to_value(highlights)?))
+            },
+        )?;
+        Ok(Reply::Later)
+    }
+
+    /// Queue the hover at `position` on the checking thread, adding the
+    /// synonyms of the word once done.
+    fn hover(
+        &mut self,
+        id: &RequestId,
+        position: TextDocumentPositionParams,
+    ) -> Result<Reply<Option<Hover>>> {
+        let uri = position.text_document.uri;
+        let Some(document) = self.checked_document(&uri) else {
+            return Ok(Reply::Now(None));
+        };
+        let text = document.text.clone();
+        let config = self.config_for(&uri);
+        let position = position.position;
+        self.queue_task(
+            id,
+            HoverRequest::METHOD,
+            move |checker, cancelled| {
+                if cancelled.load(Ordering::Relaxed) {
+                    return cancelled_task();
+                }
+                let draft = make_hover(checker, &text, &config, position);
+                Box::new(move |server, _| {
+                    let hover = draft
+                        .and_then(|draft| server.finish_hover(draft, &config));
+                    Ok(serde_json::to_value(hover)?)
+                })
+            },
+        )?;
+        Ok(Reply::Later)
+    }
+
+    /// Queue the code lenses of `uri` on the checking thread.
+    fn code_lenses(
+        &mut self,
+        id: &RequestId,
+        uri: Url,
+    ) -> Result<Reply<Option<Vec<CodeLens>>>> {
+        let Some(document) = self.checked_document(&uri) else {
+            return Ok(Reply::Now(Some(Vec::new())));
+        };
+        let text = document.text.clone();
+        let config = self.config_for(&uri);
+        self.queue_task(id, CodeLensRequest::METHOD, move |checker, _| {
+            let lenses = make_code_lenses(checker, &uri, &text, &config);
+            Box::new(move |_, _| Ok(serde_json::to_value(Some(lenses))?))
+        })?;
+        Ok(Reply::Later)
+    }
+
+    /// Queue the semantic tokens of `uri` on the checking thread.
+    fn semantic_tokens(
+        &mut self,
+        id: &RequestId,
+        uri: Url,
+    ) -> Result<Reply<Option<SemanticTokensResult>>> {
+        let config = self.config_for(&uri);
+        let document = self
+            .checked_document(&uri)
+            .filter(|_| config.presentation == Presentation::SemanticTokens);
+        let Some(document) = document else {
+            return Ok(Reply::Now(Some(SemanticTokens::default().into())));
+        };
+        let text = document.text.clone();
+        self.queue_task(
+            id,
+            SemanticTokensFullRequest::METHOD,
+            move |checker, _| {
+                let tokens: SemanticTokensResult =
+                    make_semantic_tokens(checker, &text, &config).into();
+                Box::new(move |_, _| Ok(serde_json::to_value(Some(tokens))?))
+            },
+        )?;
+        Ok(Reply::Later)
+    }
+
+    /// The text of `uri` at the last commit, if `config` only reports
+    /// problems on lines changed since.
+    fn head_text(&self, uri: &Url, config: &Config) -> Option<Arc<String>> {
+        if !config.only_changed_lines {
+            return None;
+        }
+        let path = uri.to_file_path().ok()?;
+        self.heads
+            .borrow_mut()
+            .entry(uri.clone())
+            .or_insert_with(|| changes::head_text(&path).map(Arc::new))
+            .clone()
+    }
+
+    /// Publish the diagnostics of a checked document, unless it changed or
+    /// was closed since, telling the client first about the language
+    /// detected for it if it changed.
+    fn publish_checked(
+        &mut self,
+        connection: &Connection,
+        result: CheckResult,
+    ) -> Result<()> {
+        let Some(document) = self
+            .documents
+            .get_mut(&result.uri)
+            .filter(|document| document.revision == result.revision)
+        else {
+            return Ok(());
+        };
+        let detected = result.detected.filter(|detected| {
+            document.chosen_language.is_none()
+                && *detected != document.detected_language
+        });
+        if let Some(detected) = detected {
+            document.detected_language = detected;
+            self.send_document_language(connection, &result.uri)?;
+        }
+        if !self.is_checked(&result.uri) {
+            return Ok(());
+        }
+        self.publish(result.uri, Some(result.version), result.diagnostics);
+        Ok(())
+    }
+
+    /// Whether the `include` and `exclude` settings select `uri`, and it
+    /// isn't binary or generated. Documents that aren't files, such as
+    /// `untitled:` buffers, are checked regardless of `include` and
+    /// `exclude`.
+    fn is_checked(&self, uri: &Url) -> bool {
+        let (folder, path) = self.locate_uri(uri);
+        if uri.scheme() == "file" && !folder.config.is_included(&path) {
+            return false;
+        }
+        self.documents.get(uri).is_none_or(|document| {
+            Skip::of(document.text.as_bytes(), &self.config_for(uri)).is_none()
+        })
+    }
+
+    /// The settings for `uri`: those of its folder, with matching overrides
+    /// applied.
+    fn config_for(&self, uri: &Url) -> Config {
+        let mut config = self.undetected_config_for(uri);
+        let Some(document) = self.documents.get(uri) else {
+            return config;
+        };
+        // Detected languages no longer among the candidates are ignored
+        let detected = document
+            .detected_language
+            .as_ref()
+            .filter(|language| config.detect_languages.contains(language));
+        if let (None, Some(language)) = (&document.chosen_language, detected) {
+            config.language = Some(language.clone());
+        }
+        config
+    }
+
+    /// The settings for `uri` as [`Server::config_for`], without the
+    /// language last detected for it.
+    fn undetected_config_for(&self, uri: &Url) -> Config {
+        let (folder, path) = self.locate_uri(uri);
+        let Some(document) = self.documents.get(uri) else {
+            return folder.config.for_path(&path);
+        };
+        let mut config =
+            folder.config.for_document(&path, &document.language_id);
+        if let Some(language) = &document.chosen_language {
+            config.language = Some(language.clone());
+        }
+        config
+    }
+
+    /// Check the document `uri` with `language`, loaded on the checking
+    /// thread first, or with the detected or configured one again if
+    /// `None`.
+    fn set_language(
+        &mut self,
+        connection: &Connection,
+        id: &RequestId,
+        uri: &Url,
+        language: Option<String>,
+    ) -> Result<Reply<Option<Value>>> {
+        self.open_document(uri)?;
+        let Some(language) = language else {
+            self.choose_language(connection, uri, None)?;
+            return Ok(Reply::Now(None));
+        };
+        let mut config = self.config_for(uri);
+        config.language = Some(language.clone());
+        let uri = uri.clone();
+        self.queue_update(Some(id), ExecuteCommand::METHOD, move |checker| {
+            if let Err(err) = dictionaries::load_languages(checker, &config) {
+                return failed_task(
+                    RequestError::new(
+                        ErrorCode::InvalidParams,
+                        format!("{:#}", err),
+                    )
+                    .into(),
+                );
+            }
+            Box::new(move |server, connection| {
+                server.open_document(&uri)?;
+                server.choose_language(connection, &uri, Some(language))?;
+                Ok(Value::Null)
+            })
+        })?;
+        Ok(Reply::Later)
+    }
+
+    /// The document `uri`, or an error answering requests about it if it
+    /// isn't open.
+    fn open_document(&self, uri: &Url) -> Result<&Document> {
+        self.documents.get(uri).ok_or_else(|| {
+            RequestError::new(
+                ErrorCode::InvalidParams,
+                format!("{} is not open", uri),
+            )
+            .into()
+        })
+    }
+
+    /// Check the open document `uri` with the chosen `language`, its
+    /// language being detected again if `None`.
+    fn choose_language(
+        &mut self,
+        connection: &Connection,
+        uri: &Url,
+        language: Option<String>,
+    ) -> Result<()> {
+        let document = self.documents.get_mut(uri).unwrap();
+        document.chosen_language = language;
+        document.detected_language = None;
+        self.send_document_language(connection, uri)?;
+        self.check_document(uri)
+    }
+
+    /// List the affix flags inflecting the word of `params`, and add it
+    /// with the chosen ones to the personal dictionary of its document.
+    fn add_word_with_flags(
+        &mut self,
+        id: &RequestId,
+        params: AddWordWithFlagsParams,
+    ) -> Result<Reply<AddWordWithFlags>> {
+        let config = self.config_for(&params.uri);
+        let affixes = dictionaries::affixes(&config).unwrap_or_default();
+        let flags = affixes
+            .rules()
+            .iter()
+            .filter_map(|rule| {
+                let forms = rule.apply(&params.word);
+                (!forms.is_empty()).then(|| AffixFlag {
+                    flag: rule.flag.clone(),
+                    kind: rule.kind,
+                    description: rule.description(),
+                    forms,
+                })
+            })
+            .collect();
+
+        let template = params
+            .like
+            .as_deref()
+            .map(|like| {
+                dictionaries::flags_of(&config, like).ok_or_else(|| {
+                    RequestError::new(
+                        ErrorCode::InvalidParams,
+                        format!("'{}' isn't in the dictionary", like),
+                    )
+                })
+            })
+            .transpose()?;
+        let Some(chosen) = params
+            .flags
+            .or_else(|| template.is_some().then(String::new))
+        else {
+            return Ok(Reply::Now(AddWordWithFlags {
+                flags,
+                added: Vec::new(),
+            }));
+        };
+        if let Some(unknown) = affixes
+            .split_flags(&chosen)
+            .into_iter()
+            .find(|flag| affixes.rule(flag).is_none())
+        {
+            return Err(RequestError::new(
+                ErrorCode::InvalidParams,
+                format!("unknown affix flag: {}", unknown),
+            )
+            .into());
+        }
+        let chosen = match template {
+            Some(template) => affixes.merge_flags(&[&chosen, &template]),
+            None => chosen,
+        };
+        let forms = affixes.forms(&params.word, &chosen);
+        let answer = serde_json::to_value(AddWordWithFlags {
+            flags,
+            added: forms.clone(),
+        })?;
+        let word = NewWord {
+            word: params.word,
+            flags: chosen,
+            forms,
+        };
+        self.add_word(
+            id,
+            AddWordWithFlagsRequest::METHOD,
+            config,
+            params.layer,
+            word,
+            answer,
+        )?;
+        Ok(Reply::Later)
+    }
+
+    /// The affix flags of the affix file of the document `uri`, none if its
+    /// engine doesn't use dictionary files.
+    fn make_affix_flags(&self, uri: &Url) -> Vec<AffixFlagInfo> {
+        let config = self.config_for(uri);
+        let affixes = dictionaries::affixes(&config).unwrap_or_default();
+        affixes
+            .rules()
+            .iter()
+            .map(|rule| AffixFlagInfo {
+                flag: rule.flag.clone(),
+                kind: rule.kind,
+                description: rule.description(),
+                affixes: rule.affixes(),
+                combines: rule.cross,
+            })
+            .collect()
+    }
+
+    /// Add `word` to the wordlist of `config` in `layer` on the checking
+    /// thread, then recheck open documents and answer request `id` with
+    /// `answer`.
+    fn add_word(
+        &mut self,
+        id: &RequestId,
+        method: &'static str,
+        config: Config,
+        layer: Layer,
+        word: NewWord,
+        answer: Value,
+    ) -> Result<()> {
+        if layer == Layer::Team && config.team_dictionary.is_none() {
+            return Err(RequestError::new(
+                ErrorCode::InvalidParams,
+                "no teamDictionary is configured".to_string(),
+            )
+            .into());
+        }
+        self.queue_update(Some(id), method, move |checker| {
+            let NewWord { word, flags, forms } = word;
+            if let Err(err) =
+                checker.add_word(&config, layer, &word, &flags, &forms)
+            {
+                return failed_task(err);
+            }
+            Box::new(move |server, connection| {
+                server.check_open_documents(connection)?;
+                Ok(answer)
+            })
+        })
+    }
+
+    fn send_document_language(
+        &self,
+        connection: &Connection,
+        uri: &Url,
+    ) -> Result<()> {
+        let Some(document) = self.documents.get(uri) else {
+            return Ok(());
+        };
+        let source = if document.chosen_language.is_some() {
+            LanguageSource::Chosen
+        } else if document.detected_language.is_some() {
+            LanguageSource::Detected
+        } else {
+            LanguageSource::Configured
+        };
+        let params = DocumentLanguage {
+            uri: uri.clone(),
+            language: self.config_for(uri).language,
+            source,
+        };
+        self.send(
+            connection,
+            Message::Notification(Notification::new(
+                DOCUMENT_LANGUAGE_NOTIFICATION.to_string(),
+                params,
+            )),
+        )
+    }
+
+    /// The folder of `uri` and its path as matched against the `include`,
+    /// `exclude` and override patterns. URIs of other schemes with absolute
+    /// paths, such as notebook cells, are located by their path as well,
+    /// others such as `untitled:` buffers belong to the first folder.
+    fn locate_uri(&self, uri: &Url) -> (&Folder, PathBuf) {
+        match uri.to_file_path() {
+            Ok(path) => self.locate(&path),
+            Err(()) if Path::new(uri.path()).is_absolute() => {
+                self.locate(Path::new(uri.path()))
+            }
+            Err(()) => (&self.folders[0], PathBuf::from(uri.path())),
+        }
+    }
+
+    /// The innermost workspace folder containing `path`, and `path`
+    /// relative to it. Paths outside every folder belong to the first one
+    /// and are returned unchanged.
+    fn locate(&self, path: &Path) -> (&Folder, PathBuf) {
+        self.folders
+            .iter()
+            .filter_map(|folder| {
+                path.strip_prefix(&folder.root)
+                    .ok()
+                    .map(|relative| (folder, relative.to_path_buf()))
+            })
+            .min_by_key(|(_, relative)| relative.components().count())
+            .unwrap_or((&self.folders[0], path.to_path_buf()))
+    }
+
+    /// Replace the workspace folders, falling back to the current
+    /// directory if there are none. Folders that remain keep their
+    /// settings, new ones start out with the client settings of the first
+    /// folder until the configuration is reloaded.
+    fn set_folders<'a>(&mut self, uris: impl IntoIterator<Item = &'a Url>) {
+        let mut roots = uris
+            .into_iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect::<Vec<_>>();
+        if roots.is_empty() {
+            roots.push(
+                std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            );
+        }
+
+        let client_settings = self.folders[0].client_settings.clone();
+        let mut previous = std::mem::take(&mut self.folders);
+        self.folders = roots
+            .into_iter()
+            .map(|root| {
+                match previous.iter().position(|folder| folder.root == root) {
+                    Some(index) => previous.swap_remove(index),
+                    None => Folder {
+                        client_settings: client_settings.clone(),
+                        ..Folder::new(root)
+                    },
+                }
+            })
+            .collect();
+    }
+
+    /// Text files of every workspace folder, each listed once.
+    fn workspace_files(&self) -> Vec<PathBuf> {
+        let mut files = self
+            .folders
+            .iter()
+            .flat_map(|folder| workspace::files(&folder.root))
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Watch the global configuration file and the project configuration
+    /// files of the workspace folders.
+    fn watch_config_files(&mut self) {
+        let mut files = Vec::from_iter(config::global_file());
+        files.extend(
+            self.folders
+                .iter()
+                .map(|folder| folder.root.join(config::PROJECT_FILE)),
+        );
+        self.config_watcher = watch_files(files, self.config_events.clone());
+    }
+
+    /// The personal, team and imported wordlists of every folder.
+    fn wordlist_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for folder in &self.folders {
+            let config = &folder.config;
+            files.extend(config.personal_dictionary.clone());
+            files.extend(config.team_dictionary.clone());
+            files.extend(personal::import_paths(config));
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Watch the wordlists of the workspace folders, so that edits made by
+    /// other programs, such as another instance of the server, are picked
+    /// up. The watcher is only replaced when the set of files changes.
+    fn watch_wordlists(&mut self) {
+        let files = self.wordlist_files();
+        if files == self.watched_wordlists {
+            return;
+        }
+        self.wordlist_watcher =
+            watch_files(files.clone(), self.wordlist_events.clone());
+        self.watched_wordlists = files;
+    }
+
+    /// An open document, unless it is excluded from checking.
+    fn checked_document(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri).filter(|_| self.is_checked(uri))
+    }
+
+    /// Queue the diagnostics of `uri`, replacing those not yet published.
+    fn publish(
+        &self,
+        uri: Url,
+        version: Option<i32>,
+        diagnostics: Vec<Finding>,
+    ) {
+        self.publisher.borrow_mut().push(uri, version, diagnostics);
+    }
+
+    /// Publish the queued diagnostics, if enough time passed since the
+    /// last batch.
+    fn flush_diagnostics(&self, connection: &Connection) -> Result<()> {
+        let due = self.publisher.borrow_mut().take_due(Instant::now());
+        for pending in due {
+            let diagnostics = pending
+                .diagnostics
+                .iter()
+                .map(|finding| finding.as_diagnostic(&pending.uri))
+                .collect();
+            let params = PublishDiagnosticsRef {
+                uri: &pending.uri,
+                diagnostics,
+                version: pending.version,
+            };
+            self.send(
+                connection,
+                Message::Notification(Notification::new(
+                    PublishDiagnostics::METHOD.to_string(),
+                    params,
+                )),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Queue `zspell/info` on the checking thread, which counts the words
+    /// of the wordlists.
+    fn info(&mut self, id: &RequestId) -> Result<Reply<Value>> {
+        let configs = self.folder_configs();
+        self.queue_task(id, InfoRequest::METHOD, move |checker, _| {
+            let wordlists = configs
+                .iter()
+                .map(|config| wordlists(checker, config))
+                .collect::<Vec<_>>();
+            Box::new(move |server, _| Ok(server.make_info(&wordlists)))
+        })?;
+        Ok(Reply::Later)
+    }
+
+    /// The result of `zspell/info`: the effective configuration of each
+    /// folder with its `wordlists`, the dictionaries in use, and the
+    /// optional features enabled for this client.
+    fn make_info(&self, wordlists: &[Vec<Value>]) -> Value {
+        let folders = self
+            .folders
+            .iter()
+            .zip(wordlists)
+            .map(|(folder, wordlists)| {
+                serde_json::json!({
+                    "root": folder.root,
+                    "configuration": folder.config,
+                    "wordlists": wordlists,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut used = self
+            .folders
+            .iter()
+            .flat_map(|folder| dictionaries::used(&folder.config))
+            .collect::<Vec<_>>();
+        used.sort_by(|a, b| a.dictionary.cmp(&b.dictionary));
+        used.dedup_by(|a, b| a.dictionary == b.dictionary);
+        let dictionaries = used
+            .iter()
+            .map(|files| {
+                serde_json::json!({
+                    "locale": files.locale,
+                    "affix": files.affix,
+                    "dictionary": files.dictionary,
+                    "entries": files.entries(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "folders": folders,
+            "dictionaries": dictionaries,
+            "features": {
+                "suggestions": true,
+                "pullConfiguration": self.pull_configuration,
+                "watchedFiles": self.register_watched_files,
+            },
+        })
+    }
+
+    /// Queue listing the words added to the dictionaries of every folder on
+    /// the checking thread, listing those of dictionaries shared by folders
+    /// once.
+    fn user_words(&mut self, id: &RequestId) -> Result<Reply<Vec<UserWord>>> {
+        let configs = self.folder_configs();
+        self.queue_task(
+            id,
+            ListUserWordsRequest::METHOD,
+            move |checker, _| {
+                let mut seen = HashSet::new();
+                let mut words = Vec::new();
+                for config in &configs {
+                    for word in checker.user_words(config) {
+                        if seen.insert(word.clone()) {
+                            words.push(word);
+                        }
+                    }
+                }
+                Box::new(move |_, _| Ok(serde_json::to_value(words)?))
+            },
+        )?;
+        Ok(Reply::Later)
+    }
+
+    fn make_autocorrections(&self, uri: &Url) -> Vec<TextEdit> {
+        let mut edits = Vec::new();
+
+        let config = self.config_for(uri);
+        if !config.autocorrect_on_save {
+            return edits;
+        }
+
+        let Some(document) = self.checked_document(uri) else {
+            return edits;
+        };
+
+        // Only words that are checked are corrected
+        for word in checker::checked_words(&document.text, &config) {
+            if let Some(correction) = config.substitutions.get(word.text) {
+                edits.push(TextEdit {
+                    range: word.range,
+                    new_text: correction.clone(),
+                });
+            }
+        }
+
+        edits
+    }
+
+    /// The thesaurus of `language`, opened on first use.
+    fn thesaurus(
+        &self,
+        config: &Config,
+        language: Option<&str>,
+    ) -> Option<Arc<Thesaurus>> {
+        let path = dictionaries::thesaurus(config, language)?;
+        self.thesauri
+            .borrow_mut()
+            .entry(path)
+            .or_insert_with_key(|path| match Thesaurus::open(path) {
+                Ok(thesaurus) => Some(Arc::new(thesaurus)),
+                Err(err) => {
+                    warn!("{:#}", err);
+                    None
+                }
+            })
+            .clone()
+    }
+
+    /// `draft` with the synonyms of its word, or `None` if there's
+    /// nothing to show.
+    fn finish_hover(
+        &self,
+        draft: HoverDraft,
+        config: &Config,
+    ) -> Option<Hover> {
+        let mut value = draft.value;
+        let mut empty = draft.empty;
+        if let Some((word, language)) = &draft.synonyms_of {
+            let meanings = self
+                .thesaurus(config, language.as_deref())
+                .map(|thesaurus| thesaurus.meanings(word))
+                .unwrap_or_default();
+            for meaning in meanings.iter().take(MAX_MEANINGS) {
+                value.push_str("\n\n");
+                if !meaning.part_of_speech.is_empty() {
+                    value.push_str(&format!("*{}*: ", meaning.part_of_speech));
+                }
+                value.push_str(&meaning.synonyms.join(", "));
+            }
+            empty &= meanings.is_empty();
+        }
+
+        if empty {
+            return None;
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(draft.range),
+        })
+    }
+}
+
+/// Do the `jobs` until the server stops, sending the diagnostics of checked
+/// documents and finished tasks to `results`. Jobs are done with a fork of
+/// `shared`, made again after each update of it, or with `shared` itself
+/// if its engines can't be forked. Documents changed again
+/// while waiting are only checked once, in their latest version. Long
+/// documents are checked in chunks, sending the diagnostics found so far
+/// after each, and given up on as soon as they change again.
+fn run_checks(
+    shared: &Mutex<Checker>,
+    jobs: crossbeam_channel::Receiver<Job>,
+    results: crossbeam_channel::Sender<Done>,
+) {
+    let mut own = shared.lock().unwrap().fork().map(Box::new);
+    let mut pending = VecDeque::new();
+    let mut interner = Interner::default();
+    let is_check_of = |job: &Job, uri: &Url| matches!(job, Job::Check(job) if job.uri == *uri);
+    let receive = |pending: &mut VecDeque<Job>| {
+        while let Ok(job) = jobs.try_recv() {
+            if let Job::Check(check) = &job {
+                pending.retain(|pending| !is_check_of(pending, &check.uri));
+            }
+            pending.push_back(job);
+        }
+    };
+    while let Ok(job) = jobs.recv() {
+        pending.push_back(job);
+        receive(&mut pending);
+        'jobs: while let Some(job) = pending.pop_front() {
+            let job = match job {
+                Job::Check(job) => job,
+                Job::Task(task) => {
+                    let finish = match task.work {
+                        Work::Read(work) => {
+                            with_checker(&mut own, shared, |checker| {
+                                work(checker, &task.cancelled)
+                            })
+                        }
+                        Work::Update(work) => {
+                            let mut checker = shared.lock().unwrap();
+                            let finish = work(&mut checker);
+                            own = checker.fork().map(Box::new);
+                            finish
+                        }
+                    };
+                    if results
+                        .send(Done::Task(task.id, task.method, finish))
+                        .is_err()
+                    {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let start = Instant::now();
+            let mut config = job.config.clone();
+            let detected = job.detect.then(|| {
+                with_checker(&mut own, shared, |checker| {
+                    checker
+                        .detect_language(&job.text, &config.detect_languages)
+                        .map(str::to_string)
+                })
+            });
+            if let Some(Some(language)) = &detected {
+                config.language = Some(language.clone());
+            }
+            let changed = job
+                .head
+                .as_ref()
+                .map(|head| ChangedLines::between(head, &job.text));
+            let is_changed = |diagnostic: &Finding| {
+                changed.as_ref().is_none_or(|changed| {
+                    changed.contains(diagnostic.range.start.line)
+                })
+            };
+            // Later chunks are lexed without the header of delimited values
+            let rest = Config {
+                syntax: config.syntax.after_first_line(&job.text),
+                ..config.clone()
+            };
+            let chunks = chunks(&job.text, CHUNK_LINES, &rest.syntax);
+            let mut diagnostics = Vec::from_iter(
+                long_line_diagnostic(&job.text, &config, &mut interner)
+                    .filter(is_changed),
+            );
+            for (index, (first_line, chunk)) in chunks.iter().enumerate() {
+                let config = if index == 0 { &config } else { &rest };
+                let found = with_checker(&mut own, shared, |checker| {
+                    checker.set_cache_budget(job.cache_budget);
+                    make_diagnostics(checker, chunk, config, &mut interner)
+                });
+                diagnostics.extend(
+                    found
+                        .into_iter()
+                        .map(|mut diagnostic| {
+                            diagnostic.range.start.line += first_line;
+                            diagnostic.range.end.line += first_line;
+                            diagnostic
+                        })
+                        .filter(is_changed),
+                );
+                if index + 1 == chunks.len() {
+                    break;
+                }
+                receive(&mut pending);
+                if pending.iter().any(|pending| is_check_of(pending, &job.uri))
+                {
+                    continue 'jobs;
+                }
+                let partial = CheckResult {
+                    uri: job.uri.clone(),
+                    version: job.version,
+                    revision: job.revision,
+                    detected: detected.clone(),
+                    diagnostics: report_duplicates(
+                        &diagnostics,
+                        config.report_duplicates,
+                    ),
+                };
+                if results.send(Done::Check(partial)).is_err() {
+                    return;
+                }
+            }
+            metrics().checked(start.elapsed());
+            let result = CheckResult {
+                uri: job.uri,
+                version: job.version,
+                revision: job.revision,
+                detected,
+                diagnostics: report_duplicates(
+                    &diagnostics,
+                    config.report_duplicates,
+                ),
+            };
+            if results.send(Done::Check(result)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Build the dictionaries of `checker` again from their files, the default
+/// one being selected by the first of the folder `configs`. Personal
+/// dictionaries are left empty until the configuration is reloaded.
+fn reload_dictionaries(
+    checker: &mut Checker,
+    configs: &[Config],
+) -> Result<()> {
+    let config = &configs[0];
+    let paths = dictionaries::search_paths(&config.dictionary_paths);
+    let backend = dictionaries::default_backend(config, &paths)?;
+    *checker = Checker::new(backend, config.language.clone());
+    for config in configs {
+        dictionaries::load_languages(checker, config)?;
+    }
+    Ok(())
+}
+
+/// Call `f` with `own`, or with `shared` locked if there's none.
+fn with_checker<T>(
+    own: &mut Option<Box<Checker>>,
+    shared: &Mutex<Checker>,
+    f: impl FnOnce(&mut Checker) -> T,
+) -> T {
+    match own {
+        Some(checker) => f(checker),
+        None => f(&mut shared.lock().unwrap()),
+    }
+}
+
+/// `text` split into chunks of at least `lines` lines, with the line each
+/// starts at. Chunks only end on lines leaving no block of `syntax` open,
+/// such as a fenced code block, so that each is lexed as it would be within
+/// the whole text.
+fn chunks<'a>(
+    text: &'a str,
+    lines: usize,
+    syntax: &Syntax,
+) -> Vec<(u32, &'a str)> {
+    let mut chunks = Vec::new();
+    let mut block = Block::None;
+    let mut start = 0;
+    let mut first_line = 0;
+    let mut line_start = 0;
+    let mut line_number = 0;
+    for (offset, _) in text.match_indices('\n') {
+        let line = &text[line_start..offset];
+        syntax.masked(line.trim_end_matches('\r'), &mut block);
+        line_start = offset + 1;
+        line_number += 1;
+        if line_number - first_line >= lines as u32 && block == Block::None {
+            chunks.push((first_line, &text[start..offset + 1]));
+            start = offset + 1;
+            first_line = line_number;
+        }
+    }
+    if start < text.len() || chunks.is_empty() {
+        chunks.push((first_line, &text[start..]));
+    }
+    chunks
+}
+
+/// The diagnostics of each file of a workspace scan.
+type ScanDiagnostics = Vec<(Url, Vec<Finding>)>;
+
+/// Check `files`, returning a summary of the findings and the diagnostics
+/// of each file, or `None` if cancelled first.
+fn scan_workspace(
+    checker: &Checker,
+    files: Vec<WorkspaceFile>,
+    cancelled: &AtomicBool,
+) -> Option<(Value, ScanDiagnostics)> {
+    let mut files_checked = 0;
+    let mut misspellings = 0;
+    let mut interner = Interner::default();
+    let mut published = Vec::new();
+
+    for file in files {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let config = &file.config;
+        let size = match &file.text {
+            Some(text) => text.len(),
+            None => std::fs::metadata(&file.path)
+                .map_or(0, |metadata| metadata.len() as usize),
+        };
+        if let Some(diagnostic) =
+            file_size_diagnostic(size, config, &mut interner)
+        {
+            published.push((file.uri, vec![diagnostic]));
+            continue;
+        }
+        let Some(text) = file.read() else {
+            continue;
+        };
+        if Skip::of(text.as_bytes(), config).is_some() {
+            published.push((file.uri, Vec::new()));
+            continue;
+        }
+        let changed = if config.only_changed_lines {
+            ChangedLines::since_head(&file.path, &text)
+        } else {
+            None
+        };
+        let is_changed = |line| {
+            changed
+                .as_ref()
+                .is_none_or(|changed| changed.contains(line))
+        };
+        let start = Instant::now();
+        let (mut words, issues) = checker.check(&text, config);
+        words.retain(|word| is_changed(word.range.start.line));
+        files_checked += 1;
+        misspellings += words.len();
+        let mut diagnostics =
+            words_to_diagnostics(checker, &text, &words, config, &mut interner);
+        diagnostics.extend(issue_diagnostics(
+            checker,
+            &text,
+            issues,
+            config,
+            &mut interner,
+        ));
+        diagnostics.extend(long_line_diagnostic(&text, config, &mut interner));
+        diagnostics
+            .retain(|diagnostic| is_changed(diagnostic.range.start.line));
+        metrics().checked(start.elapsed());
+        let diagnostics =
+            report_duplicates(&diagnostics, config.report_duplicates);
+        published.push((file.uri, diagnostics));
+    }
+
+    let report = serde_json::json!({
+        "filesChecked": files_checked,
+        "misspellings": misspellings,
+    });
+    Some((report, published))
+}
+
+/// Replace every misspelling with its top suggestion, skipping words
+/// without suggestions.
+fn make_fixes(checker: &Checker, text: &str, config: &Config) -> Vec<TextEdit> {
+    let sections = checker.sections(text, config);
+    checker
+        .misspelled_words(text, config)
+        .into_iter()
+        .filter_map(|word| {
+            let suggestion = checker
+                .suggest_in(&word, &sections, config)
+                .into_iter()
+                .next()?;
+            Some(TextEdit {
+                range: word.range,
+                new_text: suggestion,
+            })
+        })
+        .collect()
+}
+
+/// Replace every misspelled occurrence of `word` with `replacement`.
+fn make_replace_all(
+    checker: &Checker,
+    text: &str,
+    config: &Config,
+    word: &str,
+    replacement: &str,
+) -> Vec<TextEdit> {
+    checker
+        .misspelled_words(text, config)
+        .into_iter()
+        .filter(|misspelled| misspelled.text == word)
+        .map(|misspelled| TextEdit {
+            range: misspelled.range,
+            new_text: replacement.to_string(),
+        })
+        .collect()
+}
+
+/// Replace every misspelled occurrence of `word` in `files`, skipping
+/// those that can't be read, unless cancelled.
+fn make_workspace_replace_all(
+    checker: &Checker,
+    files: &[WorkspaceFile],
+    word: &str,
+    replacement: &str,
+    cancelled: &AtomicBool,
+) -> Option<WorkspaceEdit> {
+    let mut changes = HashMap::new();
+    for file in files {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let Some(text) = file.read() else {
+            continue;
+        };
+        let edits =
+            make_replace_all(checker, &text, &file.config, word, replacement);
+        if !edits.is_empty() {
+            changes.insert(file.uri.clone(), edits);
+        }
+    }
+    Some(WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    })
+}
+
+/// The wordlists of `config` with the number of words in each.
+fn wordlists(checker: &Checker, config: &Config) -> Vec<Value> {
+    [
+        (Layer::Personal, &config.personal_dictionary),
+        (Layer::Team, &config.team_dictionary),
+    ]
+    .into_iter()
+    .filter_map(|(layer, path)| {
+        let words = checker.wordlist_len(config, layer)?;
+        Some(serde_json::json!({
+            "layer": layer,
+            "path": path,
+            "words": words,
+        }))
+    })
+    .collect()
+}
+
+/// The answer to a task that failed with `err`.
+fn failed_task(err: anyhow::Error) -> Finish {
+    Box::new(move |_, _| Err(err))
+}
+
+/// The answer to a task cancelled before it was done.
+fn cancelled_task() -> Finish {
+    Box::new(|_, _| {
+        Err(
+            RequestError::new(ErrorCode::RequestCanceled, "request cancelled")
+                .into(),
+        )
+    })
+}
+
+/// Quick fixes for the misspellings and other issues of `text` in
+/// `range`.
+fn make_code_actions(
+    checker: &Checker,
+    uri: &Url,
+    text: &str,
+    config: &Config,
+    range: lsp_types::Range,
+) -> CodeActionResponse {
+    let mut actions = CodeActionResponse::new();
+
+    let misspelled = checker.misspelled_words(text, config);
+    let sections = checker.sections(text, config);
+    for word in misspelled.iter().filter(|word| {
+        word.range.start <= range.end && range.start <= word.range.end
+    }) {
+        let occurrences = misspelled
+            .iter()
+            .filter(|other| other.text == word.text)
+            .count();
+
+        for suggestion in checker.suggest_in(word, &sections, config) {
+            let edits = vec![TextEdit {
+                range: word.range,
+                new_text: suggestion.clone(),
+            }];
+            actions.push(
+                CodeAction {
+                    title: format!("Change to '{}'", suggestion),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+
+            if occurrences > 1 {
+                actions.push(
+                    CodeAction {
+                        title: format!(
+                            "Change all {} occurrences of '{}' to '{}'",
+                            occurrences, word.text, suggestion
+                        ),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        command: Some(Command {
+                            title: "Correct all".to_string(),
+                            command: CORRECT_ALL_COMMAND.to_string(),
+                            arguments: Some(vec![
+                                serde_json::to_value(uri).unwrap(),
+                                word.text.into(),
+                                suggestion.clone().into(),
+                            ]),
+                        }),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
+
+            actions.push(
+                CodeAction {
+                    title: format!(
+                        "Change all '{}' to '{}' in workspace",
+                        word.text, suggestion
+                    ),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    command: Some(Command {
+                        title: "Correct in workspace".to_string(),
+                        command: CORRECT_WORKSPACE_COMMAND.to_string(),
+                        arguments: Some(vec![
+                            word.text.into(),
+                            suggestion.into(),
+                        ]),
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        let mut layers = vec![(Layer::Personal, "personal")];
+        if config.team_dictionary.is_some() {
+            layers.push((Layer::Team, "team"));
+        }
+        for (layer, name) in layers {
+            actions.push(
+                CodeAction {
+                    title: format!(
+                        "Add '{}' to {} dictionary",
+                        word.text, name
+                    ),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    command: Some(Command {
+                        title: "Add to dictionary".to_string(),
+                        command: ADD_TO_DICTIONARY_COMMAND.to_string(),
+                        arguments: Some(vec![
+                            serde_json::to_value(uri).unwrap(),
+                            word.text.into(),
+                            serde_json::to_value(layer).unwrap(),
+                        ]),
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+    }
+
+    for (issue_range, issue) in checker.issues(text, config) {
+        if issue_range.start > range.end || range.start > issue_range.end {
+            continue;
+        }
+        for replacement in issue.replacements {
+            let edits = vec![TextEdit {
+                range: issue_range,
+                new_text: replacement.clone(),
+            }];
+            actions.push(
+                CodeAction {
+                    title: format!("Change to '{}'", replacement),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+    }
+
+    actions
+}
+
+/// Code lenses counting the misspellings of `text`, to fix them all or add
+/// them all to the dictionary.
+fn make_code_lenses(
+    checker: &Checker,
+    uri: &Url,
+    text: &str,
+    config: &Config,
+) -> Vec<CodeLens> {
+    let count = checker.misspelled_words(text, config).len();
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let range = lsp_types::Range::default();
+    let arguments = Some(vec![serde_json::to_value(uri).unwrap()]);
+    let noun = if count == 1 {
+        "misspelling"
+    } else {
+        "misspellings"
+    };
+    vec![
+        CodeLens {
+            range,
+            command: Some(Command {
+                title: format!("{} {} — fix all", count, noun),
+                command: FIX_ALL_COMMAND.to_string(),
+                arguments: arguments.clone(),
+            }),
+            data: None,
+        },
+        CodeLens {
+            range,
+            command: Some(Command {
+                title: "add all to dictionary".to_string(),
+                command: ADD_ALL_TO_DICTIONARY_COMMAND.to_string(),
+                arguments,
+            }),
+            data: None,
+        },
+    ]
+}
+
+/// The occurrences in `text` of the misspelling at `position`.
+fn make_highlights(
+    checker: &Checker,
+    text: &str,
+    config: &Config,
+    position: Position,
+) -> Vec<DocumentHighlight> {
+    let misspelled = checker.misspelled_words(text, config);
+    let Some(target) = misspelled.iter().find(|word| {
+        word.range.start <= position && position <= word.range.end
+    }) else {
+        return Vec::new();
+    };
+
+    misspelled
+        .iter()
+        .filter(|word| word.text == target.text)
+        .map(|word| DocumentHighlight {
+            range: word.range,
+            kind: Some(DocumentHighlightKind::TEXT),
+        })
+        .collect()
+}
+
+/// The number of occurrences of the misspelled word at `position`, the
+/// other loaded languages that know it and the stems of its top
+/// suggestions, or the stems and analysis of the word if it's spelled
+/// correctly.
+fn make_hover(
+    checker: &Checker,
+    text: &str,
+    config: &Config,
+    position: Position,
+) -> Option<HoverDraft> {
+    let sections = checker.sections(text, config);
+    let misspelled = checker.misspelled_words(text, config);
+    let Some(target) = misspelled.iter().find(|word| {
+        word.range.start <= position && position <= word.range.end
+    }) else {
+        return make_word_hover(checker, text, config, &sections, position);
+    };
+
+    let occurrences = misspelled
+        .iter()
+        .filter(|word| word.text == target.text)
+        .count();
+    let mut value = match occurrences {
+        1 => format!("**{}**: 1 occurrence in this document", target.text),
+        count => format!(
+            "**{}**: {} occurrences in this document",
+            target.text, count
+        ),
+    };
+    let languages = checker.languages_knowing(target.text, config);
+    if languages.is_empty() {
+        value.push_str("\n\nNot known in any loaded language");
+    } else {
+        let languages = languages
+            .iter()
+            .map(|language| format!("`{}`", language))
+            .collect::<Vec<_>>();
+        value.push_str(&format!("\n\nKnown in {}", languages.join(", ")));
+    }
+
+    let line = target.range.start.line;
+    let suggestions = checker
+        .suggest_in(target, &sections, config)
+        .into_iter()
+        .take(MAX_STEMMED_SUGGESTIONS)
+        .filter_map(|suggestion| {
+            let stems = checker.stems_at(&suggestion, line, &sections, config);
+            (!stems.is_empty())
+                .then(|| format!("`{}` ({})", suggestion, code(&stems)))
+        })
+        .collect::<Vec<_>>();
+    if !suggestions.is_empty() {
+        value.push_str(&format!(
+            "\n\nStems of suggestions: {}",
+            suggestions.join(", ")
+        ));
+    }
+
+    Some(HoverDraft {
+        value,
+        range: target.range,
+        synonyms_of: None,
+        empty: false,
+    })
+}
+
+/// The stems and morphological analysis of the correct word at `position`
+/// of `text`, leaving room for its synonyms.
+fn make_word_hover(
+    checker: &Checker,
+    text: &str,
+    config: &Config,
+    sections: &Sections,
+    position: Position,
+) -> Option<HoverDraft> {
+    let mut lexer = checker::lexer(text, config)?;
+    let word = loop {
+        let word = lexer.next()?;
+        if word.range.start <= position && position <= word.range.end {
+            break *word;
+        }
+    };
+
+    let line = word.range.start.line;
+    let mut value = format!("**{}**", word.text);
+    let stems = checker.stems_at(word.text, line, sections, config);
+    if !stems.is_empty() {
+        value.push_str(&format!("\n\nStems: {}", code(&stems)));
+    }
+    let analysis = checker.analyze_at(word.text, line, sections, config);
+    if !analysis.is_empty() {
+        value.push_str(&format!("\n\nAnalysis: {}", code(&analysis)));
+    }
+
+    let language = sections
+        .language_at(line)
+        .or(config.language.as_deref())
+        .map(str::to_string);
+    Some(HoverDraft {
+        value,
+        range: word.range,
+        synonyms_of: Some((word.text.to_string(), language)),
+        empty: stems.is_empty() && analysis.is_empty(),
+    })
+}
+
+/// Semantic tokens marking the misspellings of `text`.
+fn make_semantic_tokens(
+    checker: &Checker,
+    text: &str,
+    config: &Config,
+) -> SemanticTokens {
+    let mut tokens = SemanticTokens::default();
+    let mut previous = Position::default();
+    for word in checker.misspelled_words(text, config) {
+        let start = word.range.start;
+        let delta_line = start.line - previous.line;
+        let delta_start = if delta_line == 0 {
+            start.character - previous.character
+        } else {
+            start.character
+        };
+        tokens.data.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: word.range.end.character - start.character,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        });
+        previous = start;
+    }
+    tokens
+}
+
+fn make_diagnostics(
+    checker: &Checker,
+    text: &str,
+    config: &Config,
+    interner: &mut Interner,
+) -> Vec<Finding> {
+    let (words, issues) = checker.check(text, config);
+    let mut diagnostics =
+        words_to_diagnostics(checker, text, &words, config, interner);
+    diagnostics
+        .extend(issue_diagnostics(checker, text, issues, config, interner));
+    diagnostics
+}
+
+/// Diagnostics for the `issues` other than misspellings found by the
+/// backend, such as grammar issues.
+fn issue_diagnostics(
+    checker: &Checker,
+    text: &str,
+    issues: Vec<(lsp_types::Range, Issue)>,
+    config: &Config,
+    interner: &mut Interner,
+) -> Vec<Finding> {
+    if config.presentation != Presentation::Diagnostics {
+        return Vec::new();
+    }
+
+    let casing = checker.casing(config.language.as_deref());
+    issues
+        .into_iter()
+        .map(|(range, issue)| {
+            let word = &text[issue.span];
+            Finding {
+                range,
+                severity: DiagnosticSeverity::WARNING,
+                rule: interner
+                    .intern(issue.rule.as_deref().unwrap_or_default()),
+                message: interner.intern(&issue.message),
+                word: interner.intern(word),
+                normalized: interner.intern(&casing.lowercase(word)),
+                suggestions: Some(!issue.replacements.is_empty()),
+                related: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+fn words_to_diagnostics(
+    checker: &Checker,
+    text: &str,
+    words: &[Word],
+    config: &Config,
+    interner: &mut Interner,
+) -> Vec<Finding> {
+    if config.presentation != Presentation::Diagnostics {
+        return Vec::new();
+    }
+
+    let sections = checker.sections(text, config);
+    let casing = checker.casing(config.language.as_deref());
+    // The shared strings of each word
+    let mut seen = HashMap::new();
+    words
+        .iter()
+        .map(|word| {
+            let (shared, normalized) = seen
+                .entry(word.text)
+                .or_insert_with(|| {
+                    (
+                        interner.intern(word.text),
+                        interner.intern(&casing.lowercase(word.text)),
+                    )
+                })
+                .clone();
+            let forbidden = checker.is_forbidden(word, &sections, config);
+            let (rule, message, severity) = if forbidden {
+                (
+                    FORBIDDEN_RULE,
+                    "Forbidden word",
+                    diagnostic_severity(config.forbidden_severity),
+                )
+            } else if config.check_identifiers
+                && checker::is_identifier(text, word)
+            {
+                (
+                    IDENTIFIER_RULE,
+                    "Incorrect spelling in identifier",
+                    DiagnosticSeverity::HINT,
+                )
+            } else {
+                (
+                    MISSPELLING_RULE,
+                    "Incorrect spelling",
+                    DiagnosticSeverity::ERROR,
+                )
+            };
+            Finding {
+                range: word.range,
+                severity,
+                rule: interner.intern(rule),
+                message: interner.intern(message),
+                word: shared,
+                normalized,
+                // Looked for by code actions, too costly for every check
+                suggestions: None,
+                related: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// A note on the first line of `text` too long to be checked, with the
+/// number of such lines.
+fn long_line_diagnostic(
+    text: &str,
+    config: &Config,
+    interner: &mut Interner,
+) -> Option<Finding> {
+    if config.presentation != Presentation::Diagnostics {
+        return None;
+    }
+
+    let max_line_length = config.max_line_length();
+    let mut lines = lexer::long_lines(text, max_line_length);
+    let first = lines.next()?;
+    let message = match lines.count() {
+        0 => format!("Line longer than {} bytes not checked", max_line_length),
+        others => format!(
+            "{} lines longer than {} bytes not checked",
+            others + 1,
+            max_line_length
+        ),
+    };
+    let start = Position::new(first, 0);
+    Some(Finding {
+        range: lsp_types::Range { start, end: start },
+        severity: DiagnosticSeverity::INFORMATION,
+        rule: interner.intern(LONG_LINE_RULE),
+        message: interner.intern(&message),
+        word: interner.intern(""),
+        normalized: interner.intern(""),
+        suggestions: Some(false),
+        related: Vec::new(),
+    })
+}
+
+/// An informational diagnostic at the start of a file of `size` bytes
+/// saying it isn't checked, if it's larger than `maxFileSize`.
+fn file_size_diagnostic(
+    size: usize,
+    config: &Config,
+    interner: &mut Interner,
+) -> Option<Finding> {
+    let max_file_size = config.max_file_size();
+    if size <= max_file_size || config.presentation != Presentation::Diagnostics
+    {
+        return None;
+    }
+
+    let message =
+        format!("File larger than {} bytes not checked", max_file_size);
+    let start = Position::new(0, 0);
+    Some(Finding {
+        range: lsp_types::Range { start, end: start },
+        severity: DiagnosticSeverity::INFORMATION,
+        rule: interner.intern(FILE_SIZE_RULE),
+        message: interner.intern(&message),
+        word: interner.intern(""),
+        normalized: interner.intern(""),
+        suggestions: Some(false),
+        related: Vec::new(),
+    })
+}
+
+/// `findings` with the occurrences of each misspelled word after the first
+/// left out as `report` says, as other occurrences of the first or as
+/// their number in its message. Summaries group the word in any case.
+fn report_duplicates(
+    findings: &[Finding],
+    report: ReportDuplicates,
+) -> Vec<Finding> {
+    if report == ReportDuplicates::All {
+        return findings.to_vec();
+    }
+
+    let mut reported = Vec::<Finding>::new();
+    // The index of the first finding of each rule and word
+    let mut first = HashMap::<_, usize>::new();
+    let summary = report == ReportDuplicates::Summary;
+    for finding in findings {
+        if !finding.is_misspelling() {
+            reported.push(finding.clone());
+            continue;
+        }
+        let word = match summary {
+            true => &finding.normalized,
+            false => &finding.word,
+        };
+        let key = (finding.rule.clone(), word.clone());
+        match first.get(&key) {
+            Some(&index) => {
+                reported[index].related.push(finding.range);
+            }
+            None => {
+                first.insert(key, reported.len());
+                reported.push(finding.clone());
+            }
+        }
+    }
+
+    match report {
+        ReportDuplicates::Count => {
+            for finding in &mut reported {
+                let others = std::mem::take(&mut finding.related);
+                if !others.is_empty() {
+                    let message = format!(
+                        "{} ({} occurrences)",
+                        finding.message,
+                        others.len() + 1
+                    );
+                    finding.message = message.into();
+                }
+            }
+        }
+        ReportDuplicates::Summary => {
+            for finding in reported
+                .iter_mut()
+                .filter(|finding| finding.is_misspelling())
+            {
+                let occurrences = match finding.related.len() + 1 {
+                    1 => "1 occurrence".to_string(),
+                    count => format!("{} occurrences", count),
+                };
+                let message = format!(
+                    "{}: '{}', {}",
+                    finding.message, finding.word, occurrences
+                );
+                finding.message = message.into();
+            }
+        }
+        ReportDuplicates::All | ReportDuplicates::First => {}
+    }
+    reported
+}
+
+/// `items` as a list of inline code.
+fn code(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("`{}`", item))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Decode the arguments of a command, failing the request with
+/// `InvalidParams` if they don't match.
+fn command_arguments<T: DeserializeOwned>(arguments: Value) -> Result<T> {
+    serde_json::from_value(arguments).map_err(|err| {
+        RequestError::new(
+            ErrorCode::InvalidParams,
+            format!("invalid command arguments: {}", err),
+        )
+        .into()
+    })
+}
+
+fn diagnostic_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Information => DiagnosticSeverity::INFORMATION,
+        Severity::Hint => DiagnosticSeverity::HINT,
+    }
+}
+
+/// Watch `files`, sending an event whenever one of them changes. Their
+/// directories are watched rather than the files themselves, so that files
+/// created later, or replaced by editors on save, are noticed too.
+fn watch_files(
+    files: Vec<PathBuf>,
+    sender: crossbeam_channel::Sender<()>,
+) -> Option<RecommendedWatcher> {
+    let watched = files.clone();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if event.kind.is_access() {
+                return;
+            }
+            if event.paths.iter().any(|path| watched.contains(path)) {
+                _ = sender.send(());
+            }
+        })
+        .map_err(|err| warn!("unable to watch files: {}", err))
+        .ok()?;
+
+    for file in &files {
+        let Some(dir) = file.parent() else {
+            continue;
+        };
+        if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            info!("not watching {}: {}", dir.display(), err);
+        }
+    }
+
+    Some(watcher)
+}
+
+/// `path` resolved against the current directory, without `.` components, as
+/// reported back by clients in file URIs.
+fn absolute_path(path: &Path) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path).components().collect(),
+        Err(_) => path.to_path_buf(),
     }
 }
@@ -0,0 +1,135 @@
+//! Recognizing files not worth checking, such as images opened by accident
+//! or generated sources, from the start of their content.
+
+use crate::config::Config;
+use std::fmt;
+
+/// Bytes at the start of files looked at.
+const SAMPLE: usize = 8192;
+
+/// Lines at the start of files searched for generated-file markers.
+const MARKER_LINES: usize = 5;
+
+/// Markers of generated files, in lowercase.
+const MARKERS: &[&str] = &[
+    "@generated",
+    "do not edit",
+    "auto-generated",
+    "autogenerated",
+    "code generated",
+];
+
+/// Why a file isn't checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    /// The file is larger than `maxFileSize`.
+    TooLarge,
+    /// The file contains NUL bytes.
+    Binary,
+    /// The file is made of long lines with hardly any whitespace, as
+    /// minified scripts or base64 data.
+    Minified,
+    /// The file says it's generated, as with `// Code generated ... DO NOT
+    /// EDIT.`
+    Generated,
+}
+
+impl Skip {
+    /// Why the file of `bytes` shouldn't be checked, if it shouldn't.
+    /// Minified and generated files are checked if `checkGeneratedFiles`
+    /// is set.
+    pub fn of(bytes: &[u8], config: &Config) -> Option<Self> {
+        if bytes.len() > config.max_file_size() {
+            return Some(Skip::TooLarge);
+        }
+        let sample = &bytes[..bytes.len().min(SAMPLE)];
+        if sample.contains(&0) {
+            return Some(Skip::Binary);
+        }
+        if config.check_generated_files {
+            return None;
+        }
+        if is_minified(sample) {
+            return Some(Skip::Minified);
+        }
+        let head = sample
+            .split(|&byte| byte == b'\n')
+            .take(MARKER_LINES)
+            .map(|line| String::from_utf8_lossy(line).to_lowercase());
+        for line in head {
+            if MARKERS.iter().any(|marker| line.contains(marker)) {
+                return Some(Skip::Generated);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Skip::TooLarge => "large",
+            Skip::Binary => "binary",
+            Skip::Minified => "minified",
+            Skip::Generated => "generated",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// Whether `sample` has a line of more than 500 bytes and less than one
+/// whitespace character in 20. Text in scripts without spaces between
+/// words, such as Chinese, is mostly outside of ASCII and never counts.
+fn is_minified(sample: &[u8]) -> bool {
+    let ascii = sample.iter().filter(|byte| byte.is_ascii()).count();
+    if ascii < 1024 || ascii < sample.len() * 9 / 10 {
+        return false;
+    }
+    let long_line = sample
+        .split(|&byte| byte == b'\n')
+        .any(|line| line.len() > 500);
+    let whitespace = sample
+        .iter()
+        .filter(|byte| byte.is_ascii_whitespace())
+        .count();
+    long_line && whitespace * 20 < ascii
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn skipped() {
+        let config = Config::default();
+        let skip = |text: &[u8]| Skip::of(text, &config);
+        let prose = "Hello wrld, see the world. ".repeat(100);
+        let minified = format!("var a={};", "[1,2,3],".repeat(200));
+        let chinese = "你好世界".repeat(500);
+
+        assert_eq!(skip(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(Skip::Binary));
+        assert_eq!(skip(minified.as_bytes()), Some(Skip::Minified));
+        assert_eq!(
+            skip(b"// Code generated by protoc. DO NOT EDIT.\npackage x"),
+            Some(Skip::Generated)
+        );
+        assert_eq!(skip(prose.as_bytes()), None);
+        assert_eq!(skip(chinese.as_bytes()), None);
+        assert_eq!(skip(b"1\n2\n3\n4\n5\nDo not edit this line.\n"), None);
+
+        let config = Config {
+            check_generated_files: true,
+            ..Default::default()
+        };
+        assert_eq!(Skip::of(minified.as_bytes(), &config), None);
+        assert_eq!(Skip::of(b"a\0b", &config), Some(Skip::Binary));
+
+        let config = Config {
+            max_file_size: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(Skip::of(b"hello", &config), Some(Skip::TooLarge));
+        assert_eq!(Skip::of(b"see", &config), None);
+    }
+}
@@ -0,0 +1,778 @@
+//! Parts of documents in particular formats that aren't prose, such as
+//! the code spans of Rust doc comments or the macros of man pages, left
+//! out by the lexer.
+
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::Path;
+
+/// The format of a document, deciding which parts of it are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Plain,
+    /// Rust source, whose doc comments are markdown with intra-doc links.
+    Rust,
+    /// Roff sources of man pages, with macros, requests and escapes.
+    Roff,
+    /// Vim help files, with tags, links and examples.
+    VimHelp,
+    /// SubRip and WebVTT subtitles, with timings and markup around their
+    /// captions.
+    Subtitles,
+    /// Email messages, with headers, quoted replies and signatures.
+    Email,
+    /// Diffs and patches, of which only added lines are checked.
+    Diff,
+    /// Git commit messages, with comments and, when committing verbosely,
+    /// the diff being committed.
+    CommitMessage,
+    /// Comma or tab separated values, of which only `columns` are checked,
+    /// or all if there are none.
+    Delimited { delimiter: u8, columns: Vec<Column> },
+}
+
+/// A column of comma or tab separated values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Column {
+    /// The number of the column, counting from 1.
+    Number(usize),
+    /// The name of the column in the header.
+    Name(String),
+}
+
+/// What the lines before one leave open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Block {
+    #[default]
+    None,
+    /// Code, such as a fenced code block of a doc comment.
+    Code,
+    /// The body of an email, after its headers.
+    Body,
+    /// The signature of an email.
+    Signature,
+    /// The diff below the scissors line of a commit message.
+    Diff,
+    /// The caption of a subtitle cue, after its timing.
+    Cue,
+    /// The indices of the columns checked, after a header naming them.
+    Columns(Vec<usize>),
+}
+
+/// Roff requests and macros whose arguments aren't prose.
+const ROFF_REQUESTS: &[&str] = &[
+    "Ar", "Dd", "Dt", "Fl", "Nm", "Os", "TH", "Xr", "de", "ds", "el", "ft",
+    "ie", "if", "in", "ll", "ne", "nr", "so", "sp", "ta", "tr",
+];
+
+impl Syntax {
+    /// The syntax of a document at `path`, opened with `language_id` if
+    /// it's open. Vim help files are only told by their language id.
+    pub fn of(path: &Path, language_id: Option<&str>) -> Self {
+        match language_id {
+            Some("rust") => Syntax::Rust,
+            Some("groff" | "nroff" | "roff" | "man") => Syntax::Roff,
+            Some("help" | "vimhelp") => Syntax::VimHelp,
+            Some("srt" | "subtitles" | "vtt" | "webvtt") => Syntax::Subtitles,
+            Some("eml" | "email" | "mail") => Syntax::Email,
+            Some("diff") => Syntax::Diff,
+            Some("git-commit" | "gitcommit") => Syntax::CommitMessage,
+            Some("csv") => Syntax::delimited(b','),
+            Some("tsv") => Syntax::delimited(b'\t'),
+            Some(_) => Syntax::Plain,
+            None => {
+                let extension = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or_default();
+                match extension {
+                    "rs" => Syntax::Rust,
+                    "man" | "mdoc" | "roff" => Syntax::Roff,
+                    "srt" | "vtt" => Syntax::Subtitles,
+                    "eml" => Syntax::Email,
+                    "diff" | "patch" => Syntax::Diff,
+                    "csv" => Syntax::delimited(b','),
+                    "tsv" => Syntax::delimited(b'\t'),
+                    _ if path.ends_with("COMMIT_EDITMSG") => {
+                        Syntax::CommitMessage
+                    }
+                    // The messages mutt and neomutt compose
+                    _ if path.file_name().is_some_and(|name| {
+                        let name = name.to_string_lossy();
+                        name.starts_with("mutt-")
+                            || name.starts_with("neomutt-")
+                    }) =>
+                    {
+                        Syntax::Email
+                    }
+                    // Man page sections, as `ls.1` or `printf.3p`
+                    _ if extension
+                        .starts_with(|c: char| matches!(c, '1'..='9')) =>
+                    {
+                        Syntax::Roff
+                    }
+                    _ => Syntax::Plain,
+                }
+            }
+        }
+    }
+
+    fn delimited(delimiter: u8) -> Self {
+        Syntax::Delimited {
+            delimiter,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Check only `columns` if the syntax is of delimited values.
+    pub fn with_columns(mut self, columns: &[Column]) -> Self {
+        if let Syntax::Delimited {
+            columns: checked, ..
+        } = &mut self
+        {
+            *checked = columns.to_vec();
+        }
+        self
+    }
+
+    /// The syntax of the lines of `text` after the first, when they're
+    /// lexed apart from it: columns of delimited values named in its header
+    /// are selected by number instead.
+    pub fn after_first_line(&self, text: &str) -> Self {
+        match self {
+            Syntax::Delimited { delimiter, columns } => {
+                let header = text.lines().next().unwrap_or_default();
+                let columns = column_indices(header, *delimiter, columns)
+                    .into_iter()
+                    .map(|index| Column::Number(index + 1))
+                    .collect();
+                Syntax::Delimited {
+                    delimiter: *delimiter,
+                    columns,
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// The byte ranges of `line` left out. `block` is what the lines
+    /// before left open, and is updated for the next line.
+    pub fn masked(&self, line: &str, block: &mut Block) -> Vec<Range<usize>> {
+        match self {
+            Syntax::Plain => Vec::new(),
+            Syntax::Rust => rustdoc_masked(line, block),
+            Syntax::Roff => roff_masked(line, block),
+            Syntax::VimHelp => vim_help_masked(line, block),
+            Syntax::Subtitles => subtitles_masked(line, block),
+            Syntax::Email => email_masked(line, block),
+            Syntax::Diff => diff_masked(line),
+            Syntax::CommitMessage => commit_message_masked(line, block),
+            Syntax::Delimited { delimiter, columns } => {
+                delimited_masked(line, block, *delimiter, columns)
+            }
+        }
+    }
+}
+
+/// The index of the first `byte` of `bytes` from `from`.
+fn closing(bytes: &[u8], from: usize, byte: u8) -> Option<usize> {
+    bytes[from.min(bytes.len())..]
+        .iter()
+        .position(|&b| b == byte)
+        .map(|index| from + index)
+}
+
+/// The byte ranges of `line` of a Rust file left out: the code spans,
+/// fenced code blocks and intra-doc link targets of doc comments, such as
+/// the `Vec` of `[Vec]` or the `Vec::new` of `[a vector](Vec::new)`.
+fn rustdoc_masked(line: &str, block: &mut Block) -> Vec<Range<usize>> {
+    let trimmed = line.trim_start();
+    if !trimmed.starts_with("///") && !trimmed.starts_with("//!") {
+        *block = Block::None;
+        return Vec::new();
+    }
+    let mut masked = Vec::new();
+    let fence = trimmed[3..].trim_start().starts_with("```");
+    if fence {
+        *block = match block {
+            Block::Code => Block::None,
+            _ => Block::Code,
+        };
+    }
+    if fence || *block == Block::Code {
+        masked.push(0..line.len());
+        return masked;
+    }
+
+    let bytes = line.as_bytes();
+    let closing = |from, byte| closing(bytes, from, byte);
+    let mut index = line.len() - trimmed.len() + 3;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'`' => {
+                if let Some(end) = closing(index + 1, b'`') {
+                    masked.push(index..end + 1);
+                    index = end;
+                }
+            }
+            b'[' => {
+                let Some(end) = closing(index + 1, b']') else {
+                    break;
+                };
+                let target = match bytes.get(end + 1) {
+                    Some(b'(') => closing(end + 1, b')'),
+                    Some(b'[') => closing(end + 1, b']'),
+                    _ => None,
+                };
+                match target {
+                    // Check the text of ordinary links
+                    Some(target_end) => masked.push(end + 1..target_end + 1),
+                    None if !line[index + 1..end]
+                        .contains(char::is_whitespace) =>
+                    {
+                        masked.push(index..end + 1);
+                        index = end;
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    masked
+}
+
+/// The byte ranges of `line` of a roff file left out: the names of
+/// requests and macros, the arguments of those that aren't prose, escapes,
+/// comments and examples between `.EX` and `.EE` or `.nf` and `.fi`.
+fn roff_masked(line: &str, block: &mut Block) -> Vec<Range<usize>> {
+    let mut masked = Vec::new();
+    let bytes = line.as_bytes();
+    let mut index = 0;
+    if line.starts_with(['.', '\'']) {
+        let name = line[1..].trim_start();
+        let name =
+            &name[..name.find(char::is_whitespace).unwrap_or(name.len())];
+        match name {
+            "EX" | "nf" => *block = Block::Code,
+            "EE" | "fi" => *block = Block::None,
+            _ => {}
+        }
+        if name.starts_with("\\\"") || ROFF_REQUESTS.contains(&name) {
+            masked.push(0..line.len());
+            return masked;
+        }
+        index = line.len() - line[1..].trim_start().len() + name.len();
+        masked.push(0..index);
+    } else if *block == Block::Code {
+        masked.push(0..line.len());
+        return masked;
+    }
+
+    while index < bytes.len() {
+        if bytes[index] != b'\\' {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        // The escaped character, then the argument of those taking one
+        let mut end = start + 2;
+        match bytes.get(start + 1) {
+            Some(b'"') => end = bytes.len(),
+            Some(b'f' | b'F' | b'*' | b'n' | b's') => {
+                end = argument_end(bytes, start + 2);
+            }
+            Some(b'(' | b'[') => end = argument_end(bytes, start + 1),
+            _ => {}
+        }
+        let end = end.min(bytes.len());
+        masked.push(start..end);
+        index = end;
+    }
+    masked
+}
+
+/// The end of the roff escape argument at `index` of `bytes`: `(xx`,
+/// `[name]` or a single character.
+fn argument_end(bytes: &[u8], index: usize) -> usize {
+    match bytes.get(index) {
+        Some(b'(') => index + 3,
+        Some(b'[') => {
+            closing(bytes, index, b']').map_or(bytes.len(), |end| end + 1)
+        }
+        // Sizes such as `\s-1`
+        Some(b'-' | b'+') => index + 2,
+        _ => index + 1,
+    }
+}
+
+/// The byte ranges of `line` of a Vim help file left out: `*tags*`,
+/// `|links|`, `'options'`, `{arguments}`, `<keys>`, `CTRL-` keys, examples
+/// from a line ending with `>` to one starting with `<` or any character
+/// other than whitespace, and modelines.
+fn vim_help_masked(line: &str, block: &mut Block) -> Vec<Range<usize>> {
+    let mut masked = Vec::new();
+    let bytes = line.as_bytes();
+    let mut index = 0;
+    if *block == Block::Code {
+        if line.starts_with('<') {
+            *block = Block::None;
+            index = 1;
+        } else if line.is_empty() || line.starts_with(char::is_whitespace) {
+            masked.push(0..line.len());
+            return masked;
+        } else {
+            *block = Block::None;
+        }
+    }
+    if line.trim_start().starts_with("vim:") {
+        masked.push(0..line.len());
+        return masked;
+    }
+    if line == ">" || line.ends_with(" >") {
+        *block = Block::Code;
+    }
+
+    while index < bytes.len() {
+        let closing_byte = match bytes[index] {
+            b'*' => b'*',
+            b'|' => b'|',
+            b'{' => b'}',
+            b'<' => b'>',
+            b'\''
+                if index == 0 || !bytes[index - 1].is_ascii_alphanumeric() =>
+            {
+                b'\''
+            }
+            b'C' if line[index..].starts_with("CTRL-") => {
+                let end = line[index..]
+                    .find(char::is_whitespace)
+                    .map_or(line.len(), |end| index + end);
+                masked.push(index..end);
+                index = end;
+                continue;
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        let Some(end) = closing(bytes, index + 1, closing_byte) else {
+            index += 1;
+            continue;
+        };
+        let inner = &line[index + 1..end];
+        let option = closing_byte != b'\''
+            || inner.len() >= 2
+                && inner.bytes().all(|b| b.is_ascii_lowercase());
+        if !inner.is_empty() && !inner.contains(char::is_whitespace) && option {
+            masked.push(index..end + 1);
+            index = end + 1;
+        } else {
+            index += 1;
+        }
+    }
+    masked
+}
+
+/// The byte ranges of `line` of a subtitle file left out: everything but
+/// captions, such as sequence numbers and cue identifiers, timings and cue
+/// settings, the `WEBVTT` header and `NOTE`, `STYLE` and `REGION` blocks,
+/// and the tags and `{\an8}` overrides within captions. Captions are the
+/// lines after a timing, so the lines of a block before it are left out.
+fn subtitles_masked(line: &str, block: &mut Block) -> Vec<Range<usize>> {
+    let mut masked = Vec::new();
+    if line.trim().is_empty() {
+        *block = Block::None;
+        return masked;
+    }
+    if *block == Block::None
+        && ["NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|name| line.split_whitespace().next() == Some(name))
+    {
+        *block = Block::Code;
+    }
+    if line.contains("-->") && *block != Block::Code {
+        *block = Block::Cue;
+        masked.push(0..line.len());
+        return masked;
+    }
+    if *block != Block::Cue {
+        masked.push(0..line.len());
+        return masked;
+    }
+
+    let bytes = line.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        let closing_byte = match bytes[index] {
+            b'<' => b'>',
+            b'{' => b'}',
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        match closing(bytes, index + 1, closing_byte) {
+            Some(end) => {
+                masked.push(index..end + 1);
+                index = end + 1;
+            }
+            None => index += 1,
+        }
+    }
+    masked
+}
+
+/// The byte ranges of `line` of an email left out, leaving what's newly
+/// written: the headers but for the subject, quoted replies and the lines
+/// introducing them, and the signature after `-- `.
+fn email_masked(line: &str, block: &mut Block) -> Vec<Range<usize>> {
+    let mut masked = Vec::new();
+    match *block {
+        Block::Signature => {
+            masked.push(0..line.len());
+            return masked;
+        }
+        Block::Body => {}
+        _ if line.is_empty() => {
+            *block = Block::Body;
+            return masked;
+        }
+        _ => {
+            let name = line.split_once(':').map(|(name, _)| name);
+            let header = name.is_some_and(|name| {
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic())
+            });
+            if header
+                && name.is_some_and(|name| name.eq_ignore_ascii_case("subject"))
+            {
+                masked.push(0.."subject:".len());
+                return masked;
+            }
+            // Headers folded over several lines continue indented
+            if header || line.starts_with([' ', '\t']) {
+                masked.push(0..line.len());
+                return masked;
+            }
+            // Messages may be composed without their headers
+            *block = Block::Body;
+        }
+    }
+
+    if line == "-- " || line == "--" {
+        *block = Block::Signature;
+        masked.push(0..line.len());
+    } else if line.trim_start().starts_with('>')
+        || line.trim_end().ends_with("wrote:")
+    {
+        masked.push(0..line.len());
+    }
+    masked
+}
+
+/// The byte ranges of `line` of a diff left out: all of it unless it's
+/// added, and the `+` marking it otherwise.
+fn diff_masked(line: &str) -> Vec<Range<usize>> {
+    let mut masked = Vec::new();
+    if line.starts_with('+') && !line.starts_with("+++") {
+        masked.push(0..1);
+    } else {
+        masked.push(0..line.len());
+    }
+    masked
+}
+
+/// The byte ranges of `line` of a commit message left out: comments, and
+/// the diff below the scissors line but for added lines.
+fn commit_message_masked(line: &str, block: &mut Block) -> Vec<Range<usize>> {
+    if *block == Block::Diff {
+        return diff_masked(line);
+    }
+    let mut masked = Vec::new();
+    if line.starts_with('#') {
+        // `# ------------------------ >8 ------------------------`
+        if line.contains(" >8 ") {
+            *block = Block::Diff;
+        }
+        masked.push(0..line.len());
+    }
+    masked
+}
+
+/// The byte ranges of the fields of `line` separated by `delimiter`, which
+/// double quotes around a field escape.
+fn fields(line: &str, delimiter: u8) -> Vec<Range<usize>> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (index, &byte) in line.as_bytes().iter().enumerate() {
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if byte == delimiter && !quoted {
+            fields.push(start..index);
+            start = index + 1;
+        }
+    }
+    fields.push(start..line.len());
+    fields
+}
+
+/// The indices of `columns` in the `header` of delimited values. Names
+/// missing from it select none.
+fn column_indices(
+    header: &str,
+    delimiter: u8,
+    columns: &[Column],
+) -> Vec<usize> {
+    let names = fields(header, delimiter)
+        .into_iter()
+        .map(|field| header[field].trim().trim_matches('"'))
+        .collect::<Vec<_>>();
+    columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Number(number) => number.checked_sub(1),
+            Column::Name(name) => names
+                .iter()
+                .position(|header| header.eq_ignore_ascii_case(name)),
+        })
+        .collect()
+}
+
+/// The byte ranges of `line` of delimited values left out: the fields of
+/// columns other than `columns`, and the header if they're named in it.
+fn delimited_masked(
+    line: &str,
+    block: &mut Block,
+    delimiter: u8,
+    columns: &[Column],
+) -> Vec<Range<usize>> {
+    if columns.is_empty() {
+        return Vec::new();
+    }
+    let fields = fields(line, delimiter);
+    let named = columns
+        .iter()
+        .any(|column| matches!(column, Column::Name(_)));
+    if *block == Block::None && named {
+        *block = Block::Columns(column_indices(line, delimiter, columns));
+        // The names themselves aren't checked
+        return fields;
+    }
+
+    let checked = |index: usize| match &*block {
+        Block::Columns(indices) => indices.contains(&index),
+        _ => columns.contains(&Column::Number(index + 1)),
+    };
+    fields
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !checked(*index))
+        .map(|(_, field)| field)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::lexer::Lexer;
+    use streaming_iterator::StreamingIterator;
+
+    fn words(text: &str, syntax: Syntax) -> Vec<&str> {
+        Lexer::new(text)
+            .unwrap()
+            .with_syntax(syntax)
+            .map_deref(|word| word.text)
+            .collect()
+    }
+
+    #[test]
+    fn syntax_of() {
+        let syntax =
+            |path, language_id| Syntax::of(Path::new(path), language_id);
+
+        assert_eq!(syntax("src/lib.rs", None), Syntax::Rust);
+        assert_eq!(syntax("README.rs", Some("markdown")), Syntax::Plain);
+        assert_eq!(syntax("ls.1", None), Syntax::Roff);
+        assert_eq!(syntax("printf.3p", None), Syntax::Roff);
+        assert_eq!(syntax("doc/plugin.txt", Some("help")), Syntax::VimHelp);
+        assert_eq!(syntax("doc/plugin.txt", None), Syntax::Plain);
+        assert_eq!(syntax("episode.en.srt", None), Syntax::Subtitles);
+        assert_eq!(syntax("episode.vtt", None), Syntax::Subtitles);
+        assert_eq!(syntax("/tmp/mutt-host-1000-1-2", None), Syntax::Email);
+        assert_eq!(syntax("products.csv", None), Syntax::delimited(b','));
+        assert_eq!(syntax("fix.patch", None), Syntax::Diff);
+        assert_eq!(syntax(".git/COMMIT_EDITMSG", None), Syntax::CommitMessage);
+    }
+
+    #[test]
+    fn roff() {
+        let text = concat!(
+            ".TH ZSPELL_LSP 1 \"2024\"\n",
+            ".\\\" A wrld comment\n",
+            ".SH DESCRIPTION\n",
+            "\\fBzspell_lsp\\fR chekcs \\fIfiles\\fP\\(em fast\\*(Tm.\n",
+            ".EX\n",
+            "zspell_lsp \\-\\-stdio\n",
+            ".EE\n",
+            ".B wrld\n",
+        );
+
+        assert_eq!(
+            words(text, Syntax::Roff),
+            [
+                "DESCRIPTION",
+                "zspell",
+                "lsp",
+                "chekcs",
+                "files",
+                "fast",
+                "wrld"
+            ]
+        );
+    }
+
+    #[test]
+    fn vim_help() {
+        let text = concat!(
+            "*plugin.txt*\tA plugin for wrld\n",
+            "\n",
+            "Set 'textwidth' and press CTRL-W or <CR> in |insert-mode|, {count}\n",
+            "times, don't wrry: >\n",
+            "\tlet g:wrld = 1\n",
+            "<\n",
+            "Done.\n",
+            " vim:tw=78:ts=8:ft=help\n",
+        );
+
+        assert_eq!(
+            words(text, Syntax::VimHelp),
+            [
+                "A", "plugin", "for", "wrld", "Set", "and", "press", "or",
+                "in", "times", "don't", "wrry", "Done"
+            ]
+        );
+    }
+
+    #[test]
+    fn email() {
+        let text = concat!(
+            "From: Wrld <wrld@example.com>\n",
+            "To: teh@example.com\n",
+            "References: <wrld@example.com>\n",
+            "\t<teh@example.com>\n",
+            "Subject: Re: Helo\n",
+            "\n",
+            "Thnaks for writing.\n",
+            "\n",
+            "On Mon, Jan 1, 2024 Wrld Teh wrote:\n",
+            "> Helo wrld,\n",
+            ">> teh\n",
+            "-- \n",
+            "Wrld Teh\n",
+        );
+
+        assert_eq!(
+            words(text, Syntax::Email),
+            ["Re", "Helo", "Thnaks", "for", "writing"]
+        );
+        assert_eq!(words("Helo\n\n> wrld", Syntax::Email), ["Helo"]);
+    }
+
+    #[test]
+    fn diff() {
+        let diff = concat!(
+            "diff --git a/README b/README\n",
+            "--- a/README\n",
+            "+++ b/README\n",
+            "@@ -1,2 +1,2 @@ Wrld\n",
+            " Teh context\n",
+            "-Teh removed\n",
+            "+Thier added\n",
+        );
+        let message = concat!(
+            "Fix teh lexer\n",
+            "\n",
+            "# Please enter the commit mesage for your changes.\n",
+            "# ------------------------ >8 ------------------------\n",
+            "# Do not modify or remove the line above.\n",
+        );
+
+        assert_eq!(words(diff, Syntax::Diff), ["Thier", "added"]);
+        assert_eq!(
+            words(&format!("{}{}", message, diff), Syntax::CommitMessage),
+            ["Fix", "teh", "lexer", "Thier", "added"]
+        );
+    }
+
+    #[test]
+    fn delimited() {
+        let text = concat!(
+            "sku,name,description\n",
+            "XQ-12,Wrld,\"A smal, red lamp\"\n",
+            "ZZT-9,Teh,Blue chiar\n",
+        );
+        let columns = |columns: serde_json::Value| {
+            let columns = serde_json::from_value::<Vec<Column>>(columns);
+            Syntax::delimited(b',').with_columns(&columns.unwrap())
+        };
+
+        assert_eq!(
+            words(text, columns(serde_json::json!(["Description"]))),
+            ["A", "smal", "red", "lamp", "Blue", "chiar"]
+        );
+        assert_eq!(
+            words(text, columns(serde_json::json!([2]))),
+            ["name", "Wrld", "Teh"]
+        );
+        assert_eq!(words("a\tb c", Syntax::delimited(b'\t')), ["a", "b", "c"]);
+
+        let rest = text.split_once('\n').unwrap().1;
+        let syntax = columns(serde_json::json!(["description"]));
+        assert_eq!(
+            words(rest, syntax.after_first_line(text)),
+            ["A", "smal", "red", "lamp", "Blue", "chiar"]
+        );
+    }
+
+    #[test]
+    fn subtitles() {
+        let srt = concat!(
+            "1\n",
+            "00:00:01,000 --> 00:00:02,500\n",
+            "{\\an8}<i>Helo</i> there\n",
+            "\n",
+            "2\n",
+            "00:00:03,000 --> 00:00:04,000 X1:40 X2:600\n",
+            "<font color=\"red\">General</font> Kenobi\n",
+        );
+        let vtt = concat!(
+            "WEBVTT - Wrld\n",
+            "\n",
+            "NOTE wrld\n",
+            "teh notes\n",
+            "\n",
+            "intro wrld\n",
+            "00:01.000 --> 00:04.000 align:start position:10%\n",
+            "<v Roger Bingham>We are in New York City\n",
+            "\n",
+            "00:05.000 --> 00:06.000\n",
+            "Helo again\n",
+        );
+
+        assert_eq!(
+            words(srt, Syntax::Subtitles),
+            ["Helo", "there", "General", "Kenobi"]
+        );
+        assert_eq!(
+            words(vtt, Syntax::Subtitles),
+            ["We", "are", "in", "New", "York", "City", "Helo", "again"]
+        );
+    }
+}
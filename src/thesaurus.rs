@@ -0,0 +1,160 @@
+//! MyThes thesauri, the `.idx` and `.dat` file pairs shipped alongside
+//! hunspell dictionaries, for showing synonyms on hover.
+
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// A meaning of a word and its synonyms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Meaning {
+    /// The part of speech, such as `noun`, empty if not given.
+    pub part_of_speech: String,
+    pub synonyms: Vec<String>,
+}
+
+pub struct Thesaurus {
+    data: Mmap,
+    /// Whether the files are in ISO8859-1 rather than UTF-8.
+    latin1: bool,
+    /// Offsets of the entries of the data file by word.
+    index: HashMap<String, usize>,
+}
+
+impl Thesaurus {
+    /// Open the thesaurus of the data file `dat`, reading the index of the
+    /// `.idx` file next to it.
+    pub fn open(dat: &Path) -> Result<Self> {
+        let idx = dat.with_extension("idx");
+        let index = std::fs::read(&idx)
+            .with_context(|| format!("Unable to read {}", idx.display()))?;
+        let file = File::open(dat)
+            .with_context(|| format!("Unable to open {}", dat.display()))?;
+        // SAFETY: thesaurus files aren't written to while they're in use,
+        // and a file truncated meanwhile would end the process with SIGBUS
+        // rather than be misread.
+        let data = unsafe { Mmap::map(&file) }?;
+
+        let mut lines = index.split(|&byte| byte == b'\n');
+        let encoding = lines.next().unwrap_or_default();
+        let latin1 = !encoding.trim_ascii().eq_ignore_ascii_case(b"UTF-8");
+        // The line after the encoding holds the number of entries
+        let index = lines
+            .skip(1)
+            .filter_map(|line| {
+                let line = decode(line.trim_ascii_end(), latin1);
+                let (word, offset) = line.rsplit_once('|')?;
+                Some((word.to_string(), offset.parse().ok()?))
+            })
+            .collect();
+
+        Ok(Self {
+            data,
+            latin1,
+            index,
+        })
+    }
+
+    /// The meanings of `word`, or of `word` in lowercase if it has none,
+    /// in the order of the thesaurus.
+    pub fn meanings(&self, word: &str) -> Vec<Meaning> {
+        let offset = self
+            .index
+            .get(word)
+            .or_else(|| self.index.get(&word.to_lowercase()));
+        let Some(&offset) = offset else {
+            return Vec::new();
+        };
+        let Some(entry) = self.data.get(offset..) else {
+            return Vec::new();
+        };
+
+        let mut lines = entry
+            .split(|&byte| byte == b'\n')
+            .map(|line| decode(line.trim_ascii_end(), self.latin1));
+        let count = lines
+            .next()
+            .and_then(|header| header.rsplit_once('|')?.1.parse().ok())
+            .unwrap_or(0);
+        lines
+            .take(count)
+            .map(|line| {
+                let mut fields = line.split('|');
+                let part_of_speech = fields
+                    .next()
+                    .unwrap_or_default()
+                    .trim_matches(|c| matches!(c, '(' | ')' | '-' | ' '))
+                    .to_string();
+                let synonyms = fields
+                    .filter(|synonym| !synonym.is_empty())
+                    .map(String::from)
+                    .collect();
+                Meaning {
+                    part_of_speech,
+                    synonyms,
+                }
+            })
+            .collect()
+    }
+}
+
+fn decode(bytes: &[u8], latin1: bool) -> String {
+    if latin1 {
+        bytes.iter().map(|&byte| char::from(byte)).collect()
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn meanings() {
+        let dir = std::env::temp_dir()
+            .join(format!("zspell_lsp_thesaurus_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data = "UTF-8\n\
+                    happy|2\n\
+                    (adj)|glad|felicitous (similar term)\n\
+                    -|content\n\
+                    café|1\n\
+                    (noun)|coffeehouse|coffee shop\n";
+        let offset = |word: &str| data.find(&format!("{}|", word)).unwrap();
+        let index = format!(
+            "UTF-8\n2\ncafé|{}\nhappy|{}\n",
+            offset("café"),
+            offset("happy")
+        );
+        std::fs::write(dir.join("th_en_US_v2.dat"), data).unwrap();
+        std::fs::write(dir.join("th_en_US_v2.idx"), index).unwrap();
+
+        let thesaurus = Thesaurus::open(&dir.join("th_en_US_v2.dat"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let thesaurus = thesaurus.unwrap();
+        assert_eq!(
+            thesaurus.meanings("Happy"),
+            [
+                Meaning {
+                    part_of_speech: "adj".to_string(),
+                    synonyms: vec![
+                        "glad".to_string(),
+                        "felicitous (similar term)".to_string()
+                    ],
+                },
+                Meaning {
+                    part_of_speech: String::new(),
+                    synonyms: vec!["content".to_string()],
+                },
+            ]
+        );
+        assert_eq!(thesaurus.meanings("café")[0].synonyms.len(), 2);
+        assert!(thesaurus.meanings("sad").is_empty());
+    }
+}
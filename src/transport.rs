@@ -0,0 +1,272 @@
+use crossbeam_channel::bounded;
+use log::warn;
+use lsp_server::{Connection, ErrorCode, Message, RequestId, Response};
+use lsp_types::notification::{Exit, Notification as _};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io::{self, BufRead, Read, Write};
+use std::thread::JoinHandle;
+
+/// Threads reading and writing the messages of a [`stdio`] connection.
+pub struct IoThreads {
+    pub reader: JoinHandle<io::Result<()>>,
+    pub writer: JoinHandle<io::Result<()>>,
+}
+
+impl IoThreads {
+    /// Wait for both threads to stop, which they do once the connection
+    /// is dropped and the client sent `exit` or closed its output.
+    pub fn join(self) -> io::Result<()> {
+        for thread in [self.reader, self.writer] {
+            match thread.join() {
+                Ok(result) => result?,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A message read by [`read_message`].
+#[derive(Debug)]
+pub enum Incoming {
+    Message(Message),
+    /// A message whose content isn't valid, with the error response to
+    /// send back if its id could be found.
+    Invalid(Option<Response>),
+}
+
+/// A connection over stdin and stdout like [`Connection::stdio`], but one
+/// that answers malformed messages with an error instead of closing the
+/// connection, and accepts the lone surrogates some clients put in JSON
+/// strings.
+pub fn stdio() -> (Connection, IoThreads) {
+    let (writer_sender, writer_receiver) = bounded::<Message>(0);
+    let writer = std::thread::spawn(move || {
+        let mut stdout = io::stdout().lock();
+        for message in writer_receiver {
+            message.write(&mut stdout)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    });
+
+    let (reader_sender, reader_receiver) = bounded(0);
+    let error_sender = writer_sender.clone();
+    let reader = std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Some(incoming) = read_message(&mut stdin)? {
+            let message = match incoming {
+                Incoming::Message(message) => message,
+                Incoming::Invalid(Some(response)) => {
+                    _ = error_sender.send(Message::Response(response));
+                    continue;
+                }
+                Incoming::Invalid(None) => continue,
+            };
+            let is_exit = matches!(
+                &message,
+                Message::Notification(notification) if notification.method == Exit::METHOD
+            );
+            if reader_sender.send(message).is_err() || is_exit {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let connection = Connection {
+        sender: writer_sender,
+        receiver: reader_receiver,
+    };
+    (connection, IoThreads { reader, writer })
+}
+
+/// Read the next message, or `None` at the end of the input. A message
+/// whose content isn't valid is logged and read as [`Incoming::Invalid`],
+/// only errors reading the input itself are returned.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Incoming>> {
+    let mut length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+
+    let mut content = Vec::new();
+    input
+        .by_ref()
+        .take(length as u64)
+        .read_to_end(&mut content)?;
+    if content.len() < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let content = String::from_utf8_lossy(&content);
+    let content = replace_lone_surrogates(&content);
+    match serde_json::from_str(&content) {
+        Ok(message) => Ok(Some(Incoming::Message(message))),
+        Err(err) => {
+            warn!("ignoring invalid message: {}", err);
+            Ok(Some(Incoming::Invalid(error_response(&content, err))))
+        }
+    }
+}
+
+/// The response to the invalid message `content`, if it's a request whose
+/// id can be found: `InvalidRequest` if it's JSON, `ParseError` otherwise.
+fn error_response(content: &str, err: serde_json::Error) -> Option<Response> {
+    let (id, code) = match serde_json::from_str::<Value>(content) {
+        Ok(value) => {
+            value.get("method")?;
+            let id = serde_json::from_value(value.get("id")?.clone()).ok()?;
+            (id, ErrorCode::InvalidRequest)
+        }
+        Err(_) if !content.contains(r#""method""#) => return None,
+        Err(_) => {
+            // The first id is taken to be the one of the request, as
+            // clients write it before the parameters
+            let (_, rest) = content.split_once(r#""id""#)?;
+            let rest = rest.trim_start().strip_prefix(':')?;
+            let id = serde_json::Deserializer::from_str(rest)
+                .into_iter::<RequestId>()
+                .next()?
+                .ok()?;
+            (id, ErrorCode::ParseError)
+        }
+    };
+    Some(Response::new_err(id, code as i32, err.to_string()))
+}
+
+/// Replace `\u` escapes of unpaired UTF-16 surrogates in `json` with
+/// U+FFFD, which serde_json would reject otherwise.
+pub fn replace_lone_surrogates(json: &str) -> Cow<'_, str> {
+    let surrogate = |json: &str, index: usize| -> Option<u16> {
+        let digits = json.get(index + 2..index + 6)?;
+        if !json[index..].starts_with("\\u")
+            || !digits.bytes().all(|byte| byte.is_ascii_hexdigit())
+        {
+            return None;
+        }
+        let unit = u16::from_str_radix(digits, 16).ok()?;
+        (0xd800..0xe000).contains(&unit).then_some(unit)
+    };
+
+    let mut replaced = String::new();
+    let mut copied = 0;
+    let mut index = 0;
+    let bytes = json.as_bytes();
+    while index < bytes.len() {
+        if bytes[index] != b'\\' {
+            index += 1;
+            continue;
+        }
+        let Some(unit) = surrogate(json, index) else {
+            // Skip the escaped character, which may be a backslash
+            index += 2;
+            continue;
+        };
+        let paired = unit < 0xdc00
+            && surrogate(json, index + 6).is_some_and(|low| low >= 0xdc00);
+        if paired {
+            index += 12;
+            continue;
+        }
+        replaced.push_str(&json[copied..index]);
+        replaced.push_str("\\ufffd");
+        index += 6;
+        copied = index;
+    }
+
+    if copied == 0 {
+        return Cow::Borrowed(json);
+    }
+    replaced.push_str(&json[copied..]);
+    Cow::Owned(replaced)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn lone_surrogates() {
+        assert_eq!(replace_lone_surrogates(r#""aé""#), r#""aé""#);
+        assert_eq!(
+            replace_lone_surrogates(r#""\ud83d\ude00""#),
+            r#""\ud83d\ude00""#
+        );
+        assert_eq!(
+            replace_lone_surrogates(r#""\ud83d \ude00\\ud83d\ud83d""#),
+            r#""\ufffd \ufffd\\ud83d\ufffd""#
+        );
+    }
+
+    #[test]
+    fn messages() {
+        let content = r#"{"jsonrpc":"2.0","method":"a","params":"\ud800"}"#;
+        let input = format!(
+            "Content-Length: {}\r\n\r\n{}Content-Length: 4\r\n\r\nnull",
+            content.len(),
+            content
+        );
+        let mut input = input.as_bytes();
+
+        let Some(Incoming::Message(Message::Notification(notification))) =
+            read_message(&mut input).unwrap()
+        else {
+            panic!("expected a notification");
+        };
+        assert_eq!(notification.params, "\u{fffd}");
+        assert!(matches!(
+            read_message(&mut input),
+            Ok(Some(Incoming::Invalid(None)))
+        ));
+        assert!(matches!(read_message(&mut input), Ok(None)));
+    }
+
+    #[test]
+    fn error_responses() {
+        let response = |content: &str| {
+            let input =
+                format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+            match read_message(&mut input.as_bytes()).unwrap() {
+                Some(Incoming::Invalid(response)) => response.map(|response| {
+                    (response.id, response.error.unwrap().code)
+                }),
+                incoming => {
+                    panic!("expected an invalid message: {:?}", incoming)
+                }
+            }
+        };
+
+        assert_eq!(
+            response(r#"{"jsonrpc":"2.0","id":1,"method":"a","params":"#),
+            Some((1.into(), ErrorCode::ParseError as i32))
+        );
+        assert_eq!(
+            response(r#"{"jsonrpc":"2.0","id":"b","method":3,"error":3}"#),
+            Some(("b".to_string().into(), ErrorCode::InvalidRequest as i32))
+        );
+        assert_eq!(response(r#"{"jsonrpc":"2.0","method":3}"#), None);
+        assert_eq!(response(r#"{"jsonrpc":"2.0","id":2,"result":"#), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Reviewing the words added to the personal and team dictionaries, for
+//! the `words` command and the `zspell/listUserWords` request.
+
+use crate::config::{Config, Layer};
+use crate::personal::PersonalDictionary;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Subcommand, Debug)]
+pub enum WordsCommand {
+    /// List the added words with the dictionary they were added to.
+    List {
+        /// Print JSON instead of one word per line.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the added words as a wordlist, with their affix flags.
+    Export {
+        /// Only export the words of this dictionary.
+        #[arg(long, value_enum)]
+        layer: Option<Layer>,
+    },
+}
+
+/// A word added to a personal or team dictionary.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct UserWord {
+    pub word: String,
+    /// The affix flags the word was added with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<String>,
+    pub layer: Layer,
+    /// The file of the dictionary, `None` for words added while no
+    /// personal dictionary file is configured.
+    pub path: Option<PathBuf>,
+}
+
+impl UserWord {
+    /// The word as written in wordlist files, `word/SD` with flags.
+    pub fn entry(&self) -> String {
+        match &self.flags {
+            Some(flags) => format!("{}/{}", self.word, flags),
+            None => self.word.clone(),
+        }
+    }
+}
+
+/// The words of `personal`, the dictionary at `path` in `layer`.
+pub(crate) fn user_words<'a>(
+    layer: Layer,
+    path: &'a Option<PathBuf>,
+    personal: &'a PersonalDictionary,
+) -> impl Iterator<Item = UserWord> + 'a {
+    personal.entries().map(move |(word, flags)| UserWord {
+        word: word.to_string(),
+        flags: flags.map(String::from),
+        layer,
+        path: path.clone(),
+    })
+}
+
+/// Run the `words` command with the dictionaries of `config`.
+pub fn run(config: &Config, command: &WordsCommand) -> Result<()> {
+    let mut words = Vec::new();
+    for (layer, path) in [
+        (Layer::Personal, &config.personal_dictionary),
+        (Layer::Team, &config.team_dictionary),
+    ] {
+        let Some(file) = path else {
+            continue;
+        };
+        let personal = PersonalDictionary::load(path.clone(), None)
+            .with_context(|| format!("unable to read {}", file.display()))?;
+        words.extend(user_words(layer, path, &personal));
+    }
+
+    match command {
+        WordsCommand::List { json: true } => {
+            println!("{}", serde_json::to_string_pretty(&words)?);
+        }
+        WordsCommand::List { json: false } => {
+            for word in &words {
+                let layer = match word.layer {
+                    Layer::Personal => "personal",
+                    Layer::Team => "team",
+                };
+                println!("{}\t{}", word.entry(), layer);
+            }
+        }
+        WordsCommand::Export { layer } => {
+            let mut entries = words
+                .iter()
+                .filter(|word| layer.is_none_or(|layer| word.layer == layer))
+                .map(UserWord::entry)
+                .collect::<Vec<_>>();
+            entries.sort();
+            entries.dedup();
+            for entry in entries {
+                println!("{}", entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn entries() {
+        let path = std::env::temp_dir()
+            .join(format!("zspell_lsp_words_{}.txt", std::process::id()));
+        std::fs::write(&path, "teh\nzspell/S\n\nteh\n").unwrap();
+        let path = Some(path);
+        let personal = PersonalDictionary::load(path.clone(), None).unwrap();
+        std::fs::remove_file(path.as_ref().unwrap()).unwrap();
+
+        let words = user_words(Layer::Team, &path, &personal)
+            .map(|word| (word.entry(), word.layer))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            words,
+            [
+                ("teh".to_string(), Layer::Team),
+                ("zspell/S".to_string(), Layer::Team),
+            ]
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+/// Recursively collect files below `root`, honoring `.gitignore`, `.ignore`
+/// and skipping hidden entries.
+pub fn files(root: &Path) -> Vec<PathBuf> {
+    let mut files = WalkBuilder::new(root)
+        .build()
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .map(|entry| entry.into_path())
+        .collect::<Vec<_>>();
+
+    files.sort();
+    files
+}
+
+/// Whether any component of `path` is a hidden file or directory.
+pub fn is_hidden(path: &Path) -> bool {
+    path.components().any(|component| {
+        let name = component.as_os_str().to_string_lossy();
+        name.starts_with('.') && name != "." && name != ".."
+    })
+}
@@ -0,0 +1,335 @@
+//! Drives the server in-process over an in-memory connection.
+
+use lsp_server::{
+    Connection, Message, Notification, Request, RequestId, ResponseError,
+};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Initialize, Shutdown};
+use lsp_types::{
+    ClientCapabilities, Diagnostic, DidChangeTextDocumentParams,
+    DidOpenTextDocumentParams, InitializeParams, InitializedParams,
+    PublishDiagnosticsParams, Range, TextDocumentContentChangeEvent,
+    TextDocumentItem, Url, VersionedTextDocumentIdentifier, WorkspaceFolder,
+};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Once;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use zspell_lsp::backend::ZspellBackend;
+use zspell_lsp::checker::Checker;
+use zspell_lsp::server::Server;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A client connected to a server running on another thread.
+pub struct TestClient {
+    connection: Connection,
+    server: Option<JoinHandle<anyhow::Result<()>>>,
+    next_id: i32,
+    /// Workspace folder of the server, empty unless a test adds files.
+    pub root: PathBuf,
+    /// The capabilities the server declared in answer to `initialize`.
+    pub capabilities: Value,
+}
+
+impl TestClient {
+    /// Start a server whose dictionary contains `words`, initialized with
+    /// `options` as `initializationOptions`.
+    pub fn start(words: &[&str], options: Value) -> Self {
+        use_temporary_cache();
+        let root = std::env::temp_dir().join(format!(
+            "zspell_lsp_test_{}_{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let dic = format!("{}\n{}\n", words.len(), words.join("\n"));
+        let (client, connection) = Connection::memory();
+        let server = std::thread::spawn(move || {
+            let dict = zspell::builder()
+                .config_str("SET UTF-8\n")
+                .dict_str(&dic)
+                .build()
+                .unwrap();
+            let checker = Checker::new(ZspellBackend::new(dict), None);
+            let mut server =
+                Server::new(checker, Value::Object(Default::default()))
+                    .unwrap();
+            server.run_with_connection(connection)
+        });
+
+        let mut client = Self {
+            connection: client,
+            server: Some(server),
+            next_id: 0,
+            root,
+            capabilities: Value::Null,
+        };
+        client.initialize(options);
+        client
+    }
+
+    fn initialize(&mut self, options: Value) {
+        let uri = Url::from_directory_path(&self.root).unwrap();
+        let params = InitializeParams {
+            capabilities: ClientCapabilities::default(),
+            initialization_options: Some(options),
+            workspace_folders: Some(vec![WorkspaceFolder {
+                uri,
+                name: "test".to_string(),
+            }]),
+            ..Default::default()
+        };
+        let result = self.request::<Initialize>(params);
+        self.capabilities = result["capabilities"].clone();
+        self.notify::<Initialized>(InitializedParams {});
+    }
+
+    /// The URI of the file `name` in the test directory, or `name` itself
+    /// if it's a URI such as `untitled:Untitled-1`.
+    pub fn uri(&self, name: &str) -> Url {
+        Url::parse(name).unwrap_or_else(|_| {
+            Url::from_file_path(self.root.join(name)).unwrap()
+        })
+    }
+
+    pub fn notify<N: lsp_types::notification::Notification>(
+        &self,
+        params: N::Params,
+    ) {
+        let notification = Notification::new(N::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Notification(notification))
+            .unwrap();
+    }
+
+    /// Send a request without waiting for its result.
+    pub fn send_request<R: lsp_types::request::Request>(
+        &mut self,
+        params: R::Params,
+    ) -> RequestId {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), R::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Request(request))
+            .unwrap();
+        id
+    }
+
+    /// Send a request and wait for its result, handling everything the
+    /// server sends in the meantime with [`Self::receive`].
+    pub fn request<R: lsp_types::request::Request>(
+        &mut self,
+        params: R::Params,
+    ) -> Value {
+        let id = self.send_request::<R>(params);
+        loop {
+            if let Message::Response(response) = self.receive() {
+                if response.id == id {
+                    assert!(
+                        response.error.is_none(),
+                        "{} failed: {:?}",
+                        R::METHOD,
+                        response.error
+                    );
+                    return response.result.unwrap_or(Value::Null);
+                }
+            }
+        }
+    }
+
+    /// Send a request that should fail and wait for its error.
+    pub fn request_error<R: lsp_types::request::Request>(
+        &mut self,
+        params: R::Params,
+    ) -> ResponseError {
+        let id = self.send_request::<R>(params);
+        loop {
+            if let Message::Response(response) = self.receive() {
+                if response.id == id {
+                    return response.error.expect("the request succeeded");
+                }
+            }
+        }
+    }
+
+    /// The next message from the server. Panics if none arrives in time.
+    pub fn receive(&self) -> Message {
+        self.connection
+            .receiver
+            .recv_timeout(TIMEOUT)
+            .expect("no message from the server")
+    }
+
+    pub fn open(&self, name: &str, text: &str) {
+        self.open_as(name, "plaintext", text);
+    }
+
+    /// Open the document `name` with the LSP `language_id`.
+    pub fn open_as(&self, name: &str, language_id: &str, text: &str) {
+        self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: self.uri(name),
+                language_id: language_id.to_string(),
+                version: 0,
+                text: text.to_string(),
+            },
+        });
+    }
+
+    /// Replace the whole text of the document `name`.
+    pub fn change(&self, name: &str, version: i32, text: &str) {
+        self.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: self.uri(name),
+                version,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.to_string(),
+            }],
+        });
+    }
+
+    /// Replace `range` of the document `name` with `text`.
+    pub fn edit(&self, name: &str, version: i32, range: Range, text: &str) {
+        self.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: self.uri(name),
+                version,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(range),
+                range_length: None,
+                text: text.to_string(),
+            }],
+        });
+    }
+
+    /// Wait for the next diagnostics published for `name`, skipping other
+    /// messages.
+    pub fn diagnostics(&self, name: &str) -> Vec<Diagnostic> {
+        let uri = self.uri(name);
+        loop {
+            let Message::Notification(notification) = self.receive() else {
+                continue;
+            };
+            if notification.method != PublishDiagnostics::METHOD {
+                continue;
+            }
+            let params: PublishDiagnosticsParams =
+                serde_json::from_value(notification.params).unwrap();
+            if params.uri == uri {
+                return params.diagnostics;
+            }
+        }
+    }
+
+    /// Wait for the next notification with `method`, skipping other
+    /// messages, and return its parameters.
+    pub fn notification(&self, method: &str) -> Value {
+        loop {
+            if let Message::Notification(notification) = self.receive() {
+                if notification.method == method {
+                    return notification.params;
+                }
+            }
+        }
+    }
+
+    /// Stop receiving messages, so that the server fails to send the next
+    /// one.
+    pub fn close_receiver(&mut self) {
+        self.connection.receiver = crossbeam_channel::never();
+    }
+
+    /// Close the connection without shutting the server down, and wait
+    /// for it to stop.
+    pub fn disconnect(mut self) -> anyhow::Result<()> {
+        drop(std::mem::replace(
+            &mut self.connection,
+            Connection::memory().0,
+        ));
+        self.server.take().unwrap().join().unwrap()
+    }
+
+    /// Wait for the server to stop with an error.
+    pub fn crashed(mut self) -> anyhow::Error {
+        let server = self.server.take().unwrap();
+        server
+            .join()
+            .unwrap()
+            .expect_err("the server stopped normally")
+    }
+
+    /// The words of the diagnostics published next for `name`.
+    pub fn misspelled(&self, name: &str, text: &str) -> Vec<String> {
+        self.diagnostics(name)
+            .iter()
+            .map(|diagnostic| word_at(text, diagnostic))
+            .collect()
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;
+        }
+        if self.server.is_some() {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            let server = self.server.take().unwrap();
+            server.join().unwrap().unwrap();
+        }
+        _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Point the cache directory of servers at a temporary directory, so
+/// that dictionaries compiled by tests aren't written to the user's.
+fn use_temporary_cache() {
+    static CACHE: Once = Once::new();
+    CACHE.call_once(|| {
+        let cache = std::env::temp_dir()
+            .join(format!("zspell_lsp_cache_{}", std::process::id()));
+        std::env::set_var("XDG_CACHE_HOME", cache);
+    });
+}
+
+/// Write a dictionary of `words` for each locale to a new directory named
+/// after `name`, for the `dictionaryPaths` setting.
+pub fn dictionaries(name: &str, locales: &[(&str, &[&str])]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "zspell_lsp_{}_{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (locale, words) in locales {
+        let dic = format!("{}\n{}\n", words.len(), words.join("\n"));
+        std::fs::write(dir.join(format!("{}.aff", locale)), "SET UTF-8\n")
+            .unwrap();
+        std::fs::write(dir.join(format!("{}.dic", locale)), dic).unwrap();
+    }
+    dir
+}
+
+/// The text of `text` in the single line range of `diagnostic`.
+fn word_at(text: &str, diagnostic: &Diagnostic) -> String {
+    let range = diagnostic.range;
+    let line = text.lines().nth(range.start.line as usize).unwrap();
+    let units = line.encode_utf16().collect::<Vec<_>>();
+    let start = range.start.character as usize;
+    let end = range.end.character as usize;
+    String::from_utf16(&units[start..end]).unwrap()
+}
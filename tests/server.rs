@@ -0,0 +1,1030 @@
+mod common;
+
+use common::TestClient;
+use lsp_server::{ErrorCode, Message};
+use lsp_types::notification::{
+    Cancel, DidChangeTextDocument, DidCloseTextDocument, Notification,
+};
+use lsp_types::request::{
+    CodeActionRequest, ExecuteCommand, HoverRequest, WillSaveWaitUntil,
+};
+use lsp_types::{
+    CancelParams, CodeActionContext, CodeActionParams, DiagnosticSeverity,
+    DidCloseTextDocumentParams, ExecuteCommandParams, HoverParams,
+    NumberOrString, Position, Range, TextDocumentIdentifier,
+    TextDocumentPositionParams, TextDocumentSaveReason, TextEdit,
+    WillSaveTextDocumentParams,
+};
+use serde_json::{json, Value};
+use std::process::Command;
+use zspell_lsp::affixes::AffixKind;
+use zspell_lsp::config::Layer;
+use zspell_lsp::metrics::Snapshot;
+use zspell_lsp::server::{
+    AddWordWithFlags, AddWordWithFlagsParams, AddWordWithFlagsRequest,
+    AffixFlag, AffixFlagInfo, AffixFlagsParams, AffixFlagsRequest,
+    DiagnosticData, DocumentLanguage, LanguageSource, ListUserWordsRequest,
+    MetricsRequest, Ping, PingRequest, ADD_TO_DICTIONARY_COMMAND,
+    CHECK_DOCUMENT_COMMAND, CHECK_WORKSPACE_COMMAND,
+    DOCUMENT_LANGUAGE_NOTIFICATION, FILE_SIZE_RULE, IDENTIFIER_RULE,
+    LONG_LINE_RULE, MISSPELLING_RULE, PING_REQUEST, SET_LANGUAGE_COMMAND,
+};
+use zspell_lsp::words::UserWord;
+
+const WORDS: &[&str] = &["hello", "world", "see"];
+
+#[test]
+fn publishes_diagnostics() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    let text = "hello wrld\nsee teh world";
+    client.open("a.txt", text);
+    assert_eq!(client.misspelled("a.txt", text), ["wrld", "teh"]);
+
+    let text = "hello world";
+    client.change("a.txt", 1, text);
+    assert!(client.diagnostics("a.txt").is_empty());
+}
+
+#[test]
+fn diagnostic_data() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    client.open("a.txt", "Wrld");
+    let diagnostic = client.diagnostics("a.txt").remove(0);
+    let data =
+        serde_json::from_value::<DiagnosticData>(diagnostic.data.unwrap())
+            .unwrap();
+
+    assert_eq!(diagnostic.message, "Incorrect spelling");
+    assert_eq!(diagnostic.source.as_deref(), Some("zspell"));
+    assert_eq!(
+        diagnostic.code,
+        Some(NumberOrString::String(MISSPELLING_RULE.to_string()))
+    );
+    assert_eq!(
+        data,
+        DiagnosticData {
+            version: DiagnosticData::VERSION,
+            rule: MISSPELLING_RULE.to_string(),
+            word: "Wrld".to_string(),
+            normalized: "wrld".to_string(),
+            suggestions: None,
+        }
+    );
+}
+
+#[test]
+fn check_on_save() {
+    let client = TestClient::start(WORDS, json!({"checkOn": "save"}));
+
+    client.open("a.txt", "wrld");
+    assert_eq!(client.diagnostics("a.txt").len(), 1);
+    client.change("a.txt", 1, "wrld teh");
+    client.open("b.txt", "hello");
+    // Changes aren't checked, so the next diagnostics are those of b.txt
+    assert!(client.diagnostics("b.txt").is_empty());
+}
+
+#[test]
+fn autocorrect_on_save() {
+    let mut client = TestClient::start(
+        WORDS,
+        json!({"substitutions": {"teh": "the"}, "autocorrectOnSave": true}),
+    );
+
+    assert_eq!(
+        client.capabilities["textDocumentSync"]["willSaveWaitUntil"],
+        true
+    );
+
+    // Context and removed lines of diffs aren't checked, nor corrected
+    client.open_as(
+        "fix.diff",
+        "diff",
+        " teh context\n-teh removed\n+teh added\n",
+    );
+    client.diagnostics("fix.diff");
+    let edits =
+        client.request::<WillSaveWaitUntil>(WillSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier {
+                uri: client.uri("fix.diff"),
+            },
+            reason: TextDocumentSaveReason::MANUAL,
+        });
+    let edits = serde_json::from_value::<Vec<TextEdit>>(edits).unwrap();
+    assert_eq!(
+        edits,
+        [TextEdit {
+            range: Range::new(Position::new(2, 1), Position::new(2, 4)),
+            new_text: "the".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn no_will_save_wait_until_without_autocorrect() {
+    let client =
+        TestClient::start(WORDS, json!({"substitutions": {"teh": "the"}}));
+
+    assert_eq!(
+        client.capabilities["textDocumentSync"]["willSaveWaitUntil"],
+        false
+    );
+}
+
+#[test]
+fn code_actions() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    client.open("a.txt", "hello wrld");
+    client.diagnostics("a.txt");
+    let actions = client.request::<CodeActionRequest>(CodeActionParams {
+        text_document: TextDocumentIdentifier {
+            uri: client.uri("a.txt"),
+        },
+        range: Range::new(Position::new(0, 7), Position::new(0, 7)),
+        context: CodeActionContext::default(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+
+    let titles = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|action| action["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(titles.contains(&"Change to 'world'"), "{:?}", titles);
+}
+
+#[test]
+fn right_to_left() {
+    let mut client = TestClient::start(&["hello", "world", "שלום"], json!({}));
+
+    let text = "\u{200F}שלום 😀 wrld, \u{2067}עולמ\u{2069} hello";
+    client.open("a.txt", text);
+    let diagnostics = client.diagnostics("a.txt");
+    let misspelled = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.range)
+        .collect::<Vec<_>>();
+    let range =
+        |start, end| Range::new(Position::new(0, start), Position::new(0, end));
+    assert_eq!(misspelled, [range(9, 13), range(16, 20)]);
+
+    let actions = client.request::<CodeActionRequest>(CodeActionParams {
+        text_document: TextDocumentIdentifier {
+            uri: client.uri("a.txt"),
+        },
+        range: range(10, 10),
+        context: CodeActionContext::default(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+    let action = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Change to 'world'")
+        .unwrap();
+    let edit = &action["edit"]["changes"][client.uri("a.txt").as_str()][0];
+    let edit: lsp_types::TextEdit =
+        serde_json::from_value(edit.clone()).unwrap();
+
+    // Apply the edit in UTF-16 code units, as the client does
+    let mut units = text.encode_utf16().collect::<Vec<_>>();
+    let start = edit.range.start.character as usize;
+    let end = edit.range.end.character as usize;
+    units.splice(start..end, edit.new_text.encode_utf16());
+    assert_eq!(
+        String::from_utf16(&units).unwrap(),
+        "\u{200F}שלום 😀 world, \u{2067}עולמ\u{2069} hello"
+    );
+}
+
+#[test]
+fn team_dictionary() {
+    let team = std::env::temp_dir()
+        .join(format!("zspell_lsp_team_{}.txt", std::process::id()));
+    std::fs::write(&team, "wrld\n").unwrap();
+    let mut client = TestClient::start(WORDS, json!({"teamDictionary": team}));
+
+    let text = "hello wrld teh";
+    client.open("a.txt", text);
+    let misspelled = client.misspelled("a.txt", text);
+    let actions = client.request::<CodeActionRequest>(CodeActionParams {
+        text_document: TextDocumentIdentifier {
+            uri: client.uri("a.txt"),
+        },
+        range: Range::new(Position::new(0, 12), Position::new(0, 12)),
+        context: CodeActionContext::default(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+    client.request::<ExecuteCommand>(ExecuteCommandParams {
+        command: ADD_TO_DICTIONARY_COMMAND.to_string(),
+        arguments: vec![
+            json!(client.uri("a.txt")),
+            json!("teh"),
+            json!("team"),
+        ],
+        work_done_progress_params: Default::default(),
+    });
+    let words = std::fs::read_to_string(&team).unwrap();
+    let listed = client.request::<ListUserWordsRequest>(());
+    let listed = serde_json::from_value::<Vec<UserWord>>(listed).unwrap();
+
+    std::fs::remove_file(&team).unwrap();
+
+    assert_eq!(misspelled, ["teh"]);
+    let titles = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|action| action["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(titles.contains(&"Add 'teh' to personal dictionary"));
+    assert!(
+        titles.contains(&"Add 'teh' to team dictionary"),
+        "{:?}",
+        titles
+    );
+    assert_eq!(words, "wrld\nteh\n");
+    let listed = listed
+        .iter()
+        .map(|word| (word.word.as_str(), word.layer))
+        .collect::<Vec<_>>();
+    assert_eq!(listed, [("wrld", Layer::Team), ("teh", Layer::Team)]);
+}
+
+#[test]
+fn personal_dictionary_edited() {
+    let personal = std::env::temp_dir()
+        .join(format!("zspell_lsp_personal_{}.txt", std::process::id()));
+    std::fs::write(&personal, "").unwrap();
+    let client =
+        TestClient::start(WORDS, json!({"personalDictionary": personal}));
+
+    let text = "hello wrld teh";
+    client.open("a.txt", text);
+    let misspelled = client.misspelled("a.txt", text);
+    // As if added by another session
+    std::fs::write(&personal, "teh\n").unwrap();
+    let edited = client.misspelled("a.txt", text);
+
+    std::fs::remove_file(&personal).unwrap();
+
+    assert_eq!(misspelled, ["wrld", "teh"]);
+    assert_eq!(edited, ["wrld"]);
+}
+
+#[test]
+fn latest_change_wins() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    client.open("a.txt", "");
+    for version in 1..=20 {
+        client.change("a.txt", version, &"wrld ".repeat(version as usize));
+    }
+    // Checks superseded by a later change may be skipped, but diagnostics
+    // are never published for an older version after a newer one
+    let mut previous = 0;
+    while previous < 20 {
+        let count = client.diagnostics("a.txt").len();
+        assert!(count >= previous, "{} after {}", count, previous);
+        previous = count;
+    }
+}
+
+#[test]
+fn long_documents() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    // Checked in chunks, the diagnostics found so far published after each
+    let mut text = "hello world\n".repeat(25_000);
+    text.insert_str(0, "wrld ");
+    text.push_str("teh");
+    client.open("a.txt", &text);
+    let lines = loop {
+        let diagnostics = client.diagnostics("a.txt");
+        if diagnostics.len() == 2 {
+            break diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.range.start.line)
+                .collect::<Vec<_>>();
+        }
+        assert_eq!(diagnostics.len(), 1);
+    };
+    assert_eq!(lines, [0, 25_000]);
+}
+
+#[test]
+fn chunk_in_fenced_block() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    // A fenced block of a doc comment across the end of the first chunk,
+    // which is checked as a whole
+    let mut text = "/// hello\n".repeat(9_998);
+    text.push_str("/// ```\n/// wrld\n/// wrld\n/// ```\n/// teh\n");
+    client.open_as("a.rs", "rust", &text);
+    let words = loop {
+        let words = client.misspelled("a.rs", &text);
+        if !words.is_empty() {
+            break words;
+        }
+    };
+    assert_eq!(words, ["teh"]);
+}
+
+#[test]
+fn long_lines() {
+    let client = TestClient::start(WORDS, json!({"maxLineLength": 20}));
+
+    let text =
+        "wrld\nteh teh teh teh teh teh\nhello teh\nteh teh teh teh teh teh";
+    client.open("a.txt", text);
+    let diagnostics = client.diagnostics("a.txt");
+    let lines = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.range.start.line)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [1, 0, 2]);
+    assert_eq!(
+        diagnostics[0].code,
+        Some(NumberOrString::String(LONG_LINE_RULE.to_string()))
+    );
+    assert_eq!(
+        diagnostics[0].message,
+        "2 lines longer than 20 bytes not checked"
+    );
+}
+
+#[test]
+fn generated_files() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    client.open("a.txt", "// Code generated by wrld. DO NOT EDIT.\nteh");
+    assert!(client.diagnostics("a.txt").is_empty());
+    client.open("b.txt", "wrld\0");
+    assert!(client.diagnostics("b.txt").is_empty());
+    client.open("c.txt", "wrld");
+    assert_eq!(client.diagnostics("c.txt").len(), 1);
+}
+
+#[test]
+fn large_files() {
+    let client = TestClient::start(WORDS, json!({"maxFileSize": 20}));
+
+    client.open("a.txt", "wrld teh wrld teh wrld teh");
+    let diagnostics = client.diagnostics("a.txt");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].code,
+        Some(NumberOrString::String(FILE_SIZE_RULE.to_string()))
+    );
+    assert_eq!(
+        diagnostics[0].message,
+        "File larger than 20 bytes not checked"
+    );
+    client.open("b.txt", "wrld teh");
+    assert_eq!(client.diagnostics("b.txt").len(), 2);
+}
+
+#[test]
+fn changed_lines() {
+    let client = TestClient::start(WORDS, json!({"onlyChangedLines": true}));
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+            .args(args)
+            .current_dir(&client.root)
+            .output()
+            .is_ok_and(|output| output.status.success())
+    };
+    std::fs::write(client.root.join("a.txt"), "wrld\nhello\nteh\n").unwrap();
+    if !git(&["init", "-q"]) || !git(&["add", "a.txt"]) {
+        // Without git every line is checked
+        return;
+    }
+    assert!(git(&["commit", "-qm", "Add a.txt"]));
+
+    client.open("a.txt", "wrld\nhello wrld\nteh\nthier\n");
+    let lines = client
+        .diagnostics("a.txt")
+        .iter()
+        .map(|diagnostic| diagnostic.range.start.line)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [1, 3]);
+    client.open("b.txt", "wrld");
+    assert_eq!(client.diagnostics("b.txt").len(), 1);
+}
+
+#[test]
+fn untitled_documents() {
+    let mut client = TestClient::start(WORDS, json!({"include": ["docs/**"]}));
+
+    client.open("untitled:Untitled-1", "hello wrld");
+    assert_eq!(client.diagnostics("untitled:Untitled-1").len(), 1);
+    let actions = client.request::<CodeActionRequest>(CodeActionParams {
+        text_document: TextDocumentIdentifier {
+            uri: client.uri("untitled:Untitled-1"),
+        },
+        range: Range::new(Position::new(0, 6), Position::new(0, 10)),
+        context: CodeActionContext::default(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+    assert!(!actions.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn document_lifecycle() {
+    let client = TestClient::start(WORDS, json!({}));
+
+    // Opened twice, the second text replaces the first
+    client.open("a.txt", "wrld");
+    assert_eq!(client.misspelled("a.txt", "wrld"), ["wrld"]);
+    client.open("a.txt", "hello teh");
+    assert_eq!(client.misspelled("a.txt", "hello teh"), ["teh"]);
+
+    // Stale changes are ignored, unless they replace the whole text
+    client.change("a.txt", 2, "see wrld");
+    assert_eq!(client.misspelled("a.txt", "see wrld"), ["wrld"]);
+    let start = Range::new(Position::new(0, 0), Position::new(0, 3));
+    client.edit("a.txt", 1, start, "teh");
+    let end = Range::new(Position::new(0, 8), Position::new(0, 8));
+    client.edit("a.txt", 3, end, " teh");
+    assert_eq!(client.misspelled("a.txt", "see wrld teh"), ["wrld", "teh"]);
+    client.change("a.txt", 1, "teh world");
+    assert_eq!(client.misspelled("a.txt", "teh world"), ["teh"]);
+    // Keeping the newer version
+    client.edit("a.txt", 2, start, "see");
+    let end = Range::new(Position::new(0, 9), Position::new(0, 9));
+    client.edit("a.txt", 4, end, " wrld");
+    assert_eq!(
+        client.misspelled("a.txt", "teh world wrld"),
+        ["teh", "wrld"]
+    );
+
+    // Changes of unopened documents replacing their text open them
+    client.change("b.txt", 3, "teh world");
+    assert_eq!(client.misspelled("b.txt", "teh world"), ["teh"]);
+    client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
+        text_document: TextDocumentIdentifier {
+            uri: client.uri("c.txt"),
+        },
+    });
+    assert!(client.diagnostics("c.txt").is_empty());
+}
+
+#[test]
+fn report_duplicates() {
+    let client = TestClient::start(WORDS, json!({"reportDuplicates": "first"}));
+
+    let text = "wrld teh\nhello wrld wrld";
+    client.open("a.txt", text);
+    let diagnostics = client.diagnostics("a.txt");
+    let starts = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.range.start)
+        .collect::<Vec<_>>();
+    assert_eq!(starts, [Position::new(0, 0), Position::new(0, 5)]);
+    let related = diagnostics[0]
+        .related_information
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|related| related.location.range.start)
+        .collect::<Vec<_>>();
+    assert_eq!(related, [Position::new(1, 6), Position::new(1, 11)]);
+    assert_eq!(diagnostics[1].related_information, None);
+
+    let client = TestClient::start(WORDS, json!({"reportDuplicates": "count"}));
+    client.open("a.txt", text);
+    let diagnostics = client.diagnostics("a.txt");
+    let messages = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        ["Incorrect spelling (3 occurrences)", "Incorrect spelling"]
+    );
+    assert_eq!(diagnostics[0].related_information, None);
+
+    let options = json!({"reportDuplicates": "summary"});
+    let client = TestClient::start(WORDS, options);
+    client.open("a.txt", "Wrld teh\nhello wrld WRLD");
+    let diagnostics = client.diagnostics("a.txt");
+    let messages = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "Incorrect spelling: 'Wrld', 3 occurrences",
+            "Incorrect spelling: 'teh', 1 occurrence"
+        ]
+    );
+    assert_eq!(
+        diagnostics[0].related_information.as_ref().unwrap().len(),
+        2
+    );
+}
+
+#[test]
+fn identifiers() {
+    let words = ["parse", "html", "max", "size"];
+    let client = TestClient::start(&words, json!({"checkIdentifiers": true}));
+
+    let text = "fn parseHtml(max_sise: usize) {}";
+    client.open_as("lib.rs", "rust", text);
+    let diagnostics = client.diagnostics("lib.rs");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+    assert_eq!(
+        diagnostics[0].code,
+        Some(NumberOrString::String(IDENTIFIER_RULE.to_string()))
+    );
+    assert_eq!(
+        diagnostics[0].range,
+        Range::new(Position::new(0, 17), Position::new(0, 21))
+    );
+}
+
+#[test]
+fn language_ids() {
+    let dictionaries =
+        common::dictionaries("language_ids", &[("de_DE", &["hallo", "welt"])]);
+    let client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "languageIds": {"markdown": "de_DE"},
+        }),
+    );
+
+    let text = "hallo welt hello";
+    client.open_as("a.md", "markdown", text);
+    let markdown = client.misspelled("a.md", text);
+    client.open("a.txt", text);
+    let plaintext = client.misspelled("a.txt", text);
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    assert_eq!(markdown, ["hello"]);
+    assert_eq!(plaintext, ["hallo", "welt"]);
+}
+
+#[test]
+fn detect_languages() {
+    let dictionaries = common::dictionaries(
+        "detect_languages",
+        &[
+            ("de_DE", &["hallo", "welt"]),
+            ("sv_SE", &["hej", "världen"]),
+        ],
+    );
+    let mut client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "detectLanguages": ["de_DE", "sv_SE"],
+        }),
+    );
+
+    let text = "hej världen hallo";
+    client.open("a.txt", text);
+    let detected = client.notification(DOCUMENT_LANGUAGE_NOTIFICATION);
+    let misspelled = client.misspelled("a.txt", text);
+    client.send_request::<ExecuteCommand>(ExecuteCommandParams {
+        command: SET_LANGUAGE_COMMAND.to_string(),
+        arguments: vec![json!(client.uri("a.txt")), json!("de_DE")],
+        work_done_progress_params: Default::default(),
+    });
+    let chosen = client.notification(DOCUMENT_LANGUAGE_NOTIFICATION);
+    let chosen_misspelled = client.misspelled("a.txt", text);
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    let language = |params| {
+        let params = serde_json::from_value::<DocumentLanguage>(params);
+        let params = params.unwrap();
+        (params.language.unwrap(), params.source)
+    };
+    assert_eq!(
+        language(detected),
+        ("sv_SE".into(), LanguageSource::Detected)
+    );
+    assert_eq!(misspelled, ["hallo"]);
+    assert_eq!(language(chosen), ("de_DE".into(), LanguageSource::Chosen));
+    assert_eq!(chosen_misspelled, ["hej", "världen"]);
+}
+
+#[test]
+fn hover() {
+    let dictionaries = common::dictionaries(
+        "hover",
+        &[("de_DE", &["hallo", "welt"]), ("sv_SE", &["hej", "hallo"])],
+    );
+    let mut client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "detectLanguages": ["de_DE", "sv_SE"],
+        }),
+    );
+
+    let text = "hello world hallo wrld\nhallo";
+    client.open("a.txt", text);
+    client.diagnostics("a.txt");
+    let mut hover = |line, character| {
+        client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: client.uri("a.txt"),
+                },
+                position: Position::new(line, character),
+            },
+            work_done_progress_params: Default::default(),
+        })
+    };
+    let hallo = hover(0, 14);
+    let wrld = hover(0, 20);
+    let known = hover(0, 2);
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    assert_eq!(
+        hallo["contents"]["value"],
+        "**hallo**: 2 occurrences in this document\n\n\
+         Known in `de_DE`, `sv_SE`"
+    );
+    assert_eq!(
+        hallo["range"],
+        json!(Range::new(Position::new(0, 12), Position::new(0, 17)))
+    );
+    assert_eq!(
+        wrld["contents"]["value"],
+        "**wrld**: 1 occurrence in this document\n\n\
+         Not known in any loaded language"
+    );
+    assert!(known.is_null());
+}
+
+#[test]
+fn synonyms() {
+    let dir = std::env::temp_dir()
+        .join(format!("zspell_lsp_synonyms_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let data = "UTF-8\nworld|2\n(noun)|earth|globe\n(noun)|domain\n";
+    std::fs::write(dir.join("th_en_US.dat"), data).unwrap();
+    std::fs::write(dir.join("th_en_US.idx"), "UTF-8\n1\nworld|6\n").unwrap();
+    let mut client = TestClient::start(
+        WORDS,
+        json!({"thesaurus": dir.join("th_en_US.dat")}),
+    );
+
+    client.open("a.txt", "hello world");
+    client.diagnostics("a.txt");
+    let mut hover = |character| {
+        client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: client.uri("a.txt"),
+                },
+                position: Position::new(0, character),
+            },
+            work_done_progress_params: Default::default(),
+        })
+    };
+    let world = hover(8);
+    let hello = hover(2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        world["contents"]["value"],
+        "**world**\n\n*noun*: earth, globe\n\n*noun*: domain"
+    );
+    assert!(hello.is_null());
+}
+
+#[test]
+fn morphology() {
+    let dictionaries =
+        common::dictionaries("morphology", &[("xx_XX", &["drink/X po:verb"])]);
+    std::fs::write(
+        dictionaries.join("xx_XX.aff"),
+        "SET UTF-8\nTRY abdegiknlr\nSFX X Y 1\nSFX X 0 able . ds:able\n",
+    )
+    .unwrap();
+    let mut client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "language": "xx_XX",
+        }),
+    );
+
+    client.open("a.txt", "drinkable drinkible");
+    client.diagnostics("a.txt");
+    let mut hover = |character| {
+        client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: client.uri("a.txt"),
+                },
+                position: Position::new(0, character),
+            },
+            work_done_progress_params: Default::default(),
+        })
+    };
+    let correct = hover(3);
+    let misspelled = hover(13);
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    assert_eq!(
+        correct["contents"]["value"],
+        "**drinkable**\n\nStems: `drink`\n\nAnalysis: `ds:able`, `po:verb`"
+    );
+    assert_eq!(
+        misspelled["contents"]["value"],
+        "**drinkible**: 1 occurrence in this document\n\n\
+         Not known in any loaded language\n\n\
+         Stems of suggestions: `drinkable` (`drink`)"
+    );
+}
+
+#[test]
+fn add_word_with_flags() {
+    let dictionaries =
+        common::dictionaries("add_word_with_flags", &[("xx_XX", &["hello"])]);
+    std::fs::write(
+        dictionaries.join("xx_XX.aff"),
+        "SET UTF-8\n# Plural\nSFX S Y 1\nSFX S 0 s .\n",
+    )
+    .unwrap();
+    let mut client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "language": "xx_XX",
+        }),
+    );
+
+    let text = "hello zspell zspells";
+    client.open("a.txt", text);
+    let misspelled = client.misspelled("a.txt", text);
+    let params = |flags| AddWordWithFlagsParams {
+        uri: client.uri("a.txt"),
+        word: "zspell".to_string(),
+        flags,
+        like: None,
+        layer: Layer::Personal,
+    };
+    let (listing, adding) = (params(None), params(Some("S".to_string())));
+    let listed = client.request::<AddWordWithFlagsRequest>(listing);
+    client.send_request::<AddWordWithFlagsRequest>(adding);
+    let added = client.diagnostics("a.txt");
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    assert_eq!(misspelled, ["zspell", "zspells"]);
+    assert_eq!(
+        serde_json::from_value::<AddWordWithFlags>(listed).unwrap(),
+        AddWordWithFlags {
+            flags: vec![AffixFlag {
+                flag: "S".to_string(),
+                kind: AffixKind::Suffix,
+                description: "Plural".to_string(),
+                forms: vec!["zspells".to_string()],
+            }],
+            added: Vec::new(),
+        }
+    );
+    assert!(added.is_empty(), "{:?}", added);
+}
+
+#[test]
+fn affix_flags() {
+    let dictionaries =
+        common::dictionaries("affix_flags", &[("xx_XX", &["hello"])]);
+    std::fs::write(
+        dictionaries.join("xx_XX.aff"),
+        "SET UTF-8\n# Plural\nSFX S Y 2\nSFX S y ies [^aeiou]y\n\
+         SFX S 0 s [^y]\nPFX U N 1\nPFX U 0 un .\n",
+    )
+    .unwrap();
+    let mut client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "language": "xx_XX",
+        }),
+    );
+
+    let flags = client.request::<AffixFlagsRequest>(AffixFlagsParams {
+        uri: client.uri("a.txt"),
+    });
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    assert_eq!(
+        serde_json::from_value::<Vec<AffixFlagInfo>>(flags).unwrap(),
+        [
+            AffixFlagInfo {
+                flag: "S".to_string(),
+                kind: AffixKind::Suffix,
+                description: "Plural".to_string(),
+                affixes: vec!["-ies".to_string(), "-s".to_string()],
+                combines: true,
+            },
+            AffixFlagInfo {
+                flag: "U".to_string(),
+                kind: AffixKind::Prefix,
+                description: "Prefix un-".to_string(),
+                affixes: vec!["un-".to_string()],
+                combines: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn add_word_like() {
+    let dictionaries =
+        common::dictionaries("add_word_like", &[("xx_XX", &["run/SGn"])]);
+    std::fs::write(
+        dictionaries.join("xx_XX.aff"),
+        "SET UTF-8\nSFX S Y 1\nSFX S 0 s .\nSFX G Y 1\nSFX G 0 ging .\n",
+    )
+    .unwrap();
+    let mut client = TestClient::start(
+        WORDS,
+        json!({
+            "dictionaryPaths": [dictionaries],
+            "language": "xx_XX",
+        }),
+    );
+
+    let text = "blog blogs blogging";
+    client.open("a.txt", text);
+    let misspelled = client.misspelled("a.txt", text);
+    let added =
+        client.request::<AddWordWithFlagsRequest>(AddWordWithFlagsParams {
+            uri: client.uri("a.txt"),
+            word: "blog".to_string(),
+            flags: None,
+            like: Some("run".to_string()),
+            layer: Layer::Personal,
+        });
+    let diagnostics = client.diagnostics("a.txt");
+
+    std::fs::remove_dir_all(&dictionaries).unwrap();
+
+    assert_eq!(misspelled, ["blog", "blogs", "blogging"]);
+    assert_eq!(
+        serde_json::from_value::<AddWordWithFlags>(added)
+            .unwrap()
+            .added,
+        ["blog", "blogs", "blogging"]
+    );
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
+
+#[test]
+fn metrics() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    client.open("a.txt", "hello wrld");
+    client.diagnostics("a.txt");
+    let metrics = client.request::<MetricsRequest>(());
+    let metrics = serde_json::from_value::<Snapshot>(metrics).unwrap();
+
+    assert!(metrics.checks >= 1);
+    assert!(metrics.handlers["textDocument/didOpen"].count >= 1);
+}
+
+#[test]
+fn ping() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    let ping = client.request::<PingRequest>(());
+    let ping = serde_json::from_value::<Ping>(ping).unwrap();
+    // Named after the default affix file, as no language is configured
+    assert_eq!(ping.language, "index");
+    assert_eq!(ping.queue_depth, 0);
+}
+
+#[test]
+fn crash_report() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    client.request::<PingRequest>(());
+    client.close_receiver();
+    client.send_request::<PingRequest>(());
+    client.crashed();
+
+    // Named after the process, as the message telling where can't be sent
+    let suffix = format!("-{}", std::process::id());
+    let path = std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("zspell_lsp-crash-") && name.ends_with(&suffix)
+        })
+        .unwrap();
+    let state = std::fs::read_to_string(path.join("state.json")).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    assert!(state.contains(PING_REQUEST), "{}", state);
+}
+
+#[test]
+fn disconnect() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    client.request::<PingRequest>(());
+    client.disconnect().unwrap();
+}
+
+/// `textDocument/didChange` without the version of the document.
+enum UnversionedChange {}
+
+impl Notification for UnversionedChange {
+    type Params = Value;
+    const METHOD: &'static str = DidChangeTextDocument::METHOD;
+}
+
+#[test]
+fn invalid_params() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    let error = client.request_error::<ExecuteCommand>(ExecuteCommandParams {
+        command: CHECK_DOCUMENT_COMMAND.to_string(),
+        arguments: vec![json!(42)],
+        work_done_progress_params: Default::default(),
+    });
+    assert_eq!(error.code, ErrorCode::InvalidParams as i32);
+
+    let text = "see teh world";
+    client.open("a.txt", "hello wrld");
+    client.diagnostics("a.txt");
+    client.notify::<UnversionedChange>(json!({
+        "textDocument": {"uri": client.uri("a.txt")},
+        "contentChanges": [{"text": "hello world"}],
+    }));
+    client.change("a.txt", 1, text);
+    assert_eq!(client.misspelled("a.txt", text), ["teh"]);
+}
+
+#[test]
+fn check_workspace() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    std::fs::write(client.root.join("a.txt"), "hello wrld").unwrap();
+    std::fs::write(client.root.join("b.txt"), "see teh wrld").unwrap();
+    // Open documents are checked as they are in the editor
+    client.open("a.txt", "hello world");
+    client.diagnostics("a.txt");
+    let report = client.request::<ExecuteCommand>(ExecuteCommandParams {
+        command: CHECK_WORKSPACE_COMMAND.to_string(),
+        arguments: Vec::new(),
+        work_done_progress_params: Default::default(),
+    });
+    assert_eq!(report, json!({"filesChecked": 2, "misspellings": 2}));
+    assert_eq!(client.misspelled("b.txt", "see teh wrld"), ["teh", "wrld"]);
+}
+
+#[test]
+fn cancel_request() {
+    let mut client = TestClient::start(WORDS, json!({}));
+
+    // The code actions are computed after the long document is checked,
+    // and cancelled meanwhile
+    client.open("a.txt", &"hello world\n".repeat(200_000));
+    let id = client.send_request::<CodeActionRequest>(CodeActionParams {
+        text_document: TextDocumentIdentifier {
+            uri: client.uri("a.txt"),
+        },
+        range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+        context: CodeActionContext::default(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+    client.notify::<Cancel>(CancelParams {
+        id: serde_json::from_value(serde_json::to_value(&id).unwrap()).unwrap(),
+    });
+    let error = loop {
+        if let Message::Response(response) = client.receive() {
+            if response.id == id {
+                break response.error.expect("the request wasn't cancelled");
+            }
+        }
+    };
+    assert_eq!(error.code, ErrorCode::RequestCanceled as i32);
+}